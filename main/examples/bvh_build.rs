@@ -0,0 +1,32 @@
+// Times BVH builds over 2M triangles: cargo run --release --example bvh_build [--features parallel]
+use nalgebra_glm as glm;
+use glm::{DVec3, U32Vec3};
+use nanomesh::spatial::Bvh;
+use std::time::Instant;
+
+fn main() {
+    // Wavy grid of 1000 x 1000 quads, 2M triangles
+    let n = 1000;
+    let mut positions = Vec::with_capacity((n + 1) * (n + 1));
+    for y in 0..=n {
+        for x in 0..=n {
+            let (u, v) = (x as f64 / n as f64, y as f64 / n as f64);
+            positions.push(DVec3::new(u, v, 0.05 * (20.0 * u).sin() * (20.0 * v).cos()));
+        }
+    }
+    let mut triangles = Vec::with_capacity(2 * n * n);
+    for y in 0..n {
+        for x in 0..n {
+            let i = (y * (n + 1) + x) as u32;
+            let j = i + n as u32 + 1;
+            triangles.push(U32Vec3::new(i, i + 1, j + 1));
+            triangles.push(U32Vec3::new(i, j + 1, j));
+        }
+    }
+    for _ in 0..5 {
+        let (positions, triangles) = (positions.clone(), triangles.clone());
+        let start = Instant::now();
+        let bvh = Bvh::new(positions, triangles);
+        println!("{} triangles in {:.3}s", bvh.triangles().len(), start.elapsed().as_secs_f64());
+    }
+}
@@ -34,6 +34,43 @@ impl Box3
             max: DVec3::new(f64::MIN, f64::MIN, f64::MIN)
         }
     }
+
+    pub fn expand(&mut self, point: &DVec3) {
+        // Componentwise, as inf and sup are much slower in hot loops
+        self.min = DVec3::new(self.min.x.min(point.x), self.min.y.min(point.y), self.min.z.min(point.z));
+        self.max = DVec3::new(self.max.x.max(point.x), self.max.y.max(point.y), self.max.z.max(point.z));
+    }
+
+    pub fn center(&self) -> DVec3 {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn size(&self) -> DVec3 {
+        self.max - self.min
+    }
+
+    pub fn merge(&mut self, other: &Box3) {
+        self.min = DVec3::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y), self.min.z.min(other.min.z));
+        self.max = DVec3::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y), self.max.z.max(other.max.z));
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let size = self.size();
+        2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+    }
+
+    pub fn contains(&self, point: &DVec3) -> bool {
+        (0..3).all(|i| point[i] >= self.min[i] && point[i] <= self.max[i])
+    }
+
+    pub fn intersects(&self, other: &Box3) -> bool {
+        (0..3).all(|i| self.min[i] <= other.max[i] && other.min[i] <= self.max[i])
+    }
+
+    // Squared distance from the point to the box, zero inside of it
+    pub fn distance_squared(&self, point: &DVec3) -> f64 {
+        (self.min - point).sup(&(point - self.max)).sup(&DVec3::zeros()).magnitude_squared()
+    }
 }
 
 impl Default for Box3 {
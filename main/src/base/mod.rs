@@ -7,5 +7,11 @@
 pub mod box3;
 pub use box3::Box3 as Box3; 
 
+pub mod plane;
+pub use plane::Plane as Plane;
+
+pub mod ray;
+pub use ray::Ray as Ray;
+
 pub mod symmetric_matrix;
 pub use symmetric_matrix::SymmetricMatrix as SymmetricMatrix;
\ No newline at end of file
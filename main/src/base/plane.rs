@@ -0,0 +1,23 @@
+use nalgebra_glm as glm;
+use glm::{DVec3};
+
+/// Plane through `origin`. The normal is normalized, so that signed distances are in world units.
+#[derive(Debug, Copy, Clone)]
+pub struct Plane {
+    pub origin: DVec3,
+    pub normal: DVec3,
+}
+
+impl Plane {
+    pub fn new(origin: DVec3, normal: DVec3) -> Self {
+        Plane {
+            origin,
+            normal: normal.normalize(),
+        }
+    }
+
+    /// Positive on the side the normal points to
+    pub fn signed_distance(&self, point: &DVec3) -> f64 {
+        self.normal.dot(&(point - self.origin))
+    }
+}
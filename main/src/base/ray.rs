@@ -0,0 +1,22 @@
+use nalgebra_glm as glm;
+use glm::{DVec3};
+
+/// Half-line starting at `origin`. The direction is normalized, so that distances along the ray are in world units.
+#[derive(Debug, Copy, Clone)]
+pub struct Ray {
+    pub origin: DVec3,
+    pub direction: DVec3,
+}
+
+impl Ray {
+    pub fn new(origin: DVec3, direction: DVec3) -> Self {
+        Ray {
+            origin,
+            direction: direction.normalize(),
+        }
+    }
+
+    pub fn at(&self, distance: f64) -> DVec3 {
+        self.origin + self.direction * distance
+    }
+}
@@ -67,6 +67,19 @@ impl SymmetricMatrix {
         self.m[1] * self.m[1] * self.m[7]
     }
 
+    // Position minimizing the quadric error, if the matrix is invertible enough
+    pub fn optimal_position(self, min_det: f64) -> Option<DVec3> {
+        let det = self.get_det_xyz();
+        if det > min_det || det < -min_det {
+            Some(DVec3::new(
+                -1.0 / det * self.get_det_x(),
+                 1.0 / det * self.get_det_y(),
+                -1.0 / det * self.get_det_z()))
+        } else {
+            None
+        }
+    }
+
     pub fn quadric_distance_to_vertex(self, position: &DVec3) -> f64 {
         self.m[0] * position.x * position.x + 2.0 * self.m[1] * position.x * position.y + 2.0 * self.m[2] * position.x * position.z + 2.0 * self.m[3] * position.x +
         self.m[4] * position.y * position.y + 2.0 * self.m[5] * position.y * position.z + 2.0 * self.m[6] * position.y +
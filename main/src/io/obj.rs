@@ -36,6 +36,7 @@ pub fn read<T: Read>(reader: &mut BufReader<T>) -> SharedMesh {
         positions: positions,
         normals: None,
         colors: None,
+        uvs: None,
     }
 }
 
@@ -63,4 +64,16 @@ pub fn write<T: Write>(shared_mesh: &SharedMesh, writer: &mut BufWriter<T>) {
         let triangle = shared_mesh.triangles[i];
         write!("f {} {} {}", triangle[0] + 1, triangle[1] + 1, triangle[2] + 1);
     }
-}
\ No newline at end of file
+}
+
+/// Writes segments as OBJ lines, for instance to inspect self-intersections next to the mesh
+pub fn write_segments<T: Write>(segments: &[[DVec3; 2]], writer: &mut BufWriter<T>) {
+    for segment in segments {
+        for point in segment {
+            writeln!(writer, "v {} {} {}", point.x, point.y, point.z).unwrap();
+        }
+    }
+    for i in 0..segments.len() {
+        writeln!(writer, "l {} {}", 2 * i + 1, 2 * i + 2).unwrap();
+    }
+}
@@ -2,10 +2,66 @@ use nalgebra_glm as glm;
 use glm::{DVec3, U32Vec3};
 use super::super::mesh::SharedMesh;
 
-use std::io::BufWriter;
+use std::io::{self, BufWriter};
 use std::io::BufReader;
 use std::io::prelude::*;
-use std::convert::TryFrom;
+use std::convert::{TryFrom, TryInto};
+use std::collections::HashMap;
+
+// Binary STL, calling back with every triangle as it is read so that the whole mesh never has to be in memory.
+// Returns the number of triangles read.
+pub fn read_triangles<T: Read, F: FnMut(&[DVec3; 3])>(reader: &mut BufReader<T>, mut callback: F) -> io::Result<u32> {
+
+    // Header
+    let mut header = [0u8; 80];
+    reader.read_exact(&mut header)?;
+
+    let mut count = [0u8; 4];
+    reader.read_exact(&mut count)?;
+    let count = u32::from_le_bytes(count);
+
+    let mut record = [0u8; 50];
+    for _ in 0..count {
+        reader.read_exact(&mut record)?;
+        // Triangle normal (12 bytes) is skipped, as well as the trailing attribute byte count
+        let value = |i: usize| f32::from_le_bytes(record[12 + 4 * i..16 + 4 * i].try_into().unwrap()) as f64;
+        callback(&[
+            DVec3::new(value(0), value(1), value(2)),
+            DVec3::new(value(3), value(4), value(5)),
+            DVec3::new(value(6), value(7), value(8))]);
+    }
+
+    Ok(count)
+}
+
+// Binary STL. Vertices are welded by exact position, since STL stores them per triangle.
+pub fn read<T: Read>(reader: &mut BufReader<T>) -> SharedMesh {
+
+    let mut vertex_map = HashMap::<[u64; 3], u32>::new();
+    let mut positions = Vec::<DVec3>::new();
+    let mut triangles = Vec::<U32Vec3>::new();
+
+    read_triangles(reader, |triangle| {
+        let mut indices = U32Vec3::default();
+        for i in 0..3 {
+            let position = triangle[i];
+            indices[i] = *vertex_map.entry([position.x.to_bits(), position.y.to_bits(), position.z.to_bits()]).or_insert_with(|| {
+                positions.push(position);
+                (positions.len() - 1) as u32
+            });
+        }
+        triangles.push(indices);
+    }).expect("Could not read STL");
+
+    SharedMesh {
+        groups: Vec::new(),
+        triangles,
+        positions,
+        normals: None,
+        colors: None,
+        uvs: None,
+    }
+}
 
 // Binary STL https://fr.wikipedia.org/wiki/Fichier_de_st%C3%A9r%C3%A9olithographie
 pub fn write<T: Write>(shared_mesh: &SharedMesh, writer: &mut BufWriter<T>) {
@@ -3,5 +3,6 @@ include!("utils/macros.rs");
 pub mod base;
 pub mod utils;
 pub mod mesh;
+pub mod spatial;
 pub mod io;
 pub mod scene;
\ No newline at end of file
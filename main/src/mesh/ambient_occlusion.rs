@@ -0,0 +1,163 @@
+use nalgebra_glm as glm;
+use glm::DVec3;
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+use rayon::prelude::*;
+use super::SharedMesh;
+use crate::base::{Box3, Ray};
+use crate::spatial::Bvh;
+
+// Every vertex casts the same cosine weighted directions (Hammersley points mapped onto the hemisphere around its normal),
+// turned by an angle of its own around the normal so that neighboring vertices don't share their banding.
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AmbientOcclusionBlend {
+    /// Colors become the grey level of the occlusion
+    Replace,
+    /// Colors are darkened by the occlusion, starting from white when the mesh has none
+    Multiply,
+}
+
+impl SharedMesh {
+    /// Fraction of the hemisphere above every vertex that is open within `max_distance`, weighted by the cosine to the normal,
+    /// from 0 (fully occluded) to 1. Vertex normals are used when there are some, and area weighted triangle normals otherwise.
+    /// Rays that first hit the back of a triangle go through a solid from its inside, as happens on sharp corners, and are
+    /// considered open. Vertices are computed in parallel on native targets with the parallel feature, which is on by default.
+    pub fn get_ambient_occlusion(&self, sample_count: u32, max_distance: f64) -> Vec<f64> {
+        let mut ambient_occlusion = vec![1.0; self.positions.len()];
+        if self.triangles.is_empty() || sample_count == 0 {
+            return ambient_occlusion;
+        }
+        let normals = self.get_occlusion_normals();
+        let bvh = Bvh::from(self);
+        let mut bounds = Box3::unfitted();
+        for position in self.positions.iter() {
+            bounds.expand(position);
+        }
+        let bias = 1e-6 * bounds.diagonal();
+        let samples: Vec<(f64, f64)> = (0..sample_count).map(|i| ((i as f64 + 0.5) / sample_count as f64, get_radical_inverse(i))).collect();
+
+        let get_vertex_occlusion = |v: usize| -> f64 {
+            let normal = normals[v];
+            if normal == DVec3::zeros() {
+                return 1.0;
+            }
+            let tangent = normal.cross(&if normal.x.abs() < 0.9 { DVec3::x() } else { DVec3::y() }).normalize();
+            let bitangent = normal.cross(&tangent);
+            let rotation = (v as f64 * 0.6180339887498949).fract() * std::f64::consts::TAU;
+            let origin = self.positions[v] + normal * bias;
+            let open = samples.iter().filter(|(s, t)| {
+                // Cosine weighted: uniform on the disk, projected up onto the hemisphere
+                let (radius, angle) = (s.sqrt(), t * std::f64::consts::TAU + rotation);
+                let direction = tangent * (radius * angle.cos()) + bitangent * (radius * angle.sin()) + normal * (1.0 - s).max(0.0).sqrt();
+                let ray = Ray::new(origin, direction);
+                match bvh.cast_ray(&ray, max_distance) {
+                    Some(hit) => {
+                        let triangle = &self.triangles[hit.triangle as usize];
+                        let [a, b, c] = [0, 1, 2].map(|x| self.positions[triangle[x] as usize]);
+                        (b - a).cross(&(c - a)).dot(&ray.direction) >= 0.0
+                    },
+                    None => true,
+                }
+            }).count();
+            open as f64 / sample_count as f64
+        };
+
+        let mut is_used = vec![false; self.positions.len()];
+        for triangle in self.triangles.iter() {
+            for x in 0..3 {
+                is_used[triangle[x] as usize] = true;
+            }
+        }
+        let vertices: Vec<usize> = (0..self.positions.len()).filter(|&v| is_used[v]).collect();
+        #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+        let occlusions: Vec<f64> = vertices.par_iter().map(|&v| get_vertex_occlusion(v)).collect();
+        #[cfg(any(not(feature = "parallel"), target_arch = "wasm32"))]
+        let occlusions: Vec<f64> = vertices.iter().map(|&v| get_vertex_occlusion(v)).collect();
+
+        for (v, occlusion) in vertices.iter().zip(occlusions) {
+            ambient_occlusion[*v] = occlusion;
+        }
+        ambient_occlusion
+    }
+
+    /// Bakes ambient occlusion into the colors, for viewers without lighting. See `get_ambient_occlusion`.
+    pub fn bake_ambient_occlusion(&mut self, sample_count: u32, max_distance: f64, blend: AmbientOcclusionBlend) {
+        let ambient_occlusion = self.get_ambient_occlusion(sample_count, max_distance);
+        let colors = match (blend, self.colors.take()) {
+            (AmbientOcclusionBlend::Multiply, Some(colors)) if colors.len() == self.positions.len() => {
+                colors.iter().zip(ambient_occlusion.iter()).map(|(color, occlusion)| color * *occlusion).collect()
+            },
+            _ => ambient_occlusion.iter().map(|occlusion| DVec3::repeat(*occlusion)).collect(),
+        };
+        self.colors = Some(colors);
+    }
+
+    fn get_occlusion_normals(&self) -> Vec<DVec3> {
+        let mut computed = vec![DVec3::zeros(); self.positions.len()];
+        for triangle in self.triangles.iter() {
+            let [a, b, c] = [0, 1, 2].map(|x| self.positions[triangle[x] as usize]);
+            let normal = (b - a).cross(&(c - a));
+            for x in 0..3 {
+                computed[triangle[x] as usize] += normal;
+            }
+        }
+        let normals = self.normals.as_ref().filter(|normals| normals.len() == self.positions.len());
+        computed.iter().enumerate().map(|(v, computed)| {
+            normals.and_then(|normals| normals[v].try_normalize(0.0))
+                .or_else(|| computed.try_normalize(0.0))
+                .unwrap_or_else(DVec3::zeros)
+        }).collect()
+    }
+}
+
+// Van der Corput sequence in base 2, for the second coordinate of Hammersley points
+fn get_radical_inverse(i: u32) -> f64 {
+    i.reverse_bits() as f64 / 4294967296.0
+}
+
+#[cfg(test)]
+mod ambient_occlusion_tests {
+    use crate::mesh::*;
+    use crate::mesh::test_utils::{build_box, read_sphere};
+
+    // Floor facing up, made of four triangles around a vertex at the origin, with a box hovering above that vertex
+    fn build_scene() -> SharedMesh {
+        let floor = SharedMesh {
+            positions: vec![DVec3::zeros(), DVec3::new(-10., -10., 0.), DVec3::new(10., -10., 0.), DVec3::new(10., 10., 0.), DVec3::new(-10., 10., 0.)],
+            triangles: vec![U32Vec3::new(0, 1, 2), U32Vec3::new(0, 2, 3), U32Vec3::new(0, 3, 4), U32Vec3::new(0, 4, 1)],
+            groups: Vec::new(), normals: None, colors: None, uvs: None,
+        };
+        SharedMesh::combine(floor, build_box(DVec3::new(-0.5, -0.5, 0.1), DVec3::new(1., 1., 1.)))
+    }
+
+    #[test]
+    fn open_sphere() {
+        let mut shared_mesh = read_sphere();
+        assert!(shared_mesh.get_ambient_occlusion(64, 10.0).iter().all(|&occlusion| occlusion == 1.0));
+
+        shared_mesh.colors = Some(vec![DVec3::new(1., 0., 0.); shared_mesh.positions.len()]);
+        shared_mesh.bake_ambient_occlusion(16, 10.0, AmbientOcclusionBlend::Multiply);
+        assert!(shared_mesh.colors.unwrap().iter().all(|color| *color == DVec3::new(1., 0., 0.)));
+
+        // Corners of a box aren't darkened by the box itself
+        assert!(build_box(DVec3::zeros(), DVec3::new(1., 2., 3.)).get_ambient_occlusion(64, 10.0).iter().all(|&occlusion| occlusion == 1.0));
+    }
+
+    #[test]
+    fn occluded_floor() {
+        let mut shared_mesh = build_scene();
+        let ambient_occlusion = shared_mesh.get_ambient_occlusion(256, 5.0);
+        assert!(ambient_occlusion[0] < 0.2);
+        assert!(ambient_occlusion[1..5].iter().all(|&occlusion| occlusion > 0.99));
+
+        // The box is out of reach
+        assert_eq!(shared_mesh.get_ambient_occlusion(256, 0.05)[0], 1.0);
+
+        shared_mesh.colors = Some(vec![DVec3::new(0.5, 1., 1.); shared_mesh.positions.len()]);
+        shared_mesh.bake_ambient_occlusion(256, 5.0, AmbientOcclusionBlend::Multiply);
+        let colors = shared_mesh.colors.as_ref().unwrap();
+        assert_eq!(colors[0], DVec3::new(0.5, 1., 1.) * ambient_occlusion[0]);
+        shared_mesh.bake_ambient_occlusion(256, 5.0, AmbientOcclusionBlend::Replace);
+        assert_eq!(shared_mesh.colors.unwrap()[0], DVec3::repeat(ambient_occlusion[0]));
+    }
+}
@@ -0,0 +1,205 @@
+use super::SharedMesh;
+use super::topology::*;
+
+use hashbrown::{HashMap, HashSet};
+
+/// Topology and validity of a mesh, as returned by `SharedMesh::analyze`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TopologyReport {
+    /// Vertices used by at least one triangle
+    pub vertex_count: usize,
+    pub unreferenced_vertex_count: usize,
+    pub face_count: usize,
+    pub edge_count: usize,
+    /// Edges with a single triangle
+    pub boundary_edge_count: usize,
+    pub boundary_loop_count: usize,
+    /// Edges with more than two triangles
+    pub non_manifold_edge_count: usize,
+    /// Vertices whose triangles don't form a single fan
+    pub non_manifold_vertex_count: usize,
+    /// Components of triangles connected by vertices
+    pub component_count: usize,
+    /// V - E + F
+    pub euler_characteristic: i64,
+    /// Number of handles, only known for orientable manifolds
+    pub genus: Option<u32>,
+    pub degenerate_triangle_count: usize,
+    /// Edges that both of their triangles walk in the same direction
+    pub inconsistent_winding_count: usize,
+    pub is_manifold: bool,
+    /// Manifold without boundaries
+    pub is_watertight: bool,
+    /// Whether every component can be oriented consistently, even if its triangles currently aren't
+    pub is_orientable: bool,
+}
+
+impl SharedMesh {
+    /// Returns the topology of the mesh and its defects, without changing it. See `repair` to fix them.
+    pub fn analyze(&self) -> TopologyReport {
+        let edges = build_edge_map(&self.triangles);
+
+        let mut used_vertices = HashSet::new();
+        let mut vertex_components = UnionFind::new(self.positions.len());
+        for triangle in &self.triangles {
+            used_vertices.extend([triangle[0], triangle[1], triangle[2]]);
+            vertex_components.union(triangle[0], triangle[1]);
+            vertex_components.union(triangle[0], triangle[2]);
+        }
+        let component_count = used_vertices.iter().map(|&v| vertex_components.find(v)).collect::<HashSet<u32>>().len();
+
+        let mut boundary_loops = UnionFind::new(self.positions.len());
+        let mut boundary_vertices = HashSet::new();
+        let mut boundary_edge_count = 0;
+        let mut non_manifold_edge_count = 0;
+        let mut inconsistent_winding_count = 0;
+        for faces in edges.values() {
+            match faces[..] {
+                [(t, x)] => {
+                    let (a, b) = (self.triangles[t as usize][x as usize], self.triangles[t as usize][(x as usize + 1) % 3]);
+                    boundary_loops.union(a, b);
+                    boundary_vertices.extend([a, b]);
+                    boundary_edge_count += 1;
+                },
+                [(t1, x1), (t2, x2)] => {
+                    if self.triangles[t1 as usize][x1 as usize] == self.triangles[t2 as usize][x2 as usize] {
+                        inconsistent_winding_count += 1;
+                    }
+                },
+                _ => non_manifold_edge_count += 1,
+            }
+        }
+        // Boundary vertices of a manifold have two boundary edges, so connected boundary edges form loops
+        let boundary_loop_count = boundary_vertices.iter().map(|&v| boundary_loops.find(v)).collect::<HashSet<u32>>().len();
+
+        let mut fans = get_corner_fans(&self.triangles, &edges);
+        let mut vertex_fans = HashSet::new();
+        let mut fan_counts: HashMap<u32, u32> = HashMap::new();
+        for corner in 0..self.triangles.len() as u32 * 3 {
+            let vertex = self.triangles[(corner / 3) as usize][(corner % 3) as usize];
+            if vertex_fans.insert((vertex, fans.find(corner))) {
+                *fan_counts.entry(vertex).or_default() += 1;
+            }
+        }
+        let non_manifold_vertex_count = fan_counts.values().filter(|&&count| count > 1).count();
+
+        let (triangle_components, triangle_component_count) = get_components(&self.triangles, &edges);
+        let (_, orientable_components) = get_relative_flips(&self.triangles, &edges, &triangle_components, triangle_component_count);
+        let is_orientable = orientable_components.iter().all(|&orientable| orientable);
+
+        let euler_characteristic = used_vertices.len() as i64 - edges.len() as i64 + self.triangles.len() as i64;
+        let is_manifold = non_manifold_edge_count == 0 && non_manifold_vertex_count == 0;
+
+        // χ = 2c - 2g - b for orientable surfaces with c components, g handles and b boundary loops
+        let double_genus = 2 * component_count as i64 - euler_characteristic - boundary_loop_count as i64;
+        let genus = if is_manifold && is_orientable && double_genus >= 0 && double_genus % 2 == 0 {
+            Some((double_genus / 2) as u32)
+        } else {
+            None
+        };
+
+        TopologyReport {
+            vertex_count: used_vertices.len(),
+            unreferenced_vertex_count: self.positions.len() - used_vertices.len(),
+            face_count: self.triangles.len(),
+            edge_count: edges.len(),
+            boundary_edge_count,
+            boundary_loop_count,
+            non_manifold_edge_count,
+            non_manifold_vertex_count,
+            component_count,
+            euler_characteristic,
+            genus,
+            degenerate_triangle_count: self.triangles.iter().filter(|triangle| self.is_degenerate(triangle)).count(),
+            inconsistent_winding_count,
+            is_manifold,
+            is_watertight: is_manifold && boundary_edge_count == 0,
+            is_orientable,
+        }
+    }
+}
+
+#[cfg(test)]
+mod analysis_tests {
+    use crate::mesh::*;
+    use crate::mesh::test_utils::read_sphere;
+
+    // Torus made of a grid of `size` x `size` quads
+    fn build_torus(size: u32) -> SharedMesh {
+        let mut positions = Vec::new();
+        let mut triangles = Vec::new();
+        for i in 0..size {
+            for j in 0..size {
+                let (u, v) = (i as f64 / size as f64 * std::f64::consts::TAU, j as f64 / size as f64 * std::f64::consts::TAU);
+                positions.push(DVec3::new((2. + v.cos()) * u.cos(), (2. + v.cos()) * u.sin(), v.sin()));
+                let (a, b, c, d) = (i * size + j, ((i + 1) % size) * size + j, ((i + 1) % size) * size + (j + 1) % size, i * size + (j + 1) % size);
+                triangles.push(U32Vec3::new(a, b, c));
+                triangles.push(U32Vec3::new(a, c, d));
+            }
+        }
+        SharedMesh { positions, triangles, groups: Vec::new(), normals: None, colors: None, uvs: None }
+    }
+
+    #[test]
+    fn analyze_sphere() {
+        let report = read_sphere().analyze();
+
+        assert_eq!(report.vertex_count, 642);
+        assert_eq!(report.face_count, 1280);
+        assert_eq!(report.edge_count, 1920);
+        assert_eq!(report.euler_characteristic, 2);
+        assert_eq!(report.genus, Some(0));
+        assert_eq!(report.component_count, 1);
+        assert!(report.is_watertight);
+        assert_eq!(report.inconsistent_winding_count, 0);
+    }
+
+    #[test]
+    fn analyze_torus_with_hole() {
+        let mut shared_mesh = build_torus(8);
+        let report = shared_mesh.analyze();
+        assert_eq!(report.euler_characteristic, 0);
+        assert_eq!(report.genus, Some(1));
+        assert!(report.is_watertight);
+
+        shared_mesh.triangles.remove(10);
+        shared_mesh.triangles[20].swap_rows(1, 2);
+        let report = shared_mesh.analyze();
+        assert_eq!(report.boundary_edge_count, 3);
+        assert_eq!(report.boundary_loop_count, 1);
+        assert_eq!(report.genus, Some(1));
+        assert_eq!(report.inconsistent_winding_count, 3);
+        assert!(report.is_manifold && report.is_orientable && !report.is_watertight);
+    }
+
+    #[test]
+    fn analyze_defects() {
+        // Two tori touching on a vertex, a duplicate triangle and a degenerate triangle on its own
+        let mut shared_mesh = SharedMesh::combine(build_torus(6), build_torus(6));
+        for triangle in shared_mesh.triangles.iter_mut().skip(72) {
+            for x in 0..3 {
+                if triangle[x] == 36 {
+                    triangle[x] = 0;
+                }
+            }
+        }
+        shared_mesh.triangles.push(shared_mesh.triangles[0]);
+        shared_mesh.positions.extend([DVec3::new(5., 0., 0.), DVec3::new(6., 0., 0.), DVec3::new(7., 0., 0.)]);
+        shared_mesh.triangles.push(U32Vec3::new(72, 73, 74));
+
+        let report = shared_mesh.analyze();
+
+        assert_eq!(report.unreferenced_vertex_count, 1);
+        assert_eq!(report.component_count, 2);
+        assert_eq!(report.non_manifold_edge_count, 3);
+        assert_eq!(report.non_manifold_vertex_count, 3);
+        assert_eq!(report.degenerate_triangle_count, 1);
+        assert_eq!(report.genus, None);
+        assert!(!report.is_manifold);
+
+        let repair_report = shared_mesh.repair();
+        assert_eq!((repair_report.duplicate_triangles, repair_report.degenerate_triangles, repair_report.split_vertices), (1, 1, 1));
+        assert!(shared_mesh.analyze().is_manifold);
+    }
+}
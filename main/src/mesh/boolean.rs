@@ -0,0 +1,632 @@
+use nalgebra_glm as glm;
+use glm::{DVec2, DVec3, U32Vec3};
+use hashbrown::HashMap;
+use super::SharedMesh;
+use super::topology::*;
+use crate::base::Box3;
+use crate::spatial::Bvh;
+use crate::spatial::predicates::*;
+
+// Both meshes are cut along their intersection curves, then pieces are kept depending on which side of the other mesh they are.
+// Intersection points are identified combinatorially from exact predicates, as a vertex, an edge through a triangle or two edges
+// crossing, so that every triangle around a point agrees on it and cuts join without cracks. Only their positions are rounded.
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BooleanOperation {
+    Union,
+    Intersection,
+    /// The second mesh is removed from the first one
+    Difference,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BooleanError {
+    /// An input has edges that aren't shared by exactly two triangles walking them in opposite directions
+    NotClosed,
+    /// A cut triangle couldn't be triangulated, because intersection points are too close to be told apart once rounded
+    Triangulation,
+}
+
+impl SharedMesh {
+    /// Union, intersection or difference of two closed meshes oriented outward, such as repaired ones.
+    /// Coplanar faces are supported. The result is closed, with vertices shared along the intersection curves,
+    /// and doesn't keep vertex attributes nor groups.
+    pub fn boolean(&self, other: &SharedMesh, operation: BooleanOperation) -> Result<SharedMesh, BooleanError> {
+        if !is_closed(&self.triangles, &build_edge_map(&self.triangles)) || !is_closed(&other.triangles, &build_edge_map(&other.triangles)) {
+            return Err(BooleanError::NotClosed);
+        }
+        let mut boolean = Boolean::new(self, other);
+        boolean.find_intersections();
+        let (triangles, sources) = boolean.cut_triangles()?;
+        let sides = boolean.classify(&triangles, &sources);
+
+        let first_other = boolean.first_other as u32;
+        let mut kept = Vec::new();
+        for ((triangle, source), side) in triangles.iter().zip(sources.iter()).zip(sides.iter()) {
+            let is_first = *source < first_other;
+            let keep = match (operation, is_first, side) {
+                // Coplanar pieces are kept once, from the first mesh
+                (_, false, Side::SameShared | Side::OppositeShared) => false,
+                (BooleanOperation::Union | BooleanOperation::Intersection, true, Side::SameShared) => true,
+                (BooleanOperation::Difference, true, Side::OppositeShared) => true,
+                (_, _, Side::SameShared | Side::OppositeShared) => false,
+                (BooleanOperation::Union, _, side) => *side == Side::Outside,
+                (BooleanOperation::Intersection, _, side) => *side == Side::Inside,
+                (BooleanOperation::Difference, true, side) => *side == Side::Outside,
+                (BooleanOperation::Difference, false, side) => *side == Side::Inside,
+            };
+            if keep {
+                let is_flipped = operation == BooleanOperation::Difference && !is_first;
+                kept.push(if is_flipped { U32Vec3::new(triangle[0], triangle[2], triangle[1]) } else { *triangle });
+            }
+        }
+
+        // Only keeps used vertices
+        let mut new_indices = vec![u32::MAX; boolean.positions.len()];
+        let mut positions = Vec::new();
+        let triangles = kept.iter().map(|triangle| triangle.map(|v| {
+            if new_indices[v as usize] == u32::MAX {
+                new_indices[v as usize] = positions.len() as u32;
+                positions.push(boolean.positions[v as usize]);
+            }
+            new_indices[v as usize]
+        })).collect();
+        Ok(SharedMesh { groups: Vec::new(), triangles, positions, normals: None, colors: None, uvs: None })
+    }
+
+    pub fn union(&self, other: &SharedMesh) -> Result<SharedMesh, BooleanError> {
+        self.boolean(other, BooleanOperation::Union)
+    }
+
+    pub fn intersection(&self, other: &SharedMesh) -> Result<SharedMesh, BooleanError> {
+        self.boolean(other, BooleanOperation::Intersection)
+    }
+
+    pub fn difference(&self, other: &SharedMesh) -> Result<SharedMesh, BooleanError> {
+        self.boolean(other, BooleanOperation::Difference)
+    }
+}
+
+// Point where triangles of both meshes meet. Edges are edge keys, and edge pairs are sorted.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Crossing {
+    Vertex(u32),
+    EdgeTriangle(u64, u32),
+    EdgeEdge(u64, u64),
+}
+
+fn get_edge_edge(e1: u64, e2: u64) -> Crossing {
+    Crossing::EdgeEdge(e1.min(e2), e1.max(e2))
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Location {
+    Corner,
+    /// On the edge starting at the given corner
+    Edge(usize),
+    Interior,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Side {
+    Inside,
+    Outside,
+    /// On a face of the other mesh, facing the same way
+    SameShared,
+    OppositeShared,
+}
+
+// Crossings in a triangle, and the segments of intersection curves between them
+#[derive(Debug, Default)]
+struct TriangleCut {
+    crossings: Vec<Crossing>,
+    segments: Vec<[Crossing; 2]>,
+    coplanar_triangles: Vec<u32>,
+}
+
+// Both meshes in a single vertex and triangle buffer, the triangles of the second mesh starting at `first_other`.
+// Vertices of the second mesh at the exact position of a vertex of the first one are merged into it.
+struct Boolean {
+    positions: Vec<DVec3>,
+    triangles: Vec<U32Vec3>,
+    first_other: usize,
+    cuts: HashMap<u32, TriangleCut>,
+    crossing_vertices: HashMap<Crossing, u32>,
+}
+
+impl Boolean {
+    fn new(a: &SharedMesh, b: &SharedMesh) -> Self {
+        let position_key = |p: &DVec3| [p.x, p.y, p.z].map(|x| (x + 0.0).to_bits());
+        let mut vertex_of_position: HashMap<[u64; 3], u32> = HashMap::with_capacity(a.positions.len());
+        for (v, position) in a.positions.iter().enumerate() {
+            vertex_of_position.entry(position_key(position)).or_insert(v as u32);
+        }
+        let mut positions = a.positions.clone();
+        let b_vertices: Vec<u32> = b.positions.iter().map(|position| match vertex_of_position.get(&position_key(position)) {
+            Some(v) => *v,
+            None => {
+                positions.push(*position);
+                positions.len() as u32 - 1
+            },
+        }).collect();
+        let mut triangles = a.triangles.clone();
+        triangles.extend(b.triangles.iter().map(|triangle| triangle.map(|v| b_vertices[v as usize])));
+        Boolean { positions, triangles, first_other: a.triangles.len(), cuts: HashMap::new(), crossing_vertices: HashMap::new() }
+    }
+
+    fn get_corners(&self, triangle: u32) -> [DVec3; 3] {
+        let triangle = &self.triangles[triangle as usize];
+        [0, 1, 2].map(|x| self.positions[triangle[x] as usize])
+    }
+
+    fn is_degenerate(&self, triangle: u32) -> bool {
+        let [a, b, c] = self.get_corners(triangle);
+        (0..3).all(|axis| orient2d(&project(&a, axis), &project(&b, axis), &project(&c, axis)) == 0.0)
+    }
+
+    fn orient(&self, triangle: u32, vertex: u32) -> f64 {
+        let [a, b, c] = self.get_corners(triangle);
+        orient3d(&a, &b, &c, &self.positions[vertex as usize])
+    }
+
+    fn get_position(&self, crossing: Crossing) -> DVec3 {
+        let get_edge = |edge: u64| (self.positions[(edge >> 32) as usize], self.positions[(edge & 0xFFFF_FFFF) as usize]);
+        match crossing {
+            Crossing::Vertex(v) => self.positions[v as usize],
+            Crossing::EdgeTriangle(edge, triangle) => {
+                let (d, e) = get_edge(edge);
+                let [a, b, c] = self.get_corners(triangle);
+                let normal = (b - a).cross(&(c - a));
+                d + (e - d) * (normal.dot(&(a - d)) / normal.dot(&(e - d)))
+            },
+            Crossing::EdgeEdge(e1, e2) => {
+                let ((p, p_end), (q, q_end)) = (get_edge(e1), get_edge(e2));
+                let (u, v) = (p_end - p, q_end - q);
+                let normal = u.cross(&v);
+                p + u * ((q - p).cross(&v).dot(&normal) / normal.magnitude_squared())
+            },
+        }
+    }
+
+    fn find_intersections(&mut self) {
+        let other_triangles = self.triangles[self.first_other..].to_vec();
+        let bvh = Bvh::new(self.positions.clone(), other_triangles);
+        let mut candidates = Vec::new();
+        for t in 0..self.first_other as u32 {
+            if self.is_degenerate(t) {
+                continue;
+            }
+            let mut bounds = Box3::unfitted();
+            for corner in self.get_corners(t).iter() {
+                bounds.expand(corner);
+            }
+            candidates.clear();
+            bvh.visit_box_leaves(&bounds, |u| candidates.push(u + self.first_other as u32));
+            candidates.sort_unstable();
+            for &u in candidates.iter() {
+                if !self.is_degenerate(u) {
+                    self.intersect_triangles(t, u);
+                }
+            }
+        }
+    }
+
+    fn intersect_triangles(&mut self, t: u32, u: u32) {
+        let (p, q) = (self.triangles[t as usize], self.triangles[u as usize]);
+        if (0..3).all(|y| self.orient(t, q[y]) == 0.0) {
+            // Edges of each triangle clipped by the other one, in their common plane
+            let in_t = self.clip_edges(&q, t);
+            let in_u = self.clip_edges(&p, u);
+            if in_t.is_empty() && in_u.is_empty() {
+                return;
+            }
+            let crossings: Vec<Crossing> = in_t.iter().chain(in_u.iter()).flatten().cloned().collect();
+            for (triangle, chains, coplanar) in [(t, &in_t, u), (u, &in_u, t)] {
+                let cut = self.cuts.entry(triangle).or_default();
+                cut.crossings.extend_from_slice(&crossings);
+                cut.segments.extend(chains.iter().flat_map(|chain| chain.windows(2).map(|pair| [pair[0], pair[1]])));
+                cut.coplanar_triangles.push(coplanar);
+            }
+            return;
+        }
+
+        let mut crossings = Vec::new();
+        for x in 0..3 {
+            self.intersect_edge(p[x], p[(x + 1) % 3], u, &mut crossings);
+            self.intersect_edge(q[x], q[(x + 1) % 3], t, &mut crossings);
+        }
+        crossings.sort_unstable();
+        crossings.dedup();
+        if crossings.is_empty() {
+            return;
+        }
+
+        // Crossings are all on the line where both planes meet
+        let [a, b, c] = self.get_corners(t);
+        let [d, e, f] = self.get_corners(u);
+        let direction = (b - a).cross(&(c - a)).cross(&(e - d).cross(&(f - d)));
+        let mut sorted: Vec<(f64, Crossing)> = crossings.iter().map(|&crossing| (direction.dot(&self.get_position(crossing)), crossing)).collect();
+        sorted.sort_by(|x, y| x.0.total_cmp(&y.0));
+        let segments: Vec<[Crossing; 2]> = sorted.windows(2).map(|pair| [pair[0].1, pair[1].1]).collect();
+        for triangle in [t, u] {
+            let cut = self.cuts.entry(triangle).or_default();
+            cut.crossings.extend_from_slice(&crossings);
+            cut.segments.extend_from_slice(&segments);
+        }
+    }
+
+    // Crossings of the edge de with a triangle, which don't include the edge ends when it only touches the plane of the triangle
+    fn intersect_edge(&self, d: u32, e: u32, triangle: u32, crossings: &mut Vec<Crossing>) {
+        let (od, oe) = (self.orient(triangle, d), self.orient(triangle, e));
+        if od == 0.0 && oe == 0.0 {
+            crossings.extend(self.clip_edge(d, e, triangle));
+            return;
+        }
+        if (od > 0.0 && oe > 0.0) || (od < 0.0 && oe < 0.0) {
+            return;
+        }
+        if od == 0.0 || oe == 0.0 {
+            let v = if od == 0.0 { d } else { e };
+            let corners = self.get_corners(triangle);
+            let axis = get_dominant_axis(&corners[0], &corners[1], &corners[2]);
+            let [a, b, c] = corners.map(|corner| project(&corner, axis));
+            if point_in_triangle_2d(&project(&self.positions[v as usize], axis), &a, &b, &c) {
+                crossings.push(Crossing::Vertex(v));
+            }
+            return;
+        }
+
+        // The edge goes through the plane, and through the triangle when it turns the same way around all of its edges
+        let corners = self.triangles[triangle as usize];
+        let (pd, pe) = (self.positions[d as usize], self.positions[e as usize]);
+        let signs = [0, 1, 2].map(|x| orient3d(&pd, &pe, &self.positions[corners[x] as usize], &self.positions[corners[(x + 1) % 3] as usize]));
+        if signs.iter().any(|&s| s > 0.0) && signs.iter().any(|&s| s < 0.0) {
+            return;
+        }
+        let edge = get_edge_key(d, e);
+        match signs.iter().filter(|&&s| s == 0.0).count() {
+            0 => crossings.push(Crossing::EdgeTriangle(edge, triangle)),
+            1 => {
+                let x = signs.iter().position(|&s| s == 0.0).unwrap();
+                crossings.push(get_edge_edge(edge, get_edge_key(corners[x], corners[(x + 1) % 3])));
+            },
+            // Through the corner between both edges
+            _ => {
+                let x = (0..3).find(|&x| signs[x] == 0.0 && signs[(x + 1) % 3] == 0.0).unwrap();
+                crossings.push(Crossing::Vertex(corners[(x + 1) % 3]));
+            },
+        }
+    }
+
+    // Parts of the edges of `edges` inside the coplanar triangle, as chains of crossings along each edge
+    fn clip_edges(&self, edges: &U32Vec3, triangle: u32) -> Vec<Vec<Crossing>> {
+        (0..3).map(|x| self.clip_edge(edges[x], edges[(x + 1) % 3], triangle)).filter(|chain| !chain.is_empty()).collect()
+    }
+
+    // Crossings of the edge de with a triangle in the same plane, sorted from d to e
+    fn clip_edge(&self, d: u32, e: u32, triangle: u32) -> Vec<Crossing> {
+        let corners = self.get_corners(triangle);
+        let axis = get_dominant_axis(&corners[0], &corners[1], &corners[2]);
+        let vertices = self.triangles[triangle as usize];
+        let [a, b, c] = corners.map(|corner| project(&corner, axis));
+        let (pd, pe) = (project(&self.positions[d as usize], axis), project(&self.positions[e as usize], axis));
+
+        let mut crossings = Vec::new();
+        for (v, point) in [(d, &pd), (e, &pe)] {
+            if point_in_triangle_2d(point, &a, &b, &c) {
+                crossings.push(Crossing::Vertex(v));
+            }
+        }
+        let projected = [a, b, c];
+        for x in 0..3 {
+            let (p, q) = (&projected[x], &projected[(x + 1) % 3]);
+            let (op, oq) = (orient2d(&pd, &pe, p), orient2d(&pd, &pe, q));
+            for (o, point, v) in [(op, p, vertices[x]), (oq, q, vertices[(x + 1) % 3])] {
+                if o == 0.0 && is_within_segment_2d(point, &pd, &pe) {
+                    crossings.push(Crossing::Vertex(v));
+                }
+            }
+            let (od, oe) = (orient2d(p, q, &pd), orient2d(p, q, &pe));
+            if ((op > 0.0 && oq < 0.0) || (op < 0.0 && oq > 0.0)) && ((od > 0.0 && oe < 0.0) || (od < 0.0 && oe > 0.0)) {
+                crossings.push(get_edge_edge(get_edge_key(d, e), get_edge_key(vertices[x], vertices[(x + 1) % 3])));
+            }
+        }
+        crossings.sort_unstable();
+        crossings.dedup();
+
+        let (start, direction) = (self.positions[d as usize], self.positions[e as usize] - self.positions[d as usize]);
+        let mut sorted: Vec<(f64, Crossing)> = crossings.into_iter().map(|crossing| (direction.dot(&(self.get_position(crossing) - start)), crossing)).collect();
+        sorted.sort_by(|x, y| x.0.total_cmp(&y.0));
+        sorted.into_iter().map(|(_, crossing)| crossing).collect()
+    }
+
+    // Where a crossing found in a triangle is on it, from how it was found
+    fn locate(&self, crossing: Crossing, triangle: u32) -> Location {
+        let vertices = self.triangles[triangle as usize];
+        let edge_index = |edge: u64| (0..3).find(|&x| get_edge_key(vertices[x], vertices[(x + 1) % 3]) == edge);
+        match crossing {
+            Crossing::Vertex(v) => {
+                if vertices.iter().any(|&corner| corner == v) {
+                    return Location::Corner;
+                }
+                let corners = self.get_corners(triangle);
+                let axis = get_dominant_axis(&corners[0], &corners[1], &corners[2]);
+                let point = project(&self.positions[v as usize], axis);
+                let projected = corners.map(|corner| project(&corner, axis));
+                (0..3).find(|&x| orient2d(&projected[x], &projected[(x + 1) % 3], &point) == 0.0).map_or(Location::Interior, Location::Edge)
+            },
+            Crossing::EdgeTriangle(_, t) if t == triangle => Location::Interior,
+            Crossing::EdgeTriangle(edge, _) => edge_index(edge).map_or(Location::Interior, Location::Edge),
+            Crossing::EdgeEdge(e1, e2) => edge_index(e1).or_else(|| edge_index(e2)).map_or(Location::Interior, Location::Edge),
+        }
+    }
+
+    fn get_crossing_vertex(&mut self, crossing: Crossing) -> u32 {
+        if let Crossing::Vertex(v) = crossing {
+            return v;
+        }
+        if let Some(vertex) = self.crossing_vertices.get(&crossing) {
+            return *vertex;
+        }
+        self.positions.push(self.get_position(crossing));
+        let vertex = self.positions.len() as u32 - 1;
+        self.crossing_vertices.insert(crossing, vertex);
+        vertex
+    }
+
+    // Triangles of both meshes, with the cut ones replaced by their triangulation, and the triangle each one comes from
+    fn cut_triangles(&mut self) -> Result<(Vec<U32Vec3>, Vec<u32>), BooleanError> {
+        let mut triangles = Vec::with_capacity(self.triangles.len());
+        let mut sources = Vec::with_capacity(self.triangles.len());
+        for t in 0..self.triangles.len() as u32 {
+            let pieces = match self.cuts.remove(&t) {
+                Some(mut cut) => {
+                    let pieces = self.triangulate_cut(t, &mut cut)?;
+                    self.cuts.insert(t, cut);
+                    pieces
+                },
+                None => vec![self.triangles[t as usize]],
+            };
+            sources.extend(std::iter::repeat_n(t, pieces.len()));
+            triangles.extend(pieces);
+        }
+        Ok((triangles, sources))
+    }
+
+    fn triangulate_cut(&mut self, t: u32, cut: &mut TriangleCut) -> Result<Vec<U32Vec3>, BooleanError> {
+        let corners = self.triangles[t as usize];
+        cut.crossings.sort_unstable();
+        cut.crossings.dedup();
+
+        // Points of the triangulation: corners, then crossings on edges and inside
+        let mut vertices: Vec<u32> = corners.iter().cloned().collect();
+        let mut locations = vec![Location::Corner; 3];
+        let mut local_indices: HashMap<Crossing, usize> = HashMap::with_capacity(cut.crossings.len());
+        for &crossing in cut.crossings.iter() {
+            let location = self.locate(crossing, t);
+            let local = match crossing {
+                Crossing::Vertex(v) if location == Location::Corner => corners.iter().position(|&corner| corner == v).unwrap(),
+                _ => {
+                    vertices.push(self.get_crossing_vertex(crossing));
+                    locations.push(location);
+                    vertices.len() - 1
+                },
+            };
+            local_indices.insert(crossing, local);
+        }
+        if vertices.len() == 3 && cut.segments.iter().all(|segment| segment[0] == segment[1]) {
+            return Ok(vec![corners]);
+        }
+
+        // Border of the triangle, split at crossings on its edges
+        let mut edges = Vec::new();
+        for x in 0..3 {
+            let (start, end) = (self.positions[corners[x] as usize], self.positions[corners[(x + 1) % 3] as usize]);
+            let mut on_edge: Vec<(f64, usize)> = (3..vertices.len())
+                .filter(|&i| locations[i] == Location::Edge(x))
+                .map(|i| ((end - start).dot(&(self.positions[vertices[i] as usize] - start)), i))
+                .collect();
+            on_edge.sort_by(|a, b| a.0.total_cmp(&b.0));
+            let chain: Vec<usize> = std::iter::once(x).chain(on_edge.iter().map(|(_, i)| *i)).chain(std::iter::once((x + 1) % 3)).collect();
+            edges.extend(chain.windows(2).map(|pair| (pair[0], pair[1])));
+        }
+
+        // Intersection curves inside of the triangle. Interior edges are given twice so that they don't change which side
+        // of the border is inside, for the triangulation.
+        let is_on_edge = |i: usize, x: usize| match locations[i] {
+            Location::Corner => i == x || i == (x + 1) % 3,
+            location => location == Location::Edge(x),
+        };
+        let mut constraints: Vec<(usize, usize)> = cut.segments.iter()
+            .map(|segment| (local_indices[&segment[0]], local_indices[&segment[1]]))
+            .filter(|&(i, j)| i != j && !(0..3).any(|x| is_on_edge(i, x) && is_on_edge(j, x)))
+            .map(|(i, j)| (i.min(j), i.max(j)))
+            .collect();
+        constraints.sort_unstable();
+        constraints.dedup();
+        for constraint in constraints {
+            edges.push(constraint);
+            edges.push(constraint);
+        }
+
+        let positions = vertices.iter().map(|&v| self.positions[v as usize]).collect::<Vec<DVec3>>();
+        let axis = get_dominant_axis(&positions[0], &positions[1], &positions[2]);
+        let points: Vec<(f64, f64)> = positions.iter().map(|p| {
+            let point = project(p, axis);
+            (point.x, point.y)
+        }).collect();
+        let triangles = cdt::triangulate_with_edges(&points, &edges).map_err(|_| BooleanError::Triangulation)?;
+
+        // The triangulation is counterclockwise in the projection, which may mirror the triangle
+        let corners_2d = [0, 1, 2].map(|x| DVec2::new(points[x].0, points[x].1));
+        let is_mirrored = orient2d(&corners_2d[0], &corners_2d[1], &corners_2d[2]) < 0.0;
+        Ok(triangles.iter().map(|&(a, b, c)| {
+            let (b, c) = if is_mirrored { (c, b) } else { (b, c) };
+            U32Vec3::new(vertices[a], vertices[b], vertices[c])
+        }).collect())
+    }
+
+    // Side of the other mesh every triangle is on. Triangles connected without going through an intersection curve are on
+    // the same side, so the winding number of the other mesh is only computed once for each such patch.
+    fn classify(&self, triangles: &[U32Vec3], sources: &[u32]) -> Vec<Side> {
+        let mut is_on_curve = vec![false; self.positions.len()];
+        for vertex in self.crossing_vertices.values() {
+            is_on_curve[*vertex as usize] = true;
+        }
+        for cut in self.cuts.values() {
+            for crossing in cut.crossings.iter() {
+                if let Crossing::Vertex(v) = crossing {
+                    is_on_curve[*v as usize] = true;
+                }
+            }
+        }
+
+        let mut sides = vec![Side::Outside; triangles.len()];
+        let mut is_shared = vec![false; triangles.len()];
+        for (t, triangle) in triangles.iter().enumerate() {
+            if let Some(side) = self.get_shared_side(triangle, sources[t]) {
+                sides[t] = side;
+                is_shared[t] = true;
+            }
+        }
+
+        let edges = build_edge_map(triangles);
+        let mut patches = UnionFind::new(triangles.len());
+        for (edge, faces) in edges.iter() {
+            let (a, b) = ((edge >> 32) as usize, (edge & 0xFFFF_FFFF) as usize);
+            if let [(t1, _), (t2, _)] = faces[..] {
+                let is_same_mesh = (sources[t1 as usize] < self.first_other as u32) == (sources[t2 as usize] < self.first_other as u32);
+                let is_cut = (is_on_curve[a] && is_on_curve[b]) || is_shared[t1 as usize] || is_shared[t2 as usize];
+                if is_same_mesh && !is_cut {
+                    patches.union(t1, t2);
+                }
+            }
+        }
+
+        // Largest triangle of every patch, whose center is the furthest from the other mesh
+        let get_area = |triangle: &U32Vec3| {
+            let [a, b, c] = [0, 1, 2].map(|x| self.positions[triangle[x] as usize]);
+            (b - a).cross(&(c - a)).magnitude()
+        };
+        let mut representatives: HashMap<u32, (f64, usize)> = HashMap::new();
+        for (t, triangle) in triangles.iter().enumerate().filter(|(t, _)| !is_shared[*t]) {
+            let area = get_area(triangle);
+            let representative = representatives.entry(patches.find(t as u32)).or_insert((area, t));
+            if area > representative.0 {
+                *representative = (area, t);
+            }
+        }
+        let patch_sides: HashMap<u32, Side> = representatives.iter().map(|(patch, (_, t))| {
+            let triangle = &triangles[*t];
+            let center = (self.positions[triangle[0] as usize] + self.positions[triangle[1] as usize] + self.positions[triangle[2] as usize]) / 3.0;
+            let others = if sources[*t] < self.first_other as u32 { self.first_other..self.triangles.len() } else { 0..self.first_other };
+            let winding_number = self.get_winding_number(&center, &self.triangles[others]);
+            (*patch, if winding_number.abs() > 0.5 { Side::Inside } else { Side::Outside })
+        }).collect();
+        for t in 0..triangles.len() {
+            if !is_shared[t] {
+                sides[t] = patch_sides[&patches.find(t as u32)];
+            }
+        }
+        sides
+    }
+
+    // Whether a piece of a triangle lies on a coplanar triangle of the other mesh
+    fn get_shared_side(&self, triangle: &U32Vec3, source: u32) -> Option<Side> {
+        let cut = self.cuts.get(&source)?;
+        let [a, b, c] = [0, 1, 2].map(|x| self.positions[triangle[x] as usize]);
+        let center = (a + b + c) / 3.0;
+        let normal = (b - a).cross(&(c - a));
+        cut.coplanar_triangles.iter().find_map(|&other| {
+            let corners = self.get_corners(other);
+            let axis = get_dominant_axis(&corners[0], &corners[1], &corners[2]);
+            let [p, q, r] = corners.map(|corner| project(&corner, axis));
+            let point = project(&center, axis);
+            let signs = [orient2d(&p, &q, &point), orient2d(&q, &r, &point), orient2d(&r, &p, &point)];
+            if !signs.iter().all(|&s| s > 0.0) && !signs.iter().all(|&s| s < 0.0) {
+                return None;
+            }
+            let other_normal = (corners[1] - corners[0]).cross(&(corners[2] - corners[0]));
+            Some(if normal.dot(&other_normal) > 0.0 { Side::SameShared } else { Side::OppositeShared })
+        })
+    }
+
+    // Sum of the solid angles of the triangles seen from the point (Van Oosterom and Strackee), over 4π.
+    // About 1 inside a closed mesh oriented outward, and 0 outside.
+    fn get_winding_number(&self, point: &DVec3, triangles: &[U32Vec3]) -> f64 {
+        triangles.iter().map(|triangle| {
+            let [a, b, c] = [0, 1, 2].map(|x| self.positions[triangle[x] as usize] - point);
+            let (la, lb, lc) = (a.magnitude(), b.magnitude(), c.magnitude());
+            let numerator = a.dot(&b.cross(&c));
+            let denominator = la * lb * lc + a.dot(&b) * lc + b.dot(&c) * la + c.dot(&a) * lb;
+            2.0 * numerator.atan2(denominator)
+        }).sum::<f64>() / (4.0 * std::f64::consts::PI)
+    }
+}
+
+#[cfg(test)]
+mod boolean_tests {
+    use crate::mesh::*;
+    use crate::mesh::test_utils::{build_box, read_sphere};
+
+    fn translate(shared_mesh: SharedMesh, offset: DVec3) -> SharedMesh {
+        SharedMesh { positions: shared_mesh.positions.iter().map(|p| p + offset).collect(), ..shared_mesh }
+    }
+
+    // Checks that every operation gives a closed mesh of the expected volume
+    fn assert_volumes(a: &SharedMesh, b: &SharedMesh, volumes: [f64; 3], tolerance: f64) {
+        let operations = [BooleanOperation::Union, BooleanOperation::Intersection, BooleanOperation::Difference];
+        for (operation, volume) in operations.iter().zip(volumes.iter()) {
+            let result = a.boolean(b, *operation).unwrap();
+            let report = result.analyze();
+            assert!(report.face_count == 0 || (report.is_watertight && report.is_orientable), "{:?} {:?}", operation, report);
+            let properties = result.get_mass_properties();
+            assert!((properties.volume - volume).abs() < tolerance, "{:?}: {} != {}", operation, properties.volume, volume);
+        }
+    }
+
+    #[test]
+    fn overlapping_boxes() {
+        // Edges of each box go through the diagonals of the faces of the other one
+        let a = build_box(DVec3::zeros(), DVec3::new(1., 1., 1.));
+        let b = build_box(DVec3::new(0.5, 0.5, 0.5), DVec3::new(1., 1., 1.));
+        assert_volumes(&a, &b, [1.875, 0.125, 0.875], 1e-12);
+        assert_volumes(&b, &a, [1.875, 0.125, 0.875], 1e-12);
+    }
+
+    #[test]
+    fn coplanar_faces() {
+        let a = build_box(DVec3::zeros(), DVec3::new(1., 1., 1.));
+        // Sharing parts of four faces, and a whole face
+        assert_volumes(&a, &build_box(DVec3::new(0.5, 0., 0.), DVec3::new(1., 1., 1.)), [1.5, 0.5, 0.5], 1e-12);
+        assert_volumes(&a, &build_box(DVec3::new(1., 0., 0.), DVec3::new(1., 1., 1.)), [2., 0., 1.], 1e-12);
+        // Inside, touching faces of the box from the inside
+        assert_volumes(&a, &build_box(DVec3::new(0., 0.25, 0.), DVec3::new(0.5, 0.5, 1.)), [1., 0.25, 0.75], 1e-12);
+    }
+
+    #[test]
+    fn spheres() {
+        let a = read_sphere();
+        let b = translate(read_sphere(), DVec3::new(0.5, 0.3, 0.2));
+        let volume = a.get_mass_properties().volume;
+        let intersection = a.intersection(&b).unwrap().get_mass_properties().volume;
+        assert!(intersection > 0.0 && intersection < volume);
+        assert_volumes(&a, &b, [2. * volume - intersection, intersection, volume - intersection], 1e-9);
+
+        // Nested and separate spheres
+        let inner = SharedMesh { positions: a.positions.iter().map(|p| p * 0.5).collect(), ..read_sphere() };
+        let inner_volume = volume / 8.0;
+        assert!((inner.get_mass_properties().volume - inner_volume).abs() < 1e-9);
+        assert_volumes(&a, &inner, [volume, inner_volume, volume - inner_volume], 1e-9);
+        let far = translate(read_sphere(), DVec3::new(3., 0., 0.));
+        assert_volumes(&a, &far, [2. * volume, 0., volume], 1e-9);
+    }
+
+    #[test]
+    fn open_input() {
+        let a = build_box(DVec3::zeros(), DVec3::new(1., 1., 1.));
+        let mut b = build_box(DVec3::new(0.5, 0.5, 0.5), DVec3::new(1., 1., 1.));
+        b.triangles.pop();
+        assert_eq!(a.union(&b).unwrap_err(), BooleanError::NotClosed);
+    }
+}
@@ -1,44 +1,40 @@
 impl From<&SharedMesh> for ConnectedMesh {
     fn from(shared_mesh: &SharedMesh) -> Self {
-        let triangles = &shared_mesh.triangles;
-        let mut nodes = vec![Node::default(); triangles.len() * 3];
+        let corners: Vec<[[u32; 4]; 3]> = shared_mesh.triangles.iter()
+            .map(|t| [[t[0]; 4], [t[1]; 4], [t[2]; 4]])
+            .collect();
+        ConnectedMesh::from_corners(
+            shared_mesh.positions.clone(),
+            shared_mesh.normals.clone(),
+            shared_mesh.colors.clone(),
+            shared_mesh.uvs.clone(),
+            &corners)
+    }
+}
+
+impl ConnectedMesh {
+    // Builds a connected mesh from faces given as [position, normal, color, uv] corners
+    fn from_corners(positions: Vec<DVec3>, normals: Option<Vec<DVec3>>, colors: Option<Vec<DVec3>>, uvs: Option<Vec<DVec2>>, corners: &[[[u32; 4]; 3]]) -> Self {
+        // Attributes that don't cover every corner, such as the empty ones of a default SharedMesh, are dropped
+        let covers_corners = |len: usize, x: usize| corners.iter().flatten().all(|corner| (corner[x] as usize) < len);
+        let normals = normals.filter(|normals| covers_corners(normals.len(), 1));
+        let colors = colors.filter(|colors| covers_corners(colors.len(), 2));
+        let uvs = uvs.filter(|uvs| covers_corners(uvs.len(), 3));
+        let mut nodes = vec![Node::default(); corners.len() * 3];
         let mut vertex_to_nodes = HashMap::<u32, Vec<u32>, _>::with_hasher(
             BuildHasherDefault::<SimpleHasher>::default()
         );
-        let mut face_count = 0;
-        for i in 0..triangles.len() {
-            let triangle = triangles[i];
-            {
-                let mut a = &mut nodes[i];
-                a.position = triangle[0];
-                a.normal = triangle[0];
-                a.relative = (i as u32) + 1; // B
-                if !vertex_to_nodes.contains_key(&a.position) {
-                    vertex_to_nodes.insert(a.position, Vec::new());
-                }
-                vertex_to_nodes.get_mut(&a.position).unwrap().push(i as u32);
-            }
-            {
-                let mut b = &mut nodes[i + 1];
-                b.position = triangle[1];
-                b.normal = triangle[1];
-                b.relative = (i as u32) + 2; // C
-                if !vertex_to_nodes.contains_key(&b.position) {
-                    vertex_to_nodes.insert(b.position, Vec::new());
-                }  
-                vertex_to_nodes.get_mut(&b.position).unwrap().push((i as u32) + 1);
+        for (i, face) in corners.iter().enumerate() {
+            let i = i * 3;
+            for x in 0..3 {
+                let node = &mut nodes[i + x];
+                node.position = face[x][0];
+                node.normal = face[x][1];
+                node.color = face[x][2];
+                node.uv = face[x][3];
+                node.relative = (i + (x + 1) % 3) as u32; // A -> B -> C -> A
+                vertex_to_nodes.entry(node.position).or_insert_with(Vec::new).push((i + x) as u32);
             }
-            {
-                let mut c = &mut nodes[i + 2];
-                c.position = triangle[2];
-                c.normal = triangle[2];
-                c.relative = i as u32; // A
-                if !vertex_to_nodes.contains_key(&c.position) {
-                    vertex_to_nodes.insert(c.position, Vec::new());
-                }
-                vertex_to_nodes.get_mut(&c.position).unwrap().push((i as u32) + 2);
-            }
-            face_count = face_count + 1;
         }
 
         for x in vertex_to_nodes.values() {
@@ -56,11 +52,14 @@ impl From<&SharedMesh> for ConnectedMesh {
             nodes[first_sibling as usize].sibling = previous_sibling;
         }
 
-        return ConnectedMesh { 
-            positions: shared_mesh.positions.clone(),
-            normals: shared_mesh.normals.clone(),
-            nodes: nodes,
-            face_count: face_count };
+        ConnectedMesh {
+            positions,
+            normals,
+            colors,
+            uvs,
+            nodes,
+            face_count: corners.len() as u32,
+        }
     }
 }
 
@@ -73,7 +72,7 @@ impl Into<ConnectedMesh> for SharedMesh {
 impl From<&ConnectedMesh> for SharedMesh {
     fn from(connected_mesh: &ConnectedMesh) -> Self {
 
-        let mut per_vertex_map = HashMap::<[u32; 2], u32>::new();
+        let mut per_vertex_map = HashMap::<[u32; 4], u32>::new();
         let mut browsed_nodes = HashSet::new();
         let mut triangles = Vec::<U32Vec3>::with_capacity((connected_mesh.face_count / 3) as usize);
 
@@ -90,7 +89,8 @@ impl From<&ConnectedMesh> for SharedMesh {
 
             let mut x = 0;
             loop_relatives!(i as u32, connected_mesh.nodes, relative, {
-                let key = [connected_mesh.nodes[relative as usize].position, connected_mesh.nodes[relative as usize].normal];
+                // Vertices are only split by the attributes the mesh has
+                let key = connected_mesh.get_corner(relative);
                 if !per_vertex_map.contains_key(&key) {
                     per_vertex_map.insert(key, per_vertex_map.len() as u32);
                 }
@@ -122,12 +122,29 @@ impl From<&ConnectedMesh> for SharedMesh {
             None => None,
         };
 
+        let colors = connected_mesh.colors.as_ref().map(|cm_colors| {
+            let mut scolors = vec![DVec3::default(); per_vertex_map.len()];
+            for (key, value) in &per_vertex_map {
+                scolors[*value as usize] = cm_colors[key[2] as usize];
+            }
+            scolors
+        });
+
+        let uvs = connected_mesh.uvs.as_ref().map(|cm_uvs| {
+            let mut suvs = vec![DVec2::default(); per_vertex_map.len()];
+            for (key, value) in &per_vertex_map {
+                suvs[*value as usize] = cm_uvs[key[3] as usize];
+            }
+            suvs
+        });
+
         return SharedMesh {
             groups: Vec::new(),
             triangles: triangles,
             positions: positions,
             normals: normals,
-            colors: None,
+            colors,
+            uvs,
         };
     }
 }
@@ -143,11 +160,34 @@ mod builder_tests {
 
     use crate::base::*;
     use crate::mesh::*;
+    use crate::mesh::test_utils::read_sphere;
+
+    #[test]
+    fn default_shared_mesh_round_trip() {
+        // Tetrahedron built like triangulate does, leaving the default empty uvs
+        let mut shared_mesh = SharedMesh::default();
+        for (i, position) in [[0., 0., 0.], [1., 0., 0.], [0., 1., 0.], [0., 0., 1.]].iter().enumerate() {
+            shared_mesh.positions.push(DVec3::new(position[0], position[1], position[2]));
+            shared_mesh.normals.as_mut().unwrap().push(DVec3::new(position[0], position[1], position[2]));
+            shared_mesh.colors.as_mut().unwrap().push(DVec3::repeat(i as f64));
+        }
+        shared_mesh.triangles = vec![U32Vec3::new(0, 2, 1), U32Vec3::new(0, 1, 3), U32Vec3::new(0, 3, 2), U32Vec3::new(1, 2, 3)];
+        assert_eq!(SharedMesh::default().uvs, None);
+
+        shared_mesh.uvs = Some(Vec::new());
+        let round_trip = SharedMesh::from(&ConnectedMesh::from(&shared_mesh));
+        assert_eq!(round_trip.triangles.len(), 4);
+        assert_eq!(round_trip.positions.len(), 4);
+        assert_eq!(round_trip.uvs, None);
+        assert_eq!(round_trip.colors.unwrap().len(), 4);
+        assert_eq!(round_trip.normals.unwrap().len(), 4);
+
+        let lods = ConnectedMesh::from(&shared_mesh).generate_lods_shared(&[1.0]);
+        assert_eq!(lods.triangles.len(), 4);
+    }
 
     #[test]
     fn shared_mesh_to_connected_mesh() {
-        
-        return; // todo: Fix this 
 
         let mut positions = Vec::new();
         // Build a square
@@ -166,6 +206,7 @@ mod builder_tests {
             groups: Vec::new(),
             triangles: triangles,
             colors: None,
+            uvs: None,
             positions: positions,
             normals: None,
         };
@@ -197,6 +238,35 @@ mod builder_tests {
         }
     }
 
+    #[test]
+    fn sphere_to_connected_mesh() {
+
+        let shared_mesh = read_sphere();
+        let connected_mesh = ConnectedMesh::from(&shared_mesh);
+
+        assert_eq!(connected_mesh.face_count, 1280);
+        assert_eq!(connected_mesh.nodes.len(), 3840);
+
+        // Every triangle is a loop of 3 relatives, and every vertex of the closed sphere has 5 or 6 siblings
+        for i in 0..connected_mesh.nodes.len() as u32 {
+            let mut relatives = 0;
+            loop_relatives!(i, connected_mesh.nodes, relative, {
+                relatives += 1;
+            });
+            assert_eq!(relatives, 3);
+            let mut siblings = 0;
+            loop_siblings!(i, connected_mesh.nodes, sibling, {
+                siblings += 1;
+            });
+            assert!(siblings == 5 || siblings == 6);
+        }
+
+        let round_trip = SharedMesh::from(&connected_mesh);
+
+        assert_eq!(round_trip.triangles.len(), 1280);
+        assert_eq!(round_trip.positions.len(), 642);
+    }
+
     #[test]
     fn connected_mesh_to_shared_mesh() {
 
@@ -218,6 +288,8 @@ mod builder_tests {
         let connected_mesh = ConnectedMesh {
             positions: positions,
             normals: None,
+            colors: None,
+            uvs: None,
             nodes: nodes,
             face_count: 2,
         };
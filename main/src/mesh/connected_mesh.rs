@@ -9,8 +9,8 @@ pub struct ConnectedMesh {
 
     positions: Vec<DVec3>,
     normals: Option<Vec<DVec3>>,
-    // uv0: Vec<Vector3>,
-    // colors: Vec<Vector3>,
+    colors: Option<Vec<DVec3>>,
+    uvs: Option<Vec<DVec2>>,
 }
 
 impl Default for ConnectedMesh {
@@ -18,6 +18,8 @@ impl Default for ConnectedMesh {
         ConnectedMesh { 
             positions: Vec::new(),
             normals: None,
+            colors: None,
+            uvs: None,
             nodes: Vec::new(),
             face_count: 0
         }
@@ -52,6 +54,61 @@ macro_rules! loop_siblings {
 
 impl ConnectedMesh {
 
+    pub fn face_count(&self) -> u32 {
+        self.face_count
+    }
+
+    // A face is represented by the smallest index among its nodes
+    fn is_first_node_of_face(&self, node_index: u32) -> bool {
+        let node = &self.nodes[node_index as usize];
+        !node.is_removed && node_index < node.relative && node_index < self.nodes[node.relative as usize].relative
+    }
+
+    // Returns the [position, normal, color, uv] indices of a node, with absent attributes set to 0
+    fn get_corner(&self, node_index: u32) -> [u32; 4] {
+        let node = &self.nodes[node_index as usize];
+        [
+            node.position,
+            if self.normals.is_some() { node.normal } else { 0 },
+            if self.colors.is_some() { node.color } else { 0 },
+            if self.uvs.is_some() { node.uv } else { 0 },
+        ]
+    }
+
+    // Returns the node opposite to the edge going from the given node to its relative,
+    // or None if the edge is a border or is shared by more than two faces
+    fn get_twin(&self, node_index: u32) -> Option<u32> {
+        let pos_a = self.nodes[node_index as usize].position;
+        let node_index_b = self.nodes[node_index as usize].relative;
+        let mut twin = None;
+        let mut twin_count = 0;
+        loop_siblings!(node_index_b, self.nodes, sibling_of_b, {
+            let sibling = &self.nodes[sibling_of_b as usize];
+            if sibling_of_b != node_index_b && !sibling.is_removed && self.nodes[sibling.relative as usize].position == pos_a {
+                twin = Some(sibling_of_b);
+                twin_count += 1;
+            }
+        });
+        if twin_count == 1 { twin } else { None }
+    }
+
+    // An edge (from the given node to its relative) is a feature edge if it is a border, or if normals are split along it
+    fn is_feature_edge(&self, node_index: u32) -> bool {
+        let twin = match self.get_twin(node_index) {
+            Some(twin) => twin,
+            None => return true,
+        };
+        match &self.normals {
+            Some(normals) => {
+                let node = &self.nodes[node_index as usize];
+                let twin_node = &self.nodes[twin as usize];
+                normals[node.normal as usize] != normals[self.nodes[twin_node.relative as usize].normal as usize]
+                    || normals[self.nodes[node.relative as usize].normal as usize] != normals[twin_node.normal as usize]
+            },
+            None => false,
+        }
+    }
+
     fn check_siblings(&self, node_index: u32) -> bool {
         let mut i = 0;
         loop_siblings!(node_index, self.nodes, sibling, {
@@ -292,6 +349,10 @@ impl ConnectedMesh {
 }
 
 include!("decimate/decimate.rs");
+include!("subdivision.rs");
+include!("remesh.rs");
+include!("smooth.rs");
+include!("holes.rs");
 
 #[derive(Debug, Copy, Clone)]
 pub struct Node {
@@ -300,21 +361,21 @@ pub struct Node {
 
     position: u32,
     normal: u32,
-    // uv0: u32,
-    // color: u32,
+    color: u32,
+    uv: u32,
 
     is_removed: bool,
 }
 
 impl Node {
     fn from_layout(position: u32, sibling: u32, relative: u32) -> Self {
-        Node { position: position, sibling: sibling, relative: relative,  normal: 0, is_removed: false }
+        Node { position, sibling, relative,  normal: 0, color: 0, uv: 0, is_removed: false }
     }
 }
 
 impl Default for Node {
     fn default() -> Self {
-        Node { position: 0, sibling: 0, relative: 0,  normal: 0, is_removed: false }
+        Node { position: 0, sibling: 0, relative: 0,  normal: 0, color: 0, uv: 0, is_removed: false }
     }
 }
 
@@ -377,6 +438,8 @@ mod connected_mesh_tests {
             positions: positions,
             nodes: nodes,
             normals: None,
+            colors: None,
+            uvs: None,
             face_count: 6 };
 
         // Verify connectivity
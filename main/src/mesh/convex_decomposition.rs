@@ -0,0 +1,260 @@
+use nalgebra_glm as glm;
+use glm::DVec3;
+use super::SharedMesh;
+use crate::base::{Box3, Plane};
+
+// Hierarchical decomposition in the style of V-HACD: the most concave part is cut in two by the axis aligned plane that
+// leaves the least concave halves, until every part is convex enough or the hull budget is spent. Cuts are capped so that
+// parts stay closed and their volumes stay meaningful. There is no randomness, so results only depend on the input.
+
+/// Candidate cutting planes along each axis, evenly spaced across the part
+const PLANES_PER_AXIS: usize = 15;
+
+/// Parts only merge with their closest parts, by distance between bounding boxes
+const MERGE_NEIGHBORS: usize = 8;
+
+impl SharedMesh {
+    /// Approximate convex decomposition of a closed mesh, as a list of convex hulls.
+    /// Concavity is the volume a hull adds to its part, relative to the volume of the hull of the whole mesh:
+    /// parts are split until all of them are below `max_concavity` or there are `max_hulls` of them.
+    /// Separate components are decomposed separately, and merged with their closest components when there are more than `max_hulls`.
+    pub fn get_convex_decomposition(&self, max_concavity: f64, max_hulls: usize) -> Vec<SharedMesh> {
+        let max_hulls = max_hulls.max(1);
+        let geometry = SharedMesh { groups: Vec::new(), triangles: self.triangles.clone(), positions: self.positions.clone(), normals: None, colors: None, uvs: None };
+        let total_volume = get_hull_volume(&geometry).max(f64::MIN_POSITIVE);
+        let mut parts: Vec<Part> = geometry.split_components().into_iter().map(Part::new).collect();
+
+        merge_parts(&mut parts, f64::NEG_INFINITY, max_hulls);
+
+        while parts.len() < max_hulls {
+            let most_concave = parts.iter().enumerate()
+                .filter(|(_, part)| part.get_concavity() / total_volume > max_concavity)
+                .max_by(|(_, a), (_, b)| a.get_concavity().total_cmp(&b.get_concavity()))
+                .map(|(i, _)| i);
+            let i = match most_concave {
+                Some(i) => i,
+                None => break,
+            };
+            match split_part(&parts[i]) {
+                Some(halves) => {
+                    parts.remove(i);
+                    parts.extend(halves);
+                },
+                // Parts that can't be cut aren't considered again
+                None => parts[i].hull_volume = parts[i].volume,
+            }
+        }
+
+        // Cuts only happen at a few positions, so neighboring parts often make a convex enough part together.
+        // Cut halves may also have several components, which can go over the budget.
+        merge_parts(&mut parts, max_concavity * total_volume, max_hulls);
+
+        parts.iter().map(|part| get_hull(&part.mesh)).collect()
+    }
+}
+
+struct Part {
+    mesh: SharedMesh,
+    volume: f64,
+    hull_volume: f64,
+    bounds: Box3,
+}
+
+impl Part {
+    fn new(mesh: SharedMesh) -> Self {
+        let volume = mesh.get_mass_properties().volume;
+        let hull_volume = get_hull_volume(&mesh);
+        let bounds = get_bounds(&mesh);
+        Part { mesh, volume, hull_volume, bounds }
+    }
+
+    fn get_concavity(&self) -> f64 {
+        (self.hull_volume - self.volume).max(0.0)
+    }
+}
+
+// Hull of the vertices used by triangles, as cuts leave the others behind
+fn get_hull(mesh: &SharedMesh) -> SharedMesh {
+    let mut is_used = vec![false; mesh.positions.len()];
+    for triangle in mesh.triangles.iter() {
+        for x in 0..3 {
+            is_used[triangle[x] as usize] = true;
+        }
+    }
+    let points: Vec<DVec3> = mesh.positions.iter().zip(is_used.iter()).filter(|(_, is_used)| **is_used).map(|(p, _)| *p).collect();
+    SharedMesh::from_convex_hull(&points, None)
+}
+
+fn get_hull_volume(mesh: &SharedMesh) -> f64 {
+    get_hull(mesh).get_mass_properties().volume
+}
+
+// Bounds of the vertices used by triangles
+fn get_bounds(mesh: &SharedMesh) -> Box3 {
+    let mut bounds = Box3::unfitted();
+    for triangle in mesh.triangles.iter() {
+        for x in 0..3 {
+            bounds.expand(&mesh.positions[triangle[x] as usize]);
+        }
+    }
+    bounds
+}
+
+// Best cut of a part, as the components of both halves
+fn split_part(part: &Part) -> Option<Vec<Part>> {
+    let bounds = part.bounds;
+    let size = bounds.size();
+
+    let mut best: Option<(f64, Vec<Part>)> = None;
+    for axis in 0..3 {
+        for i in 1..=PLANES_PER_AXIS {
+            let mut origin = bounds.min;
+            origin[axis] += size[axis] * i as f64 / (PLANES_PER_AXIS + 1) as f64;
+            let plane = Plane::new(origin, DVec3::ith(axis, 1.0));
+            let [below, above] = part.mesh.split_by_plane(&plane, true);
+            if below.triangles.is_empty() || above.triangles.is_empty() {
+                continue;
+            }
+            let halves: Vec<Part> = below.split_components().into_iter().chain(above.split_components()).map(Part::new).collect();
+            let concavity = halves.iter().map(Part::get_concavity).sum::<f64>();
+            if best.as_ref().is_none_or(|(best_concavity, _)| concavity < *best_concavity) {
+                best = Some((concavity, halves));
+            }
+        }
+    }
+    best.map(|(_, halves)| halves)
+}
+
+// Merges pairs of neighboring parts, the pair making the least concave part first, as long as that concavity is below
+// `max_concavity` or there are more than `max_parts` parts. The concavity of every candidate pair is computed once, and
+// after a merge only the pairs of the new part are, so each merge costs a few hulls instead of one per pair of parts.
+fn merge_parts(parts: &mut Vec<Part>, max_concavity: f64, max_parts: usize) {
+    let mut slots: Vec<Option<Part>> = parts.drain(..).map(Some).collect();
+    let mut part_count = slots.len();
+    let mut pairs: Vec<(usize, usize)> = (0..slots.len())
+        .flat_map(|i| get_neighbors(&slots, i).into_iter().map(move |j| (i.min(j), i.max(j))))
+        .collect();
+    pairs.sort_unstable();
+    pairs.dedup();
+    // Concavity of the part made by merging the parts of every candidate pair, with the lowest index first
+    let mut candidates: Vec<(f64, usize, usize)> = pairs.into_iter()
+        .map(|(i, j)| (get_merged_concavity(&slots, i, j), i, j))
+        .collect();
+
+    while part_count > 1 {
+        let best = candidates.iter()
+            .min_by(|a, b| a.0.total_cmp(&b.0).then_with(|| (a.1, a.2).cmp(&(b.1, b.2))))
+            .copied();
+        let (i, j) = match best {
+            Some((concavity, i, j)) if concavity <= max_concavity || part_count > max_parts => (i, j),
+            _ => break,
+        };
+        let b = slots[j].take().unwrap();
+        let a = slots[i].take().unwrap();
+        slots[i] = Some(Part::new(SharedMesh::combine(a.mesh, b.mesh)));
+        part_count -= 1;
+
+        candidates.retain(|(_, a, b)| ![i, j].contains(a) && ![i, j].contains(b));
+        for k in get_neighbors(&slots, i) {
+            candidates.push((get_merged_concavity(&slots, i, k), i.min(k), i.max(k)));
+        }
+    }
+
+    parts.extend(slots.into_iter().flatten());
+}
+
+// Closest remaining parts to a part, by distance between bounding boxes
+fn get_neighbors(slots: &[Option<Part>], i: usize) -> Vec<usize> {
+    let bounds = &slots[i].as_ref().unwrap().bounds;
+    let mut neighbors: Vec<(f64, usize)> = slots.iter().enumerate()
+        .filter(|(j, _)| *j != i)
+        .filter_map(|(j, slot)| slot.as_ref().map(|part| (get_gap(bounds, &part.bounds), j)))
+        .collect();
+    neighbors.sort_by(|a, b| a.0.total_cmp(&b.0).then_with(|| a.1.cmp(&b.1)));
+    neighbors.truncate(MERGE_NEIGHBORS);
+    neighbors.into_iter().map(|(_, j)| j).collect()
+}
+
+// Squared distance between two boxes, zero when they overlap
+fn get_gap(a: &Box3, b: &Box3) -> f64 {
+    (a.min - b.max).sup(&(b.min - a.max)).sup(&DVec3::zeros()).magnitude_squared()
+}
+
+// Concavity of the part that two parts make together
+fn get_merged_concavity(slots: &[Option<Part>], i: usize, j: usize) -> f64 {
+    let (a, b) = (slots[i].as_ref().unwrap(), slots[j].as_ref().unwrap());
+    let merged = SharedMesh::combine(a.mesh.clone(), b.mesh.clone());
+    get_hull_volume(&merged) - a.volume - b.volume
+}
+
+#[cfg(test)]
+mod convex_decomposition_tests {
+    use crate::mesh::*;
+    use crate::mesh::test_utils::{build_box, read_sphere};
+
+    fn get_total_volume(hulls: &[SharedMesh]) -> f64 {
+        hulls.iter().map(|hull| hull.get_mass_properties().volume).sum()
+    }
+
+    fn build_l_shape() -> SharedMesh {
+        build_box(DVec3::zeros(), DVec3::new(2., 1., 1.)).union(&build_box(DVec3::zeros(), DVec3::new(1., 2., 1.))).unwrap()
+    }
+
+    #[test]
+    fn decompose_l_shape() {
+        // The hull of the L shape is 3.5, for a volume of 3
+        let l_shape = build_l_shape();
+        let hulls = l_shape.get_convex_decomposition(0.5, 8);
+        assert_eq!(hulls.len(), 1);
+        assert!((get_total_volume(&hulls) - 3.5).abs() < 1e-9);
+
+        let hulls = l_shape.get_convex_decomposition(0.01, 8);
+        let volume = get_total_volume(&hulls);
+        assert!(hulls.len() > 1);
+        assert!(volume > 3.0 - 1e-9 && volume < 3.0 + 0.01 * 3.5 * hulls.len() as f64);
+        assert!(hulls.iter().all(|hull| hull.analyze().is_watertight));
+
+        let hulls = l_shape.get_convex_decomposition(0.01, 2);
+        assert_eq!(hulls.len(), 2);
+        assert!(get_total_volume(&hulls) < 3.1);
+
+        // Already convex
+        let sphere = read_sphere();
+        assert_eq!(sphere.get_convex_decomposition(0.01, 8).len(), 1);
+    }
+
+    #[test]
+    fn hull_budget_and_components() {
+        // Two L shapes on top of each other
+        let l_shape = build_l_shape();
+        let other = SharedMesh { positions: l_shape.positions.iter().map(|p| p + DVec3::new(0., 0., 3.)).collect(), ..l_shape.clone() };
+        let both = SharedMesh::combine(l_shape, other);
+
+        let hulls = both.get_convex_decomposition(0.01, 8);
+        assert_eq!(hulls.len(), 4);
+        assert!(hulls.iter().all(|hull| hull.positions.iter().all(|p| p.z <= 1.0) || hull.positions.iter().all(|p| p.z >= 3.0)));
+
+        assert_eq!(both.get_convex_decomposition(0.01, 3).len(), 3);
+        let hulls = both.get_convex_decomposition(0.01, 1);
+        assert_eq!(hulls.len(), 1);
+        assert!((get_total_volume(&hulls) - 3.5 * 4.0).abs() < 1e-9);
+
+        // Deterministic
+        let again = both.get_convex_decomposition(0.01, 3);
+        assert!(both.get_convex_decomposition(0.01, 3).iter().zip(again.iter()).all(|(a, b)| a.positions == b.positions && a.triangles == b.triangles));
+    }
+
+    #[test]
+    fn many_components() {
+        // Grid of 10 x 10 separate cubes, which only merge with their neighbors
+        let cubes = (0..100)
+            .map(|i| build_box(DVec3::new((i % 10) as f64 * 2., (i / 10) as f64 * 2., 0.), DVec3::new(1., 1., 1.)))
+            .reduce(SharedMesh::combine)
+            .unwrap();
+
+        let hulls = cubes.get_convex_decomposition(0.01, 8);
+        assert_eq!(hulls.len(), 8);
+        let volume = get_total_volume(&hulls);
+        assert!(volume > 100.0 - 1e-9 && volume < 19.0 * 19.0);
+    }
+}
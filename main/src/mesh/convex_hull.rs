@@ -0,0 +1,296 @@
+use nalgebra_glm as glm;
+use glm::{DVec2, DVec3, U32Vec3};
+use hashbrown::HashMap;
+use super::SharedMesh;
+use crate::spatial::predicates::*;
+
+// Quickhull (Barber, Dobkin and Huhdanpaa). Hull vertices are input points, so which faces a point sees is decided exactly
+// with orient3d, which keeps faces consistent around the horizon even with coplanar points. Distances in floating point are
+// only used to choose which point comes next.
+
+impl SharedMesh {
+    /// Convex hull of a set of points, with outward triangles. Points on the faces of the hull aren't kept.
+    /// Points are added farthest first, so with a maximum vertex count (at least 4) the hull is a simplification
+    /// that leaves the points it didn't reach outside of it.
+    /// Coplanar points give a flat hull with both sides, and fewer than three non-collinear points an empty mesh.
+    pub fn from_convex_hull(points: &[DVec3], max_vertices: Option<usize>) -> SharedMesh {
+        let triangles = get_convex_hull(points, max_vertices.map_or(usize::MAX, |max| max.max(4)));
+
+        // Only keeps hull vertices
+        let mut new_indices = vec![u32::MAX; points.len()];
+        let mut positions = Vec::new();
+        let triangles = triangles.iter().map(|triangle| triangle.map(|v| {
+            if new_indices[v as usize] == u32::MAX {
+                new_indices[v as usize] = positions.len() as u32;
+                positions.push(points[v as usize]);
+            }
+            new_indices[v as usize]
+        })).collect();
+        SharedMesh { groups: Vec::new(), triangles, positions, normals: None, colors: None, uvs: None }
+    }
+
+    /// Convex hull of the positions of the mesh. See `from_convex_hull`.
+    pub fn get_convex_hull(&self, max_vertices: Option<usize>) -> SharedMesh {
+        SharedMesh::from_convex_hull(&self.positions, max_vertices)
+    }
+
+    /// Convex hull of every connected component, as collision proxies for objects made of separate parts.
+    /// Hulls are in the order of `split_components`.
+    pub fn get_component_convex_hulls(&self, max_vertices: Option<usize>) -> Vec<SharedMesh> {
+        self.split_components().iter().map(|component| component.get_convex_hull(max_vertices)).collect()
+    }
+}
+
+struct Face {
+    vertices: U32Vec3,
+    /// Points seen by the face, that no earlier face sees
+    outside: Vec<u32>,
+    /// Farthest point of `outside` and its distance to the plane
+    farthest: Option<(u32, f64)>,
+    is_alive: bool,
+}
+
+struct Quickhull<'a> {
+    points: &'a [DVec3],
+    faces: Vec<Face>,
+    /// Face walking every directed edge
+    edges: HashMap<(u32, u32), usize>,
+}
+
+impl Quickhull<'_> {
+    fn sees(&self, face: usize, point: u32) -> bool {
+        let [a, b, c] = [0, 1, 2].map(|x| self.points[self.faces[face].vertices[x] as usize]);
+        orient3d(&a, &b, &c, &self.points[point as usize]) < 0.0
+    }
+
+    fn add_face(&mut self, vertices: U32Vec3, candidates: &mut Vec<u32>) {
+        let face = self.faces.len();
+        self.faces.push(Face { vertices, outside: Vec::new(), farthest: None, is_alive: true });
+        for x in 0..3 {
+            self.edges.insert((vertices[x], vertices[(x + 1) % 3]), face);
+        }
+
+        let [a, b, c] = [0, 1, 2].map(|x| self.points[vertices[x] as usize]);
+        let normal = (b - a).cross(&(c - a)).normalize();
+        let mut remaining = Vec::with_capacity(candidates.len());
+        for &point in candidates.iter() {
+            if !self.sees(face, point) {
+                remaining.push(point);
+                continue;
+            }
+            let distance = normal.dot(&(self.points[point as usize] - a));
+            let face = &mut self.faces[face];
+            face.outside.push(point);
+            if face.farthest.is_none_or(|(_, farthest)| distance > farthest) {
+                face.farthest = Some((point, distance));
+            }
+        }
+        *candidates = remaining;
+    }
+
+    // Replaces the faces seen from the point by a cone from the point to their horizon
+    fn add_point(&mut self, face: usize, point: u32) {
+        let mut visible = vec![face];
+        let mut is_visible: HashMap<usize, bool> = HashMap::new();
+        is_visible.insert(face, true);
+        let mut horizon = Vec::new();
+        let mut i = 0;
+        while i < visible.len() {
+            let vertices = self.faces[visible[i]].vertices;
+            for x in 0..3 {
+                let (a, b) = (vertices[x], vertices[(x + 1) % 3]);
+                let neighbor = self.edges[&(b, a)];
+                let sees = *is_visible.entry(neighbor).or_insert_with(|| self.sees(neighbor, point));
+                if !sees {
+                    horizon.push((a, b));
+                } else if !visible.contains(&neighbor) {
+                    visible.push(neighbor);
+                }
+            }
+            i += 1;
+        }
+
+        let mut candidates = Vec::new();
+        for &face in visible.iter() {
+            let face = &mut self.faces[face];
+            face.is_alive = false;
+            candidates.append(&mut face.outside);
+            for x in 0..3 {
+                self.edges.remove(&(face.vertices[x], face.vertices[(x + 1) % 3]));
+            }
+        }
+        candidates.retain(|&candidate| candidate != point);
+        for (a, b) in horizon {
+            self.add_face(U32Vec3::new(a, b, point), &mut candidates);
+        }
+    }
+}
+
+fn get_convex_hull(points: &[DVec3], max_vertices: usize) -> Vec<U32Vec3> {
+    // Starts from a tetrahedron as large as possible: the farthest pair of extreme points, the point farthest from their
+    // line, then the point farthest from their plane
+    let mut extremes = Vec::new();
+    for axis in 0..3 {
+        let by_axis = |a: &usize, b: &usize| points[*a][axis].total_cmp(&points[*b][axis]);
+        extremes.extend((0..points.len()).min_by(by_axis));
+        extremes.extend((0..points.len()).max_by(by_axis));
+    }
+    let farthest_pair = extremes.iter().flat_map(|&a| extremes.iter().map(move |&b| (a, b)))
+        .max_by(|x, y| points[x.0].metric_distance(&points[x.1]).total_cmp(&points[y.0].metric_distance(&points[y.1])));
+    let (a, b) = match farthest_pair {
+        Some((a, b)) if points[a] != points[b] => (a, b),
+        _ => return Vec::new(),
+    };
+    let direction = (points[b] - points[a]).normalize();
+    let is_collinear = |c: usize| (0..3).all(|axis| orient2d(&project(&points[a], axis), &project(&points[b], axis), &project(&points[c], axis)) == 0.0);
+    let third = (0..points.len()).filter(|&c| !is_collinear(c)).max_by(|&x, &y| {
+        let distance = |c: usize| (points[c] - points[a]).cross(&direction).magnitude();
+        distance(x).total_cmp(&distance(y))
+    });
+    let c = match third {
+        Some(c) => c,
+        None => return Vec::new(),
+    };
+    let fourth = (0..points.len()).map(|d| (d, orient3d(&points[a], &points[b], &points[c], &points[d])))
+        .filter(|(_, orientation)| *orientation != 0.0)
+        .max_by(|x, y| x.1.abs().total_cmp(&y.1.abs()));
+    let (d, orientation) = match fourth {
+        Some(fourth) => fourth,
+        None => return get_flat_hull(points, [a, b, c]),
+    };
+
+    // abc is clockwise seen from d when it is below, so it faces away from d
+    let (a, b, c, d) = if orientation > 0.0 { (a, b, c, d) } else { (a, c, b, d) };
+    let [a, b, c, d] = [a, b, c, d].map(|v| v as u32);
+    let mut hull = Quickhull { points, faces: Vec::new(), edges: HashMap::new() };
+    let mut candidates: Vec<u32> = (0..points.len() as u32).filter(|&v| v != a && v != b && v != c && v != d).collect();
+    for vertices in [[a, b, c], [a, d, b], [b, d, c], [c, d, a]] {
+        hull.add_face(U32Vec3::from(vertices), &mut candidates);
+    }
+
+    // Adds the farthest point from the hull until none is outside, or the hull has enough vertices
+    let mut vertex_count = 4;
+    while vertex_count < max_vertices {
+        let farthest = hull.faces.iter().enumerate()
+            .filter(|(_, face)| face.is_alive)
+            .filter_map(|(f, face)| face.farthest.map(|(point, distance)| (f, point, distance)))
+            .max_by(|x, y| x.2.total_cmp(&y.2));
+        match farthest {
+            Some((face, point, _)) => hull.add_point(face, point),
+            None => break,
+        }
+        vertex_count += 1;
+    }
+
+    hull.faces.iter().filter(|face| face.is_alive).map(|face| face.vertices).collect()
+}
+
+// Convex polygon of coplanar points (Andrew's monotone chain), closed with a fan on each side. The fans start from
+// different vertices, so that no diagonal is shared by the two sides.
+fn get_flat_hull(points: &[DVec3], [a, b, c]: [usize; 3]) -> Vec<U32Vec3> {
+    let axis = get_dominant_axis(&points[a], &points[b], &points[c]);
+    let projected: Vec<DVec2> = points.iter().map(|p| project(p, axis)).collect();
+    let mut sorted: Vec<u32> = (0..points.len() as u32).collect();
+    sorted.sort_by(|&x, &y| {
+        let (p, q) = (projected[x as usize], projected[y as usize]);
+        p.x.total_cmp(&q.x).then(p.y.total_cmp(&q.y))
+    });
+    sorted.dedup_by(|x, y| projected[*x as usize] == projected[*y as usize]);
+
+    // Lower then upper chain, only keeping strict left turns
+    let mut polygon: Vec<u32> = Vec::new();
+    for pass in 0..2 {
+        let start = polygon.len();
+        let chain: Box<dyn Iterator<Item = &u32>> = if pass == 0 { Box::new(sorted.iter()) } else { Box::new(sorted.iter().rev()) };
+        for &v in chain {
+            while polygon.len() >= start + 2
+                && orient2d(&projected[polygon[polygon.len() - 2] as usize], &projected[polygon[polygon.len() - 1] as usize], &projected[v as usize]) <= 0.0 {
+                polygon.pop();
+            }
+            polygon.push(v);
+        }
+        // The last point of a chain starts the other one
+        polygon.pop();
+    }
+
+    let n = polygon.len();
+    let mut triangles = Vec::with_capacity(2 * n.saturating_sub(2));
+    for i in 1..n.saturating_sub(1) {
+        triangles.push(U32Vec3::new(polygon[0], polygon[i], polygon[i + 1]));
+        triangles.push(U32Vec3::new(polygon[1], polygon[(i + 2) % n], polygon[i + 1]));
+    }
+    triangles
+}
+
+#[cfg(test)]
+mod convex_hull_tests {
+    use crate::mesh::*;
+    use crate::mesh::test_utils::read_sphere;
+
+    fn build_grid(size: usize, is_flat: bool) -> Vec<DVec3> {
+        let depth = if is_flat { 1 } else { size };
+        let scale = 1.0 / (size - 1) as f64;
+        (0..size * size * depth).map(|i| DVec3::new((i % size) as f64, (i / size % size) as f64, (i / size / size) as f64) * scale).collect()
+    }
+
+    fn assert_closed(hull: &SharedMesh) {
+        let report = hull.analyze();
+        assert!(report.is_watertight && report.is_orientable && report.inconsistent_winding_count == 0, "{:?}", report);
+    }
+
+    #[test]
+    fn hull_of_sphere_and_grid() {
+        let sphere = read_sphere();
+        let hull = sphere.get_convex_hull(None);
+        assert_closed(&hull);
+        assert_eq!(hull.positions.len(), 642);
+        assert_eq!(hull.triangles.len(), 1280);
+        assert!((hull.get_mass_properties().volume - sphere.get_mass_properties().volume).abs() < 1e-9);
+
+        // Points on faces, edges and inside are left out
+        let hull = SharedMesh::from_convex_hull(&build_grid(5, false), None);
+        assert_closed(&hull);
+        assert_eq!(hull.positions.len(), 8);
+        assert!((hull.get_mass_properties().volume - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn vertex_limit() {
+        let sphere = read_sphere();
+        let volume = sphere.get_mass_properties().volume;
+        let mut previous = 0.0;
+        for max_vertices in [4, 12, 40, 200] {
+            let hull = sphere.get_convex_hull(Some(max_vertices));
+            assert_closed(&hull);
+            assert_eq!(hull.positions.len(), max_vertices);
+            assert!(hull.positions.iter().all(|p| sphere.positions.contains(p)));
+            let hull_volume = hull.get_mass_properties().volume;
+            assert!(hull_volume > previous && hull_volume < volume);
+            previous = hull_volume;
+        }
+        assert!(previous > 0.95 * volume);
+    }
+
+    #[test]
+    fn degenerate_inputs() {
+        // Flat square, with both sides
+        let hull = SharedMesh::from_convex_hull(&build_grid(4, true), None);
+        assert_closed(&hull);
+        assert_eq!(hull.positions.len(), 4);
+        assert_eq!(hull.triangles.len(), 4);
+        assert_eq!(hull.get_mass_properties().area, 2.0);
+
+        let line: Vec<DVec3> = (0..5).map(|i| DVec3::new(i as f64, 2.0 * i as f64, 0.)).collect();
+        assert!(SharedMesh::from_convex_hull(&line, None).triangles.is_empty());
+        assert!(SharedMesh::from_convex_hull(&[DVec3::new(1., 1., 1.); 3], None).triangles.is_empty());
+        assert!(SharedMesh::from_convex_hull(&[], None).triangles.is_empty());
+
+        // A hull per part
+        let a = SharedMesh::from_convex_hull(&build_grid(3, false), None);
+        let b = SharedMesh { positions: a.positions.iter().map(|p| p + DVec3::new(3., 0., 0.)).collect(), ..a.clone() };
+        let hulls = SharedMesh::combine(a, b).get_component_convex_hulls(Some(6));
+        assert_eq!(hulls.len(), 2);
+        assert!(hulls.iter().all(|hull| hull.positions.len() == 6));
+        assert!(hulls[1].positions.iter().all(|p| p.x >= 3.0));
+    }
+}
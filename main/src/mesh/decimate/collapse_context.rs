@@ -3,6 +3,16 @@ struct CollapseContext {
     collapse_to: DVec3,
     error: f64, // TODO: f32 ?
     weight: f64, // TODO: f32 ?
+    tie_breaker: u64,
+}
+
+impl CollapseContext {
+    fn new(edge: &Edge) -> Self {
+        Self {
+            tie_breaker: edge.get_key(),
+            ..Self::default()
+        }
+    }
 }
 
 impl Default for CollapseContext {
@@ -11,6 +21,7 @@ impl Default for CollapseContext {
             collapse_to: DVec3::default(),
             error: 0.,
             weight: 0.,
+            tie_breaker: 0,
         }
     }
 }
@@ -21,14 +32,12 @@ impl PartialOrd for CollapseContext {
     }
 }
 
+// Total order, so that the collapse sequence never depends on the internal layout of the queue.
+// Highest (least negative) error comes first, and on equal errors the edge with the smallest key comes first.
 impl Ord for CollapseContext {
     fn cmp(&self, other: &Self) -> Ordering {
-        if self.error > other.error {
-            return Ordering::Greater;
-        } else if self.error < other.error {
-            return Ordering::Less;
-        }
-        Ordering::Equal
+        self.error.total_cmp(&other.error)
+            .then_with(|| other.tie_breaker.cmp(&self.tie_breaker))
     }
 }
 
@@ -38,6 +47,6 @@ impl Eq for CollapseContext {
 
 impl PartialEq for CollapseContext {
     fn eq(&self, other: &Self) -> bool {
-        self.error == other.error
+        self.cmp(other) == Ordering::Equal
     }
-}
\ No newline at end of file
+}
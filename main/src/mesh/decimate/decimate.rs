@@ -7,13 +7,27 @@ use pool::Pool;
 
 include!("edge.rs");
 include!("collapse_context.rs");
+include!("lods.rs");
+include!("progressive.rs");
+include!("parallel.rs");
 
 impl ConnectedMesh {    
     pub fn decimate_to_ratio(&mut self, target_triangle_ratio: f32) {
         self.decimate((target_triangle_ratio * self.face_count as f32) as u32);
     }
 
+    /// Collapses edges by increasing quadric error until the face count drops to the given target.
+    /// The result is bit-identical for identical inputs, whatever the run or platform:
+    /// on equal errors, the edge whose (smallest position index, largest position index) pair is the lowest collapses first.
     pub fn decimate(&mut self, target_triangle_count: u32) {
+        self.decimate_in_steps(&[target_triangle_count], None, &mut None, |_, _| ());
+    }
+
+    // Runs a single collapse queue through each of the given triangle counts (expected in decreasing order),
+    // calling back with the index of the target every time one is reached.
+    // Edges touching a locked position (if any) are never collapsed.
+    // When a recorder is given, every collapse is recorded so that it can be undone later on.
+    fn decimate_in_steps<F: FnMut(&ConnectedMesh, usize)>(&mut self, target_triangle_counts: &[u32], locked_positions: Option<&[bool]>, recorder: &mut Option<&mut CollapseRecorder>, mut on_target_reached: F) {
 
         macro_rules! loop_edges {
             ($node_index:expr, $edge_buffer:expr,$nodes:expr, $relative:ident, $exec:expr) => {{
@@ -45,7 +59,7 @@ impl ConnectedMesh {
             }
             // TODO: Check if enough? Maybe there is a better loop for this purpose
             let edge = Edge::new(self.nodes[i].position, self.nodes[self.nodes[i].relative as usize].position);
-            queue.push(edge, CollapseContext::default());
+            queue.push(edge, CollapseContext::new(&edge));
             position_to_node.insert(self.nodes[i as usize].position, i as u32);
         }
 
@@ -78,60 +92,83 @@ impl ConnectedMesh {
         }
 
         // Iterate
-        while self.face_count > target_triangle_count {
+        for (target_index, target_triangle_count) in target_triangle_counts.iter().enumerate() {
+            while self.face_count > *target_triangle_count {
+
+                let pair_to_collapse = match queue.pop() {
+                    Some(pair_to_collapse) => pair_to_collapse,
+                    None => break // Nothing left to collapse
+                };
+                let edge_to_collapse = pair_to_collapse.0;
+                let collapse_context = pair_to_collapse.1;
+
+                match position_to_node.get(&edge_to_collapse.pos_a) {
+                    Some(_) => (),
+                    None => continue
+                };
+
+                match position_to_node.get(&edge_to_collapse.pos_b) {
+                    Some(_) => (),
+                    None => continue
+                };
+
+                if let Some(locked_positions) = locked_positions {
+                    if locked_positions[edge_to_collapse.pos_a as usize] || locked_positions[edge_to_collapse.pos_b as usize] {
+                        continue;
+                    }
+                }
+        
+                // Collapse edge
+                let node_index_a = *position_to_node.get(&edge_to_collapse.pos_a).unwrap();
+                let node_index_b = *position_to_node.get(&edge_to_collapse.pos_b).unwrap();
 
-            let pair_to_collapse = queue.pop().unwrap();
-            let edge_to_collapse = pair_to_collapse.0;
-            let collapse_context = pair_to_collapse.1;
+                if let Some(recorder) = recorder {
+                    recorder.record_collapse(self, node_index_a, node_index_b);
+                }
 
-            match position_to_node.get(&edge_to_collapse.pos_a) {
-                Some(_) => (),
-                None => continue
-            };
+                let valid_node_index_o = self.collapse_edge_to_a(node_index_a, node_index_b, &mut Some(&mut position_to_node));
 
-            match position_to_node.get(&edge_to_collapse.pos_b) {
-                Some(_) => (),
-                None => continue
-            };
-        
-            // Collapse edge
-            let valid_node_index_o = self.collapse_edge_to_a(*position_to_node.get(&edge_to_collapse.pos_a).unwrap(), *position_to_node.get(&edge_to_collapse.pos_b).unwrap(), &mut Some(&mut position_to_node));
+                if valid_node_index_o.is_none() {
+                    continue;
+                }
 
-            if valid_node_index_o.is_none() {
-                continue;
-            }
+                let valid_node_index = valid_node_index_o.unwrap();
 
-            let valid_node_index = valid_node_index_o.unwrap();
+                // Use optimal position
+                self.positions[self.nodes[valid_node_index as usize].position as usize] = collapse_context.collapse_to;
 
-            // Use optimal position
-            self.positions[self.nodes[valid_node_index as usize].position as usize] = collapse_context.collapse_to;
+                if let Some(recorder) = recorder {
+                    recorder.record_collapsed_position(collapse_context.collapse_to);
+                }
 
-            // Recalculate quadric at A
-            calculate_quadric(self, &mut quadrics, valid_node_index);
+                // Recalculate quadric at A
+                calculate_quadric(self, &mut quadrics, valid_node_index);
 
-            let node_a = self.nodes[valid_node_index as usize];
+                let node_a = self.nodes[valid_node_index as usize];
 
-            let mut positions = pool.checkout().unwrap();
+                let mut positions = pool.checkout().unwrap();
 
-            loop_edges!(valid_node_index, positions, self.nodes, relative, {
-                let node_c = self.nodes[relative as usize];
-                let edge = &Edge::new(node_a.position, node_c.position);
-                // Recompute quadric
-                calculate_quadric(self, &mut quadrics, node_c.sibling);
-                // Refresh edge in queue (new collapse target position)
-                let mut collapse_context = CollapseContext::default();
-                queue.push(*edge, collapse_context);
-                calculate_weight(self, &position_to_node, edge, &mut collapse_context);
-            });
+                loop_edges!(valid_node_index, positions, self.nodes, relative, {
+                    let node_c = self.nodes[relative as usize];
+                    let edge = &Edge::new(node_a.position, node_c.position);
+                    // Recompute quadric
+                    calculate_quadric(self, &mut quadrics, node_c.sibling);
+                    // Refresh edge in queue (new collapse target position)
+                    let mut collapse_context = CollapseContext::new(edge);
+                    queue.push(*edge, collapse_context);
+                    calculate_weight(self, &position_to_node, edge, &mut collapse_context);
+                });
 
-            for position in positions.iter() {
-                debug_assert!(node_a.position != *position);
-                let edge = &Edge::new(node_a.position, *position);
-                // Refresh edge in queue (new collapse target position)
-                let mut collapse_context = *queue.get(&edge).unwrap().1;
-                calculate_error(self, &mut quadrics, &queue, &position_to_node, &mut pool.checkout().unwrap(), edge, &mut collapse_context);
-                queue.change_priority(edge, collapse_context);
+                for position in positions.iter() {
+                    debug_assert!(node_a.position != *position);
+                    let edge = &Edge::new(node_a.position, *position);
+                    // Refresh edge in queue (new collapse target position)
+                    let mut collapse_context = *queue.get(&edge).unwrap().1;
+                    calculate_error(self, &mut quadrics, &queue, &position_to_node, &mut pool.checkout().unwrap(), edge, &mut collapse_context);
+                    queue.change_priority(edge, collapse_context);
+                }
             }
+            on_target_reached(self, target_index);
         }
 
         fn calculate_quadric(connected_mesh: &mut ConnectedMesh, quadrics: &mut Vec<SymmetricMatrix>, node_index: u32)
@@ -169,17 +206,10 @@ impl ConnectedMesh {
 
             let matrix = &quadrics[edge.pos_a as usize] + &quadrics[edge.pos_b as usize];
 
-            let det = matrix.get_det_xyz();
-
             let (error_o, pos_o) = &
-            if det > 0.001 || det < -0.001 {
-                let pos = DVec3::new(
-                    -1.0 / det * matrix.get_det_x(),
-                     1.0 / det * matrix.get_det_y(),
-                    -1.0 / det * matrix.get_det_z());
-                (matrix.quadric_distance_to_vertex(&pos), pos)
-            } else {
-                (f64::MAX, DVec3::default())
+            match matrix.optimal_position(0.001) {
+                Some(pos) => (matrix.quadric_distance_to_vertex(&pos), pos),
+                None => (f64::MAX, DVec3::default())
             };
 
             let mut error_a = matrix.quadric_distance_to_vertex(&pos_a);
@@ -214,4 +244,57 @@ impl ConnectedMesh {
             collapse_context.collapse_to = *xpos;
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod decimate_tests {
+    use crate::mesh::*;
+    use crate::mesh::test_utils::read_sphere;
+
+    // FNV-1a over the output buffers, so that the hash itself does not depend on the platform
+    fn hash(shared_mesh: &SharedMesh) -> u64 {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        let mut write = |value: u64| {
+            for byte in value.to_le_bytes().iter() {
+                hash ^= *byte as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        };
+        for position in shared_mesh.positions.iter() {
+            write(position.x.to_bits());
+            write(position.y.to_bits());
+            write(position.z.to_bits());
+        }
+        for triangle in shared_mesh.triangles.iter() {
+            write(triangle[0] as u64);
+            write(triangle[1] as u64);
+            write(triangle[2] as u64);
+        }
+        hash
+    }
+
+    fn decimate_sphere(target_triangle_ratio: f32) -> SharedMesh {
+        let mut connected_mesh = ConnectedMesh::from(&read_sphere());
+        connected_mesh.decimate_to_ratio(target_triangle_ratio);
+        SharedMesh::from(&connected_mesh)
+    }
+
+    #[test]
+    fn decimate_is_reproducible() {
+        for ratio in [0.5, 0.2, 0.05] {
+            assert_eq!(hash(&decimate_sphere(ratio)), hash(&decimate_sphere(ratio)));
+        }
+    }
+
+    #[test]
+    fn decimate_matches_golden_output() {
+        let decimated = decimate_sphere(0.5);
+        assert_eq!(decimated.triangles.len(), 640);
+        assert_eq!(decimated.positions.len(), 322);
+        assert_eq!(hash(&decimated), 0x01e67ac79644da59);
+
+        let decimated = decimate_sphere(0.2);
+        assert_eq!(decimated.triangles.len(), 256);
+        assert_eq!(decimated.positions.len(), 130);
+        assert_eq!(hash(&decimated), 0x15766db5f9987847);
+    }
+}
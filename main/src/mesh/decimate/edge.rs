@@ -32,6 +32,11 @@ impl Edge {
             pos_b: pos_b,
         }
     }
+
+    // Same key whatever the direction of the edge: smallest position in the high bits, largest in the low bits
+    fn get_key(&self) -> u64 {
+        (self.pos_a.min(self.pos_b) as u64) << 32 | self.pos_a.max(self.pos_b) as u64
+    }
 }
 
 impl Display for Edge {
@@ -0,0 +1,133 @@
+impl ConnectedMesh {
+    /// Generates one level of detail per given triangle ratio, running the collapse queue only once.
+    /// Ratios are relative to the current face count and can be given in any order, levels are returned in the same order.
+    /// The connected mesh is left at the coarsest level.
+    pub fn generate_lods(&mut self, target_triangle_ratios: &[f32]) -> Vec<SharedMesh> {
+        let mut lods: Vec<Option<SharedMesh>> = target_triangle_ratios.iter().map(|_| None).collect();
+        let order = self.lods_order(target_triangle_ratios);
+        let target_triangle_counts: Vec<u32> = order.iter().map(|x| x.1).collect();
+
+        self.decimate_in_steps(&target_triangle_counts, None, &mut None, |connected_mesh, target_index| {
+            lods[order[target_index].0] = Some(SharedMesh::from(connected_mesh));
+        });
+
+        lods.into_iter().map(|lod| lod.unwrap()).collect()
+    }
+
+    /// Same as `generate_lods`, but all levels share a single vertex buffer.
+    /// Each level of detail is written as a `Group` (index range) in the same order as the given ratios, glTF LOD style.
+    pub fn generate_lods_shared(&mut self, target_triangle_ratios: &[f32]) -> SharedMesh {
+        let mut lods: Vec<Option<Vec<U32Vec3>>> = target_triangle_ratios.iter().map(|_| None).collect();
+        let order = self.lods_order(target_triangle_ratios);
+        let target_triangle_counts: Vec<u32> = order.iter().map(|x| x.1).collect();
+
+        // Vertices are identified by their attributes and current location, since collapses move positions around
+        let mut per_vertex_map = HashMap::<[u64; 7], u32>::new();
+        let mut positions = Vec::<DVec3>::new();
+        let mut normals = Vec::<DVec3>::new();
+        let mut colors = Vec::<DVec3>::new();
+        let mut uvs = Vec::<DVec2>::new();
+
+        self.decimate_in_steps(&target_triangle_counts, None, &mut None, |connected_mesh, target_index| {
+            let mut triangles = Vec::<U32Vec3>::with_capacity(connected_mesh.face_count as usize);
+            for i in 0..connected_mesh.nodes.len() {
+                if !connected_mesh.is_first_node_of_face(i as u32) {
+                    continue;
+                }
+                let mut triangle = U32Vec3::default();
+                let mut x = 0;
+                loop_relatives!(i as u32, connected_mesh.nodes, relative, {
+                    let corner = connected_mesh.get_corner(relative);
+                    let position = connected_mesh.positions[corner[0] as usize];
+                    let key = [corner[0] as u64, corner[1] as u64, corner[2] as u64, corner[3] as u64,
+                        position.x.to_bits(), position.y.to_bits(), position.z.to_bits()];
+                    let index = *per_vertex_map.entry(key).or_insert_with(|| {
+                        positions.push(position);
+                        if let Some(cm_normals) = &connected_mesh.normals {
+                            normals.push(cm_normals[corner[1] as usize]);
+                        }
+                        if let Some(cm_colors) = &connected_mesh.colors {
+                            colors.push(cm_colors[corner[2] as usize]);
+                        }
+                        if let Some(cm_uvs) = &connected_mesh.uvs {
+                            uvs.push(cm_uvs[corner[3] as usize]);
+                        }
+                        (positions.len() - 1) as u32
+                    });
+                    triangle[x] = index;
+                    x += 1;
+                });
+                triangles.push(triangle);
+            }
+            lods[order[target_index].0] = Some(triangles);
+        });
+
+        let mut groups = Vec::with_capacity(lods.len());
+        let mut triangles = Vec::<U32Vec3>::new();
+        for lod in lods.into_iter() {
+            let lod = lod.unwrap();
+            groups.push(Group::new(3 * triangles.len() as u32, 3 * lod.len() as u32));
+            triangles.extend(lod);
+        }
+
+        SharedMesh {
+            groups,
+            triangles,
+            positions,
+            normals: self.normals.as_ref().map(|_| normals),
+            colors: self.colors.as_ref().map(|_| colors),
+            uvs: self.uvs.as_ref().map(|_| uvs),
+        }
+    }
+
+    // Returns (index of the ratio, target triangle count) pairs sorted by decreasing triangle count
+    fn lods_order(&self, target_triangle_ratios: &[f32]) -> Vec<(usize, u32)> {
+        let mut order: Vec<(usize, u32)> = target_triangle_ratios.iter()
+            .map(|ratio| (ratio * self.face_count as f32) as u32)
+            .enumerate()
+            .collect();
+        order.sort_by_key(|x| std::cmp::Reverse(x.1));
+        order
+    }
+}
+
+#[cfg(test)]
+mod lods_tests {
+    use crate::mesh::*;
+    use crate::mesh::test_utils::read_sphere;
+
+    #[test]
+    fn generate_lods() {
+        let mut connected_mesh = ConnectedMesh::from(&read_sphere());
+        let face_count = connected_mesh.face_count;
+
+        // Unordered on purpose
+        let lods = connected_mesh.generate_lods(&[0.5, 1.0, 0.1, 0.25]);
+
+        assert_eq!(lods.len(), 4);
+        assert_eq!(lods[1].triangles.len() as u32, face_count);
+        assert!(lods[0].triangles.len() as u32 <= face_count / 2);
+        assert!(lods[3].triangles.len() as u32 <= face_count / 4);
+        assert!(lods[2].triangles.len() as u32 <= face_count / 10);
+        assert!(lods[2].triangles.len() < lods[3].triangles.len());
+        assert!(lods[3].triangles.len() < lods[0].triangles.len());
+    }
+
+    #[test]
+    fn generate_lods_shared() {
+        let mut connected_mesh = ConnectedMesh::from(&read_sphere());
+        let lods = ConnectedMesh::from(&read_sphere()).generate_lods(&[0.5, 0.2]);
+        let shared = connected_mesh.generate_lods_shared(&[0.5, 0.2]);
+
+        assert_eq!(shared.groups.len(), 2);
+        for (group, lod) in shared.groups.iter().zip(lods.iter()) {
+            assert_eq!(group.index_count() as usize, 3 * lod.triangles.len());
+        }
+        assert_eq!(shared.groups[1].first_index(), shared.groups[0].index_count());
+        for triangle in shared.triangles.iter() {
+            for i in triangle.iter() {
+                assert!((*i as usize) < shared.positions.len());
+            }
+        }
+    }
+}
@@ -0,0 +1,184 @@
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+use rayon::prelude::*;
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+use super::base::Box3;
+
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+const MIN_FACES_PER_CLUSTER: u32 = 10_000;
+
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+impl ConnectedMesh {
+    /// Decimates the mesh using all available threads.
+    /// The mesh is partitioned into spatial clusters whose interiors are decimated concurrently, with cluster borders locked.
+    /// A final serial pass then decimates across borders down to the target triangle count.
+    pub fn decimate_parallel(&mut self, target_triangle_count: u32) {
+        let cluster_count = (2 * rayon::current_num_threads()).next_power_of_two()
+            .min((self.face_count / MIN_FACES_PER_CLUSTER).next_power_of_two() as usize);
+        self.decimate_in_clusters(target_triangle_count, cluster_count);
+    }
+
+    pub fn decimate_parallel_to_ratio(&mut self, target_triangle_ratio: f32) {
+        self.decimate_parallel((target_triangle_ratio * self.face_count as f32) as u32);
+    }
+
+    fn decimate_in_clusters(&mut self, target_triangle_count: u32, cluster_count: usize) {
+        if cluster_count < 2 || self.face_count <= target_triangle_count {
+            self.decimate(target_triangle_count);
+            return;
+        }
+
+        // Partition faces in balanced clusters by recursively splitting them at the median of their centroids
+        let mut faces: Vec<(u32, DVec3)> = (0..self.nodes.len() as u32)
+            .filter(|i| self.is_first_node_of_face(*i))
+            .map(|i| {
+                let mut centroid = DVec3::default();
+                loop_relatives!(i, self.nodes, relative, {
+                    centroid += self.positions[self.nodes[relative as usize].position as usize];
+                });
+                (i, centroid / 3.0)
+            })
+            .collect();
+        let mut clusters = Vec::with_capacity(cluster_count);
+        split_in_clusters(&mut faces, cluster_count, &mut clusters);
+
+        // Positions shared by several clusters form the borders, which are locked during the concurrent pass
+        let mut position_clusters = vec![u32::MAX; self.positions.len()];
+        let mut locked_positions = vec![false; self.positions.len()];
+        for (cluster_index, cluster) in clusters.iter().enumerate() {
+            for face in cluster.iter() {
+                loop_relatives!(*face, self.nodes, relative, {
+                    let position = self.nodes[relative as usize].position as usize;
+                    if position_clusters[position] == u32::MAX {
+                        position_clusters[position] = cluster_index as u32;
+                    } else if position_clusters[position] != cluster_index as u32 {
+                        locked_positions[position] = true;
+                    }
+                });
+            }
+        }
+
+        let face_count = self.face_count as u64;
+        let decimated_clusters: Vec<(ConnectedMesh, [Vec<u32>; 4])> = clusters.par_iter()
+            .map(|cluster| {
+                let (mut connected_mesh, local_to_global) = self.extract_faces(cluster);
+                let local_locked_positions: Vec<bool> = local_to_global[0].iter()
+                    .map(|global| locked_positions[*global as usize])
+                    .collect();
+                let target = (cluster.len() as u64 * target_triangle_count as u64 / face_count) as u32;
+                connected_mesh.decimate_in_steps(&[target], Some(&local_locked_positions), &mut None, |_, _| ());
+                (connected_mesh, local_to_global)
+            })
+            .collect();
+
+        // Stitch clusters back together. Unlocked positions belong to a single cluster, so they keep their global index.
+        let mut positions = std::mem::take(&mut self.positions);
+        let mut corners = Vec::<[[u32; 4]; 3]>::new();
+        for (connected_mesh, local_to_global) in decimated_clusters.iter() {
+            for (local, global) in local_to_global[0].iter().enumerate() {
+                positions[*global as usize] = connected_mesh.positions[local];
+            }
+            for i in 0..connected_mesh.nodes.len() as u32 {
+                if !connected_mesh.is_first_node_of_face(i) {
+                    continue;
+                }
+                let mut face = [[0; 4]; 3];
+                let mut x = 0;
+                loop_relatives!(i, connected_mesh.nodes, relative, {
+                    let corner = connected_mesh.get_corner(relative);
+                    for channel in 0..4 {
+                        face[x][channel] = local_to_global[channel][corner[channel] as usize];
+                    }
+                    x += 1;
+                });
+                corners.push(face);
+            }
+        }
+
+        *self = ConnectedMesh::from_corners(positions, self.normals.take(), self.colors.take(), self.uvs.take(), &corners);
+
+        // Final pass, with borders unlocked
+        self.decimate(target_triangle_count);
+
+        fn split_in_clusters(faces: &mut [(u32, DVec3)], cluster_count: usize, clusters: &mut Vec<Vec<u32>>) {
+            if cluster_count < 2 || faces.len() < 2 {
+                clusters.push(faces.iter().map(|x| x.0).collect());
+                return;
+            }
+            let mut bounds = Box3::unfitted();
+            for face in faces.iter() {
+                bounds.expand(&face.1);
+            }
+            let size = bounds.size();
+            let axis = if size.x > size.y && size.x > size.z { 0 } else if size.y > size.z { 1 } else { 2 };
+            let median = faces.len() / 2;
+            faces.select_nth_unstable_by(median, |a, b| a.1[axis].partial_cmp(&b.1[axis]).unwrap_or(Ordering::Equal));
+            let (left, right) = faces.split_at_mut(median);
+            split_in_clusters(left, cluster_count / 2, clusters);
+            split_in_clusters(right, cluster_count - cluster_count / 2, clusters);
+        }
+    }
+
+    // Copies the given faces (by first node) into a new connected mesh.
+    // Also returns the global index of every local position, normal, color and uv.
+    fn extract_faces(&self, faces: &[u32]) -> (ConnectedMesh, [Vec<u32>; 4]) {
+        let mut global_to_local: [U32Map; 4] = Default::default();
+        let mut local_to_global: [Vec<u32>; 4] = Default::default();
+        let mut corners = Vec::with_capacity(faces.len());
+
+        for face in faces.iter() {
+            let mut face_corners = [[0; 4]; 3];
+            let mut x = 0;
+            loop_relatives!(*face, self.nodes, relative, {
+                let corner = self.get_corner(relative);
+                for channel in 0..4 {
+                    let local_to_global = &mut local_to_global[channel];
+                    face_corners[x][channel] = *global_to_local[channel].entry(corner[channel]).or_insert_with(|| {
+                        local_to_global.push(corner[channel]);
+                        (local_to_global.len() - 1) as u32
+                    });
+                }
+                x += 1;
+            });
+            corners.push(face_corners);
+        }
+
+        let positions = local_to_global[0].iter().map(|i| self.positions[*i as usize]).collect();
+        let normals = self.normals.as_ref().map(|normals| local_to_global[1].iter().map(|i| normals[*i as usize]).collect());
+        let colors = self.colors.as_ref().map(|colors| local_to_global[2].iter().map(|i| colors[*i as usize]).collect());
+        let uvs = self.uvs.as_ref().map(|uvs| local_to_global[3].iter().map(|i| uvs[*i as usize]).collect());
+
+        (ConnectedMesh::from_corners(positions, normals, colors, uvs, &corners), local_to_global)
+    }
+}
+
+#[cfg(all(test, feature = "parallel", not(target_arch = "wasm32")))]
+mod parallel_tests {
+    use crate::mesh::*;
+    use crate::mesh::test_utils::read_sphere;
+
+    fn mean_radius_error(connected_mesh: &ConnectedMesh) -> f64 {
+        let shared_mesh = SharedMesh::from(connected_mesh);
+        shared_mesh.positions.iter().map(|p| (1.0 - p.magnitude()).abs()).sum::<f64>() / shared_mesh.positions.len() as f64
+    }
+
+    #[test]
+    fn decimate_in_clusters() {
+        let mut serial = ConnectedMesh::from(&read_sphere());
+        serial.decimate(300);
+
+        let mut parallel = ConnectedMesh::from(&read_sphere());
+        parallel.decimate_in_clusters(300, 4);
+
+        assert!(parallel.face_count() <= 300);
+        assert!(parallel.face_count() > 290);
+        for i in 0..parallel.nodes.len() {
+            if !parallel.nodes[i].is_removed {
+                assert!(parallel.check_siblings(i as u32));
+                assert!(parallel.check_relatives(i as u32));
+            }
+        }
+
+        // Quality should stay in the same ballpark as the serial decimation
+        assert!(mean_radius_error(&parallel) < 2.0 * mean_radius_error(&serial));
+    }
+}
@@ -0,0 +1,169 @@
+// A collapse as seen from the connected mesh, in position indices
+struct CollapseRecord {
+    position_a: u32,
+    position_b: u32,
+    collapsed_to: Option<DVec3>,
+    // Faces removed by the collapse (first node of face, corner positions at the time of removal)
+    removed_faces: Vec<(u32, U32Vec3)>,
+    // Corners moved from B to A (first node of face, corner)
+    moved_corners: Vec<(u32, u8)>,
+    // Coordinates of every position involved, at the time of the collapse
+    coordinates: Vec<(u32, DVec3)>,
+}
+
+#[derive(Default)]
+struct CollapseRecorder {
+    records: Vec<CollapseRecord>,
+}
+
+impl CollapseRecorder {
+    fn record_collapse(&mut self, connected_mesh: &ConnectedMesh, node_index_a: u32, node_index_b: u32) {
+        let nodes = &connected_mesh.nodes;
+        let position_a = nodes[node_index_a as usize].position;
+        let position_b = nodes[node_index_b as usize].position;
+
+        let mut removed_faces = Vec::new();
+        loop_siblings!(node_index_a, nodes, sibling_of_a, {
+            let mut is_face_touched = false;
+            loop_relatives!(sibling_of_a, nodes, relative_of_a, {
+                if nodes[relative_of_a as usize].position == position_b {
+                    is_face_touched = true;
+                }
+            });
+            if is_face_touched {
+                removed_faces.push(connected_mesh.get_face_snapshot(sibling_of_a));
+            }
+        });
+
+        let mut moved_corners = Vec::new();
+        loop_siblings!(node_index_b, nodes, sibling_of_b, {
+            let (first_node, corner) = connected_mesh.get_face_corner(sibling_of_b);
+            if !removed_faces.iter().any(|x| x.0 == first_node) {
+                moved_corners.push((first_node, corner));
+            }
+        });
+
+        let mut coordinates = vec![
+            (position_a, connected_mesh.positions[position_a as usize]),
+            (position_b, connected_mesh.positions[position_b as usize])];
+        for (_, face) in removed_faces.iter() {
+            for position in face.iter() {
+                coordinates.push((*position, connected_mesh.positions[*position as usize]));
+            }
+        }
+
+        self.records.push(CollapseRecord {
+            position_a,
+            position_b,
+            collapsed_to: None,
+            removed_faces,
+            moved_corners,
+            coordinates,
+        });
+    }
+
+    fn record_collapsed_position(&mut self, collapsed_to: DVec3) {
+        self.records.last_mut().unwrap().collapsed_to = Some(collapsed_to);
+    }
+}
+
+impl ConnectedMesh {
+    /// Decimates the mesh down to the given triangle count while recording every edge collapse as an invertible vertex split.
+    /// The returned progressive mesh starts at the coarsest level and can be refined back up to the original face count.
+    pub fn decimate_progressive(&mut self, target_triangle_count: u32) -> ProgressiveMesh {
+        let mut recorder = CollapseRecorder::default();
+        self.decimate_in_steps(&[target_triangle_count], None, &mut Some(&mut recorder), |_, _| ());
+
+        // Vertices and faces are renumbered in order of appearance when refining, so that streaming only appends to buffers
+        let mut vertex_ids = vec![u32::MAX; self.positions.len()];
+        let mut vertex_count = 0;
+        let mut face_ids = U32Map::with_hasher(BuildHasherDefault::<SimpleHasher>::default());
+
+        let mut positions = Vec::new();
+        let mut triangles = Vec::new();
+        for i in 0..self.nodes.len() {
+            if !self.is_first_node_of_face(i as u32) {
+                continue;
+            }
+            let (_, face) = self.get_face_snapshot(i as u32);
+            let mut triangle = U32Vec3::default();
+            for x in 0..3 {
+                if vertex_ids[face[x] as usize] == u32::MAX {
+                    vertex_ids[face[x] as usize] = vertex_count;
+                    vertex_count += 1;
+                    positions.push(self.positions[face[x] as usize]);
+                }
+                triangle[x] = vertex_ids[face[x] as usize];
+            }
+            face_ids.insert(i as u32, triangles.len() as u32);
+            triangles.push(triangle);
+        }
+
+        let mut progressive_mesh = ProgressiveMesh::new(positions, triangles);
+
+        for record in recorder.records.iter().rev() {
+            let mut new_positions = Vec::new();
+            let mut involved_positions = vec![record.position_a, record.position_b];
+            involved_positions.extend(record.removed_faces.iter().flat_map(|(_, face)| face.iter().copied()));
+            for position in involved_positions {
+                if vertex_ids[position as usize] == u32::MAX {
+                    vertex_ids[position as usize] = vertex_count;
+                    vertex_count += 1;
+                    new_positions.push(record.coordinates.iter().find(|x| x.0 == position).unwrap().1);
+                }
+            }
+
+            let mut faces = Vec::with_capacity(record.removed_faces.len());
+            for (first_node, face) in record.removed_faces.iter() {
+                face_ids.insert(*first_node, (progressive_mesh.triangles().len() + faces.len()) as u32);
+                faces.push(U32Vec3::new(vertex_ids[face[0] as usize], vertex_ids[face[1] as usize], vertex_ids[face[2] as usize]));
+            }
+
+            let moved_corners = record.moved_corners.iter()
+                .map(|(first_node, corner)| face_ids[first_node] << 2 | *corner as u32)
+                .collect();
+
+            progressive_mesh.push_split(VertexSplit {
+                vertex: vertex_ids[record.position_a as usize],
+                split_vertex: vertex_ids[record.position_b as usize],
+                position: record.coordinates[0].1,
+                new_positions,
+                faces,
+                moved_corners,
+            });
+
+            debug_assert!(record.collapsed_to.is_none() || record.collapsed_to == Some(progressive_mesh.collapsed_position(progressive_mesh.split_count() - 1)));
+        }
+
+        // Start from the coarsest level
+        progressive_mesh.set_level(0);
+        progressive_mesh
+    }
+
+    // Returns the first node of the face (the one with the smallest index) and the corner of the given node in it
+    fn get_face_corner(&self, node_index: u32) -> (u32, u8) {
+        let mut first_node = node_index;
+        loop_relatives!(node_index, self.nodes, relative, {
+            first_node = first_node.min(relative);
+        });
+        let mut corner = 0;
+        let mut relative = first_node;
+        while relative != node_index {
+            relative = self.nodes[relative as usize].relative;
+            corner += 1;
+        }
+        (first_node, corner)
+    }
+
+    // Returns the first node of the face and its positions, starting from that node
+    fn get_face_snapshot(&self, node_index: u32) -> (u32, U32Vec3) {
+        let (first_node, _) = self.get_face_corner(node_index);
+        let mut face = U32Vec3::default();
+        let mut x = 0;
+        loop_relatives!(first_node, self.nodes, relative, {
+            face[x] = self.nodes[relative as usize].position;
+            x += 1;
+        });
+        (first_node, face)
+    }
+}
@@ -0,0 +1,278 @@
+use nalgebra_glm as glm;
+use glm::{DVec3};
+use hashbrown::HashMap;
+use super::SharedMesh;
+use crate::spatial::{Bvh, ClosestPoint};
+
+// Additive recurrence of the R2 sequence (Roberts), which spreads points evenly in the unit square
+const R2_STEPS: (f64, f64) = (0.754_877_666_246_692_7, 0.569_840_290_998_053_2);
+
+/// Distances from points sampled on a surface to another surface
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct DistanceStats {
+    /// One-sided Hausdorff distance
+    pub max: f64,
+    pub mean: f64,
+    pub rms: f64,
+}
+
+/// How far a mesh deviates from a reference mesh, as returned by `SharedMesh::compare`
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DeviationReport {
+    /// From the samples of the mesh to the reference
+    pub forward: DistanceStats,
+    /// From the samples of the reference to the mesh
+    pub backward: DistanceStats,
+    /// Symmetric Hausdorff distance, the largest of both one-sided distances
+    pub hausdorff: f64,
+    /// Over the samples of both surfaces
+    pub mean: f64,
+    pub rms: f64,
+    /// Distance from every vertex of the mesh to the reference, positive outside of it. The side is given by the angle
+    /// weighted pseudo-normal of the closest face, edge or vertex, so it holds near edges and corners of a closed reference.
+    pub vertex_deviations: Vec<f64>,
+}
+
+impl SharedMesh {
+    /// Measures how far this mesh is from a reference mesh (for instance the original of a decimated mesh).
+    /// Both surfaces are sampled with their vertices and `sample_count` points spread evenly by area, deterministically.
+    pub fn compare(&self, reference: &SharedMesh, sample_count: usize) -> DeviationReport {
+        let (self_bvh, reference_bvh) = (Bvh::from(self), Bvh::from(reference));
+
+        let reference_normals = PseudoNormals::new(&reference_bvh);
+
+        let mut forward_distances = Vec::new();
+        let mut vertex_deviations = Vec::with_capacity(self.positions.len());
+        for position in self.positions.iter() {
+            let deviation = reference_normals.get_signed_distance(&reference_bvh, position);
+            vertex_deviations.push(deviation);
+            forward_distances.push(deviation.abs());
+        }
+        forward_distances.extend(self.sample_surface(sample_count).iter().map(|p| get_distance(&reference_bvh, p)));
+
+        let mut backward_distances: Vec<f64> = reference.positions.iter().map(|p| get_distance(&self_bvh, p)).collect();
+        backward_distances.extend(reference.sample_surface(sample_count).iter().map(|p| get_distance(&self_bvh, p)));
+
+        let (forward, backward) = (get_stats(&forward_distances), get_stats(&backward_distances));
+        let (forward_count, backward_count) = (forward_distances.len() as f64, backward_distances.len() as f64);
+        let total_count = (forward_count + backward_count).max(1.0);
+        DeviationReport {
+            forward,
+            backward,
+            hausdorff: forward.max.max(backward.max),
+            mean: (forward.mean * forward_count + backward.mean * backward_count) / total_count,
+            rms: ((forward.rms * forward.rms * forward_count + backward.rms * backward.rms * backward_count) / total_count).sqrt(),
+            vertex_deviations,
+        }
+    }
+
+    /// Replaces vertex colors with a heatmap of per-vertex values, such as `DeviationReport::vertex_deviations`:
+    /// blue at `-range` and below, green at zero and red at `range` and above.
+    pub fn set_heatmap_colors(&mut self, values: &[f64], range: f64) {
+        self.colors = Some(values.iter().map(|value| {
+            let t = if range > 0.0 { (value / range).clamp(-1.0, 1.0) } else { 0.0 };
+            if t >= 0.0 {
+                DVec3::new(t, 1.0 - t, 0.0)
+            } else {
+                DVec3::new(0.0, 1.0 + t, -t)
+            }
+        }).collect());
+    }
+
+    // Points spread over the triangles proportionally to their area
+    fn sample_surface(&self, sample_count: usize) -> Vec<DVec3> {
+        let mut cumulated_areas = Vec::with_capacity(self.triangles.len());
+        let mut total_area = 0.0;
+        for triangle in self.triangles.iter() {
+            let [a, b, c] = [0, 1, 2].map(|x| self.positions[triangle[x] as usize]);
+            total_area += 0.5 * (b - a).cross(&(c - a)).magnitude();
+            cumulated_areas.push(total_area);
+        }
+        if total_area == 0.0 {
+            return Vec::new();
+        }
+
+        (0..sample_count).map(|i| {
+            // Stratified by area, so that every triangle gets its share of samples
+            let target = (i as f64 + 0.5) / sample_count as f64 * total_area;
+            let t = cumulated_areas.partition_point(|&area| area < target).min(self.triangles.len() - 1);
+            let triangle = &self.triangles[t];
+            let [a, b, c] = [0, 1, 2].map(|x| self.positions[triangle[x] as usize]);
+            let (mut u, mut v) = ((0.5 + i as f64 * R2_STEPS.0).fract(), (0.5 + i as f64 * R2_STEPS.1).fract());
+            // Folds the unit square onto the triangle
+            if u + v > 1.0 {
+                u = 1.0 - u;
+                v = 1.0 - v;
+            }
+            a + (b - a) * u + (c - a) * v
+        }).collect()
+    }
+}
+
+fn get_distance(bvh: &Bvh, point: &DVec3) -> f64 {
+    bvh.get_closest_point(point, f64::MAX).map_or(f64::INFINITY, |closest| closest.distance)
+}
+
+// Angle weighted pseudo-normals of the vertices and edges of a mesh (Bærentzen and Aanæs, 2005). Unlike the normal of the
+// closest triangle, which is arbitrary when the closest point is on an edge or a vertex, the pseudo-normal of the closest
+// feature always tells the inside of a closed mesh from its outside. Vertices at the same position are welded.
+struct PseudoNormals {
+    // Welded vertex of every position
+    vertices: Vec<u32>,
+    vertex_normals: Vec<DVec3>,
+    edge_normals: HashMap<(u32, u32), DVec3>,
+}
+
+impl PseudoNormals {
+    fn new(bvh: &Bvh) -> Self {
+        let mut welded: HashMap<[u64; 3], u32> = HashMap::new();
+        let vertices: Vec<u32> = bvh.positions().iter().map(|p| {
+            let count = welded.len() as u32;
+            *welded.entry([p.x.to_bits(), p.y.to_bits(), p.z.to_bits()]).or_insert(count)
+        }).collect();
+
+        let mut vertex_normals = vec![DVec3::zeros(); welded.len()];
+        let mut edge_normals = HashMap::new();
+        for triangle in bvh.triangles().iter() {
+            let positions = [0, 1, 2].map(|x| bvh.positions()[triangle[x] as usize]);
+            let normal = match (positions[1] - positions[0]).cross(&(positions[2] - positions[0])).try_normalize(0.0) {
+                Some(normal) => normal,
+                None => continue,
+            };
+            for x in 0..3 {
+                let (u, v) = (positions[(x + 1) % 3] - positions[x], positions[(x + 2) % 3] - positions[x]);
+                vertex_normals[vertices[triangle[x] as usize] as usize] += normal * u.angle(&v);
+                *edge_normals.entry(get_edge(&vertices, triangle[x], triangle[(x + 1) % 3])).or_insert_with(DVec3::zeros) += normal;
+            }
+        }
+        PseudoNormals { vertices, vertex_normals, edge_normals }
+    }
+
+    // Distance to the closest point, positive on the side the pseudo-normal of its feature points to
+    fn get_signed_distance(&self, bvh: &Bvh, point: &DVec3) -> f64 {
+        match bvh.get_closest_point(point, f64::MAX) {
+            Some(closest) => if self.get_normal(bvh, &closest).dot(&(point - closest.point)) < 0.0 { -closest.distance } else { closest.distance },
+            None => f64::INFINITY,
+        }
+    }
+
+    fn get_normal(&self, bvh: &Bvh, closest: &ClosestPoint) -> DVec3 {
+        let triangle = &bvh.triangles()[closest.triangle as usize];
+        let [a, b, c] = [0, 1, 2].map(|x| bvh.positions()[triangle[x] as usize]);
+        // Barycentric coordinates of the closest point tell the feature it lies on
+        let (ab, ac, aq) = (b - a, c - a, closest.point - a);
+        let (d00, d01, d11) = (ab.dot(&ab), ab.dot(&ac), ac.dot(&ac));
+        let denominator = d00 * d11 - d01 * d01;
+        if denominator <= 0.0 {
+            return ab.cross(&ac);
+        }
+        let v = (d11 * ab.dot(&aq) - d01 * ac.dot(&aq)) / denominator;
+        let w = (d00 * ac.dot(&aq) - d01 * ab.dot(&aq)) / denominator;
+        let is_on_feature = [1.0 - v - w, v, w].map(|coordinate| coordinate > 1e-9);
+        let get_edge_normal = |x: usize, y: usize| self.edge_normals.get(&get_edge(&self.vertices, triangle[x], triangle[y])).copied();
+        match is_on_feature {
+            [true, false, false] => Some(self.vertex_normals[self.vertices[triangle[0] as usize] as usize]),
+            [false, true, false] => Some(self.vertex_normals[self.vertices[triangle[1] as usize] as usize]),
+            [false, false, true] => Some(self.vertex_normals[self.vertices[triangle[2] as usize] as usize]),
+            [true, true, false] => get_edge_normal(0, 1),
+            [false, true, true] => get_edge_normal(1, 2),
+            [true, false, true] => get_edge_normal(2, 0),
+            _ => None,
+        }.unwrap_or_else(|| ab.cross(&ac))
+    }
+}
+
+fn get_edge(vertices: &[u32], a: u32, b: u32) -> (u32, u32) {
+    let (a, b) = (vertices[a as usize], vertices[b as usize]);
+    (a.min(b), a.max(b))
+}
+
+fn get_stats(distances: &[f64]) -> DistanceStats {
+    if distances.is_empty() {
+        return DistanceStats::default();
+    }
+    let count = distances.len() as f64;
+    DistanceStats {
+        max: distances.iter().cloned().fold(0.0, f64::max),
+        mean: distances.iter().sum::<f64>() / count,
+        rms: (distances.iter().map(|d| d * d).sum::<f64>() / count).sqrt(),
+    }
+}
+
+#[cfg(test)]
+mod deviation_tests {
+    use crate::mesh::*;
+    use crate::spatial::Bvh;
+    use super::PseudoNormals;
+    use crate::mesh::test_utils::read_sphere;
+
+    #[test]
+    fn identical_meshes() {
+        let sphere = read_sphere();
+        let report = sphere.compare(&read_sphere(), 1000);
+        assert!(report.hausdorff < 1e-12 && report.rms < 1e-12);
+        assert!(report.vertex_deviations.iter().all(|d| *d == 0.0));
+    }
+
+    #[test]
+    fn scaled_sphere() {
+        let original = read_sphere();
+        let mut scaled = read_sphere();
+        for position in scaled.positions.iter_mut() {
+            *position *= 1.1;
+        }
+
+        let report = scaled.compare(&original, 5000);
+        // Vertices are on the sphere, faces slightly inside of it
+        assert!(report.forward.max > 0.1 && report.forward.max < 0.12, "{:?}", report.forward);
+        assert!(report.backward.max > 0.09 && report.backward.max < 0.11, "{:?}", report.backward);
+        assert_eq!(report.hausdorff, report.forward.max);
+        assert!(report.mean > 0.09 && report.mean <= report.rms && report.rms <= report.hausdorff);
+        assert!(report.vertex_deviations.iter().all(|d| *d > 0.09 && *d < 0.12));
+
+        let report = original.compare(&scaled, 5000);
+        assert!(report.vertex_deviations.iter().all(|d| *d < -0.09 && *d > -0.12));
+    }
+
+    #[test]
+    fn sign_near_sharp_edge() {
+        // Prism along Y with a sharp top edge, where triangle normals are more than 90° apart
+        let positions = vec![
+            DVec3::new(0., 0., 1.), DVec3::new(0.1, 0., 0.), DVec3::new(-0.1, 0., 0.),
+            DVec3::new(0., 1., 1.), DVec3::new(0.1, 1., 0.), DVec3::new(-0.1, 1., 0.),
+        ];
+        let triangles = [[0, 2, 1], [3, 4, 5], [0, 4, 3], [0, 1, 4], [0, 5, 2], [0, 3, 5], [1, 5, 4], [1, 2, 5]]
+            .iter().map(|t| U32Vec3::new(t[0], t[1], t[2])).collect();
+        let bvh = Bvh::new(positions, triangles);
+        let normals = PseudoNormals::new(&bvh);
+
+        // Points around the top edge, whose closest point is on that edge
+        for degrees in (-80..=80).step_by(10) {
+            let angle = (degrees as f64).to_radians();
+            let point = DVec3::new(0., 0.5, 1.) + DVec3::new(angle.sin(), 0., angle.cos()) * 0.05;
+            assert!((normals.get_signed_distance(&bvh, &point) - 0.05).abs() < 1e-12, "{}°", degrees);
+        }
+        // Around a vertex of the edge, and inside
+        assert!(normals.get_signed_distance(&bvh, &DVec3::new(-0.05, -0.01, 1.01)) > 0.0);
+        assert!(normals.get_signed_distance(&bvh, &DVec3::new(0.01, 0.5, 0.5)) < 0.0);
+    }
+
+    #[test]
+    fn heatmap_of_decimated_mesh() {
+        let original = read_sphere();
+        let mut connected_mesh = ConnectedMesh::from(&original);
+        connected_mesh.decimate_to_ratio(0.25);
+        let mut decimated = SharedMesh::from(&connected_mesh);
+
+        let report = decimated.compare(&original, 2000);
+        assert!(report.hausdorff > 0.0 && report.hausdorff < 0.1, "{:?}", report.hausdorff);
+        assert_eq!(report.vertex_deviations.len(), decimated.positions.len());
+
+        decimated.set_heatmap_colors(&report.vertex_deviations, report.hausdorff);
+        let colors = decimated.colors.as_ref().unwrap();
+        assert_eq!(colors.len(), decimated.positions.len());
+        for (color, deviation) in colors.iter().zip(report.vertex_deviations.iter()) {
+            assert!((color.x - color.z - deviation / report.hausdorff).abs() < 1e-9);
+        }
+    }
+}
@@ -1,4 +1,24 @@
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Group {
   first_index: u32,
   index_count: u32,
 }
+
+impl Group {
+  pub fn new(first_index: u32, index_count: u32) -> Self {
+    Group { first_index, index_count }
+  }
+
+  // Offset of the group in the flattened index buffer (3 indices per triangle)
+  pub fn first_index(&self) -> u32 {
+    self.first_index
+  }
+
+  pub fn index_count(&self) -> u32 {
+    self.index_count
+  }
+
+  pub fn triangle_range(&self) -> std::ops::Range<usize> {
+    (self.first_index / 3) as usize..((self.first_index + self.index_count) / 3) as usize
+  }
+}
@@ -0,0 +1,168 @@
+use nalgebra_glm as glm;
+use glm::DVec3;
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+use rayon::prelude::*;
+use super::SharedMesh;
+use super::topology::UnionFind;
+use crate::base::{Box3, Ray};
+use crate::spatial::Bvh;
+
+// Visibility is sampled with parallel rays from directions spread evenly over a sphere around the mesh (Fibonacci lattice).
+// Every direction casts a square grid of rays across the bounding sphere, offset by a low discrepancy sequence from one
+// direction to the next so that successive grids don't hit the same points.
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HiddenRemoval {
+    /// Removes every triangle that no ray hits. Small visible triangles may be missed with too few rays.
+    Triangles,
+    /// Only removes components (triangles sharing vertices) that no ray hits, keeping visible ones whole
+    Components,
+}
+
+impl SharedMesh {
+    /// Whether every triangle is hit first by any ray, casting `resolution`² rays from each of `direction_count` directions
+    pub fn find_visible_triangles(&self, direction_count: u32, resolution: u32) -> Vec<bool> {
+        let mut bounds = Box3::unfitted();
+        for triangle in self.triangles.iter() {
+            for x in 0..3 {
+                bounds.expand(&self.positions[triangle[x] as usize]);
+            }
+        }
+        let mut is_visible = vec![false; self.triangles.len()];
+        if self.triangles.is_empty() {
+            return is_visible;
+        }
+        let bvh = Bvh::from(self);
+        let center = bounds.center();
+        let radius = 0.5 * bounds.diagonal() * 1.01 + f64::EPSILON;
+
+        let cast_rays = |i: u32| -> Vec<u32> {
+            let direction = get_fibonacci_direction(i, direction_count);
+            let u = direction.cross(&if direction.x.abs() < 0.9 { DVec3::x() } else { DVec3::y() }).normalize();
+            let v = direction.cross(&u);
+            let offset = ((i as f64 * 0.7548776662466927).fract(), (i as f64 * 0.5698402909980532).fract());
+            let mut hits = Vec::new();
+            for a in 0..resolution {
+                for b in 0..resolution {
+                    let x = ((a as f64 + offset.0) / resolution as f64 * 2.0 - 1.0) * radius;
+                    let y = ((b as f64 + offset.1) / resolution as f64 * 2.0 - 1.0) * radius;
+                    if x * x + y * y > radius * radius {
+                        continue;
+                    }
+                    let ray = Ray::new(center + direction * radius + u * x + v * y, -direction);
+                    if let Some(hit) = bvh.cast_ray(&ray, 2.0 * radius) {
+                        hits.push(hit.triangle);
+                    }
+                }
+            }
+            hits
+        };
+
+        #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+        let hits: Vec<Vec<u32>> = (0..direction_count).into_par_iter().map(cast_rays).collect();
+        #[cfg(any(not(feature = "parallel"), target_arch = "wasm32"))]
+        let hits: Vec<Vec<u32>> = (0..direction_count).map(cast_rays).collect();
+
+        for triangle in hits.iter().flatten() {
+            is_visible[*triangle as usize] = true;
+        }
+        is_visible
+    }
+
+    /// Removes the geometry that can't be seen from outside of the mesh, such as inner parts of CAD assemblies, and the
+    /// vertices that aren't used anymore. Groups left without triangles are removed. Returns the number of removed triangles.
+    /// See `find_visible_triangles` for the sampling.
+    pub fn remove_hidden(&mut self, direction_count: u32, resolution: u32, removal: HiddenRemoval) -> usize {
+        let mut is_visible = self.find_visible_triangles(direction_count, resolution);
+        if removal == HiddenRemoval::Components {
+            let mut components = UnionFind::new(self.positions.len());
+            for triangle in self.triangles.iter() {
+                components.union(triangle[0], triangle[1]);
+                components.union(triangle[0], triangle[2]);
+            }
+            let mut is_component_visible = vec![false; self.positions.len()];
+            for (triangle, is_visible) in self.triangles.iter().zip(is_visible.iter()) {
+                if *is_visible {
+                    is_component_visible[components.find(triangle[0]) as usize] = true;
+                }
+            }
+            for (triangle, is_visible) in self.triangles.iter().zip(is_visible.iter_mut()) {
+                *is_visible = is_component_visible[components.find(triangle[0]) as usize];
+            }
+        }
+
+        let kept: Vec<usize> = (0..self.triangles.len()).filter(|&t| is_visible[t]).collect();
+        let removed = self.triangles.len() - kept.len();
+        if removed > 0 {
+            let mut new_indices = vec![u32::MAX; self.positions.len()];
+            *self = self.extract_triangles(&kept, &mut new_indices);
+        }
+        removed
+    }
+}
+
+// Direction i of n, spread evenly over the unit sphere
+fn get_fibonacci_direction(i: u32, n: u32) -> DVec3 {
+    let golden_angle = std::f64::consts::PI * (3.0 - 5f64.sqrt());
+    let z = 1.0 - 2.0 * (i as f64 + 0.5) / n as f64;
+    let r = (1.0 - z * z).sqrt();
+    let phi = golden_angle * i as f64;
+    DVec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+#[cfg(test)]
+mod hidden_tests {
+    use crate::mesh::*;
+    use crate::mesh::test_utils::{build_box, read_sphere};
+
+    fn combine(meshes: Vec<SharedMesh>) -> SharedMesh {
+        let mut groups = Vec::new();
+        let mut combined = SharedMesh { positions: Vec::new(), triangles: Vec::new(), groups: Vec::new(), normals: None, colors: None, uvs: None };
+        for shared_mesh in meshes {
+            groups.push(Group::new(combined.triangles.len() as u32 * 3, shared_mesh.triangles.len() as u32 * 3));
+            combined = SharedMesh::combine(combined, shared_mesh);
+        }
+        combined.groups = groups;
+        combined
+    }
+
+    #[test]
+    fn remove_inner_parts() {
+        // A housing with a screw inside, and a part next to it
+        let mut shared_mesh = combine(vec![
+            build_box(DVec3::new(0.4, 0.4, 0.2), DVec3::new(0.2, 0.2, 0.6)),
+            build_box(DVec3::zeros(), DVec3::new(1., 1., 1.)),
+            build_box(DVec3::new(2., 0., 0.), DVec3::new(1., 1., 1.)),
+        ]);
+        let removed = shared_mesh.remove_hidden(64, 32, HiddenRemoval::Triangles);
+        assert_eq!(removed, 12);
+        assert_eq!(shared_mesh.triangles.len(), 24);
+        assert_eq!(shared_mesh.positions.len(), 16);
+        assert_eq!(shared_mesh.groups, vec![Group::new(0, 36), Group::new(36, 36)]);
+        assert!(shared_mesh.positions.iter().all(|p| p.x == 0.0 || p.x >= 1.0));
+    }
+
+    #[test]
+    fn hidden_faces_of_visible_parts() {
+        // Boxes touching on a face, which is hidden on both
+        let shared_mesh = combine(vec![
+            build_box(DVec3::zeros(), DVec3::new(1., 1., 1.)),
+            build_box(DVec3::new(1., 0., 0.), DVec3::new(1., 1., 1.)),
+        ]);
+        let is_visible = shared_mesh.find_visible_triangles(64, 32);
+        let hidden: Vec<usize> = (0..24).filter(|&t| !is_visible[t]).collect();
+        assert_eq!(hidden, vec![10, 11, 20, 21]);
+
+        let mut triangles = shared_mesh.clone();
+        assert_eq!(triangles.remove_hidden(64, 32, HiddenRemoval::Triangles), 4);
+        let mut components = shared_mesh.clone();
+        assert_eq!(components.remove_hidden(64, 32, HiddenRemoval::Components), 0);
+    }
+
+    #[test]
+    fn keep_visible_sphere() {
+        let mut shared_mesh = read_sphere();
+        assert_eq!(shared_mesh.remove_hidden(32, 48, HiddenRemoval::Triangles), 0);
+        assert_eq!(shared_mesh.triangles.len(), 1280);
+    }
+}
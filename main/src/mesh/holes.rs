@@ -0,0 +1,392 @@
+/// How holes are triangulated by `fill_holes`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum HoleTriangulation {
+    /// Liepa's minimum weight triangulation, which minimizes the largest dihedral angle and then the area.
+    /// Follows the surface well even for curved or twisted borders, but takes cubic time in the hole size.
+    MinimumWeight,
+    /// Constrained Delaunay triangulation of the border projected onto its best-fit plane.
+    /// Much faster on large holes, for borders that are close to planar.
+    /// Falls back to `MinimumWeight` when the projected border intersects itself.
+    PlanarProjection,
+}
+
+impl ConnectedMesh {
+    /// Returns every border loop as a list of positions, ordered along the edges of the faces they border
+    pub fn get_border_loops(&self) -> Vec<Vec<u32>> {
+        self.get_border_loop_nodes().iter()
+            .map(|border_loop| border_loop.iter().map(|node| self.nodes[*node as usize].position).collect())
+            .collect()
+    }
+
+    /// Closes every hole whose border has at most `max_hole_size` edges, and returns how many were filled.
+    /// When `fair` is set, the patch is refined to the density of the surrounding mesh and smoothed,
+    /// instead of being only made of the border vertices. Attributes of new vertices are interpolated.
+    pub fn fill_holes(&mut self, max_hole_size: usize, triangulation: HoleTriangulation, fair: bool) -> usize {
+        let border_loops: Vec<Vec<u32>> = self.get_border_loop_nodes().into_iter()
+            .filter(|border_loop| border_loop.len() <= max_hole_size)
+            .collect();
+        if border_loops.is_empty() {
+            return 0;
+        }
+
+        let mut positions = self.positions.clone();
+        let mut attributes = CornerAttributes::new(self);
+        let mut corners = Vec::<[[u32; 4]; 3]>::with_capacity(self.face_count as usize);
+        for i in 0..self.nodes.len() as u32 {
+            if self.is_first_node_of_face(i) {
+                corners.push(self.get_face_nodes(i).map(|node| self.get_corner(node)));
+            }
+        }
+
+        for border_loop in border_loops.iter() {
+            let triangles = match triangulation {
+                HoleTriangulation::MinimumWeight => self.triangulate_minimum_weight(border_loop),
+                HoleTriangulation::PlanarProjection => self.triangulate_projected(border_loop)
+                    .unwrap_or_else(|| self.triangulate_minimum_weight(border_loop)),
+            };
+
+            // Border edges go from a node to its relative, so the patch goes the other way around to match the orientation of the mesh
+            let mut patch: Vec<[[u32; 4]; 3]> = triangles.iter()
+                .map(|triangle| [triangle[2], triangle[1], triangle[0]].map(|x| self.get_corner(border_loop[x])))
+                .collect();
+
+            if fair {
+                let border_positions: Vec<u32> = border_loop.iter().map(|node| self.nodes[*node as usize].position).collect();
+                let target_length = border_positions.iter()
+                    .zip(border_positions.iter().cycle().skip(1))
+                    .map(|(a, b)| (self.positions[*a as usize] - self.positions[*b as usize]).magnitude())
+                    .sum::<f64>() / border_positions.len() as f64;
+                let first_new_position = positions.len() as u32;
+                refine_patch(&mut patch, &mut positions, &mut attributes, target_length);
+                fair_patch(&patch, &mut positions, first_new_position);
+            }
+
+            corners.extend(patch);
+        }
+
+        *self = attributes.build(positions, &corners);
+        border_loops.len()
+    }
+
+    // Same as get_border_loops, with the node of the face each border edge starts from
+    fn get_border_loop_nodes(&self) -> Vec<Vec<u32>> {
+        // Border edges by start position. There can be several at non-manifold vertices.
+        let mut border_nodes = HashMap::<u32, Vec<u32>>::new();
+        for i in 0..self.nodes.len() as u32 {
+            if !self.nodes[i as usize].is_removed && self.is_border_edge(i) {
+                border_nodes.entry(self.nodes[i as usize].position).or_default().push(i);
+            }
+        }
+
+        let mut is_visited = HashSet::<u32>::new();
+        let mut border_loops = Vec::new();
+        for i in 0..self.nodes.len() as u32 {
+            if self.nodes[i as usize].is_removed || is_visited.contains(&i) || !self.is_border_edge(i) {
+                continue;
+            }
+            let mut border_loop = Vec::new();
+            let mut node = i;
+            loop {
+                is_visited.insert(node);
+                border_loop.push(node);
+                let end = self.nodes[self.nodes[node as usize].relative as usize].position;
+                match border_nodes.get(&end).and_then(|nodes| nodes.iter().find(|x| !is_visited.contains(x))) {
+                    Some(next) => node = *next,
+                    None => break,
+                }
+            }
+            // Borders that don't close up (through non-manifold vertices) can't be filled
+            let start = self.nodes[i as usize].position;
+            if border_loop.len() >= 3 && self.nodes[self.nodes[node as usize].relative as usize].position == start {
+                border_loops.push(border_loop);
+            }
+        }
+        border_loops
+    }
+
+    // An edge (from the given node to its relative) is a border if no other face contains it
+    fn is_border_edge(&self, node_index: u32) -> bool {
+        let pos_b = self.nodes[self.nodes[node_index as usize].relative as usize].position;
+        let mut face_count = 0;
+        loop_siblings!(node_index, self.nodes, sibling, {
+            let next = self.nodes[sibling as usize].relative;
+            let previous = self.nodes[next as usize].relative;
+            if !self.nodes[sibling as usize].is_removed
+                && (self.nodes[next as usize].position == pos_b || self.nodes[previous as usize].position == pos_b) {
+                face_count += 1;
+            }
+        });
+        face_count == 1
+    }
+
+    // Returns triangles as indices in the border loop
+    #[allow(clippy::needless_range_loop)]
+    fn triangulate_minimum_weight(&self, border_loop: &[u32]) -> Vec<[usize; 3]> {
+        let n = border_loop.len();
+        let get_position = |x: usize| self.positions[self.nodes[border_loop[x] as usize].position as usize];
+
+        // Normal of the face bordering the edge from x to x + 1
+        let border_normals: Vec<DVec3> = (0..n).map(|x| {
+            let node_c = self.nodes[self.nodes[border_loop[x] as usize].relative as usize].relative;
+            let pos_c = self.positions[self.nodes[node_c as usize].position as usize];
+            (get_position((x + 1) % n) - get_position(x)).cross(&(pos_c - get_position(x)))
+        }).collect();
+        // Patch triangles go the other way around, see fill_holes
+        let get_normal = |i: usize, m: usize, k: usize| (get_position(m) - get_position(k)).cross(&(get_position(i) - get_position(k)));
+        let get_dihedral = |a: &DVec3, b: &DVec3| a.angle(b);
+
+        // weights[i][k] is the (largest dihedral angle, area) of the best triangulation of the polygon from i to k,
+        // whose last triangle has its third corner at middles[i][k]
+        let mut weights = vec![vec![(0.0, 0.0); n]; n];
+        let mut middles = vec![vec![usize::MAX; n]; n];
+        for length in 2..n {
+            for i in 0..n - length {
+                let k = i + length;
+                let mut best = (f64::MAX, f64::MAX);
+                for m in i + 1..k {
+                    let normal = get_normal(i, m, k);
+                    // Dihedral angles with the triangles (or faces) on the two other sides
+                    let mut angle: f64 = 0.0;
+                    for (a, b) in [(i, m), (m, k)] {
+                        let neighbor_normal = if b == a + 1 {
+                            border_normals[a]
+                        } else {
+                            get_normal(a, middles[a][b], b)
+                        };
+                        angle = angle.max(get_dihedral(&normal, &neighbor_normal));
+                    }
+                    if i == 0 && k == n - 1 {
+                        angle = angle.max(get_dihedral(&normal, &border_normals[n - 1]));
+                    }
+                    let weight = (
+                        angle.max(weights[i][m].0).max(weights[m][k].0),
+                        0.5 * normal.magnitude() + weights[i][m].1 + weights[m][k].1);
+                    if weight < best {
+                        best = weight;
+                        middles[i][k] = m;
+                    }
+                }
+                weights[i][k] = best;
+            }
+        }
+
+        let mut triangles = Vec::with_capacity(n - 2);
+        let mut ranges = vec![(0, n - 1)];
+        while let Some((i, k)) = ranges.pop() {
+            if k - i < 2 {
+                continue;
+            }
+            let m = middles[i][k];
+            triangles.push([i, m, k]);
+            ranges.push((i, m));
+            ranges.push((m, k));
+        }
+        triangles
+    }
+
+    // Returns triangles as indices in the border loop, or None if the projected border is not a simple polygon
+    fn triangulate_projected(&self, border_loop: &[u32]) -> Option<Vec<[usize; 3]>> {
+        let points: Vec<DVec3> = border_loop.iter().map(|node| self.positions[self.nodes[*node as usize].position as usize]).collect();
+
+        // Best-fit plane normal of the border with Newell's method
+        let mut normal = DVec3::default();
+        for (a, b) in points.iter().zip(points.iter().cycle().skip(1)) {
+            normal += DVec3::new((a.y - b.y) * (a.z + b.z), (a.z - b.z) * (a.x + b.x), (a.x - b.x) * (a.y + b.y));
+        }
+        let normal = normal.try_normalize(0.0)?;
+        let axis = if normal.x.abs() < 0.9 { DVec3::new(1., 0., 0.) } else { DVec3::new(0., 1., 0.) };
+        let u = normal.cross(&axis).normalize();
+        let v = normal.cross(&u);
+
+        let projected: Vec<(f64, f64)> = points.iter().map(|p| (p.dot(&u), p.dot(&v))).collect();
+        let contour: Vec<usize> = (0..points.len()).chain(std::iter::once(0)).collect();
+        let triangles = cdt::triangulate_contours(&projected, &[contour]).ok()?;
+        if triangles.len() != points.len() - 2 {
+            return None;
+        }
+
+        // Triangles are expected in the same order as the border, as the ones from triangulate_minimum_weight
+        Some(triangles.iter()
+            .map(|(a, b, c)| {
+                let triangle_normal = (points[*b] - points[*a]).cross(&(points[*c] - points[*a]));
+                if triangle_normal.dot(&normal) >= 0.0 { [*a, *b, *c] } else { [*a, *c, *b] }
+            })
+            .collect())
+    }
+}
+
+// Liepa's refinement: splits patch triangles at their centroid until their edges are about the target length,
+// then flips edges until the patch is Delaunay
+fn refine_patch(patch: &mut Vec<[[u32; 4]; 3]>, positions: &mut Vec<DVec3>, attributes: &mut CornerAttributes, target_length: f64) {
+    const MAX_ROUNDS: u32 = 16;
+    for _ in 0..MAX_ROUNDS {
+        let mut is_split = false;
+        let mut refined = Vec::with_capacity(patch.len());
+        for triangle in patch.iter() {
+            let points = triangle.map(|corner| positions[corner[0] as usize]);
+            let longest = (0..3).map(|x| (points[x] - points[(x + 1) % 3]).magnitude()).fold(0.0, f64::max);
+            if longest <= std::f64::consts::SQRT_2 * target_length {
+                refined.push(*triangle);
+                continue;
+            }
+            positions.push((points[0] + points[1] + points[2]) / 3.0);
+            let center = attributes.blend((positions.len() - 1) as u32, &triangle.map(|corner| (corner, 2)));
+            for x in 0..3 {
+                refined.push([triangle[x], triangle[(x + 1) % 3], center]);
+            }
+            is_split = true;
+        }
+        *patch = refined;
+        flip_patch_to_delaunay(patch, positions);
+        if !is_split {
+            break;
+        }
+    }
+}
+
+fn flip_patch_to_delaunay(patch: &mut [[[u32; 4]; 3]], positions: &[DVec3]) {
+    const MAX_PASSES: u32 = 16;
+    for _ in 0..MAX_PASSES {
+        // Triangles on each side of every edge, with the corner the edge starts at
+        let mut edge_triangles = HashMap::<u64, Vec<(usize, usize)>>::new();
+        for (t, triangle) in patch.iter().enumerate() {
+            for x in 0..3 {
+                edge_triangles.entry(Edge::new(triangle[x][0], triangle[(x + 1) % 3][0]).get_key()).or_default().push((t, x));
+            }
+        }
+        let mut edges: Vec<(u64, Vec<(usize, usize)>)> = edge_triangles.into_iter().filter(|x| x.1.len() == 2).collect();
+        edges.sort_unstable_by_key(|x| x.0);
+
+        let mut is_flipped = vec![false; patch.len()];
+        let mut flip_count = 0;
+        for (_, sides) in edges.iter() {
+            let ((t_ab, x_ab), (t_ba, x_ba)) = (sides[0], sides[1]);
+            // Each triangle is flipped at most once per pass, as the edge lists would be outdated
+            if is_flipped[t_ab] || is_flipped[t_ba] {
+                continue;
+            }
+            let corner_a = patch[t_ab][x_ab];
+            let corner_b = patch[t_ab][(x_ab + 1) % 3];
+            let corner_c = patch[t_ab][(x_ab + 2) % 3];
+            let corner_d = patch[t_ba][(x_ba + 2) % 3];
+            let [a, b, c, d] = [corner_a, corner_b, corner_c, corner_d].map(|corner| positions[corner[0] as usize]);
+
+            // Locally Delaunay if the angles opposite to the edge sum up to less than π
+            if (a - c).angle(&(b - c)) + (a - d).angle(&(b - d)) <= std::f64::consts::PI + 1e-9 {
+                continue;
+            }
+            // The quad must be convex, or one of the new triangles would be flipped
+            let normal = (b - a).cross(&(c - a)) + (a - b).cross(&(d - b));
+            if (d - a).cross(&(c - a)).dot(&normal) <= 0.0 || (b - d).cross(&(c - d)).dot(&normal) <= 0.0 {
+                continue;
+            }
+            patch[t_ab] = [corner_a, corner_d, corner_c];
+            patch[t_ba] = [corner_d, corner_b, corner_c];
+            is_flipped[t_ab] = true;
+            is_flipped[t_ba] = true;
+            flip_count += 1;
+        }
+        if flip_count == 0 {
+            break;
+        }
+    }
+}
+
+// Moves positions added to the patch to the average of their neighbors (membrane fairing), border positions staying in place
+fn fair_patch(patch: &[[[u32; 4]; 3]], positions: &mut [DVec3], first_new_position: u32) {
+    const ITERATIONS: u32 = 100;
+    let mut neighbors = HashMap::<u32, Vec<u32>>::new();
+    for triangle in patch.iter() {
+        for x in 0..3 {
+            let (a, b) = (triangle[x][0], triangle[(x + 1) % 3][0]);
+            for (from, to) in [(a, b), (b, a)] {
+                if from >= first_new_position {
+                    let list = neighbors.entry(from).or_insert_with(Vec::new);
+                    if !list.contains(&to) {
+                        list.push(to);
+                    }
+                }
+            }
+        }
+    }
+    let mut neighbors: Vec<(u32, Vec<u32>)> = neighbors.into_iter().collect();
+    neighbors.sort_unstable_by_key(|x| x.0);
+
+    for _ in 0..ITERATIONS {
+        for (position, list) in neighbors.iter() {
+            let sum = list.iter().fold(DVec3::default(), |sum, x| sum + positions[*x as usize]);
+            positions[*position as usize] = sum / list.len() as f64;
+        }
+    }
+}
+
+#[cfg(test)]
+mod holes_tests {
+    use super::*;
+    use crate::mesh::test_utils::read_sphere;
+
+    // Sphere without the faces whose centroid passes the filter
+    fn read_holed_sphere<F: Fn(&DVec3) -> bool>(filter: F) -> (ConnectedMesh, usize) {
+        let mut shared_mesh = read_sphere();
+        let positions = shared_mesh.positions.clone();
+        let face_count = shared_mesh.triangles.len();
+        shared_mesh.triangles.retain(|t| !filter(&((positions[t[0] as usize] + positions[t[1] as usize] + positions[t[2] as usize]) / 3.0)));
+        let removed = face_count - shared_mesh.triangles.len();
+        (ConnectedMesh::from(&shared_mesh), removed)
+    }
+
+    fn assert_closed(connected_mesh: &ConnectedMesh) {
+        assert!(connected_mesh.get_border_loops().is_empty());
+        for i in 0..connected_mesh.nodes.len() as u32 {
+            assert!(connected_mesh.get_twin(i).is_some());
+        }
+    }
+
+    #[test]
+    fn find_border_loops() {
+        let (connected_mesh, _) = read_holed_sphere(|c| c.z > 0.97 || c.x > 0.97);
+        let border_loops = connected_mesh.get_border_loops();
+        assert_eq!(border_loops.len(), 2);
+        for border_loop in border_loops.iter() {
+            assert!(border_loop.len() >= 5);
+        }
+        // Both holes are planar enough not to fall back to minimum weight triangulation
+        for border_loop in connected_mesh.get_border_loop_nodes().iter() {
+            assert_eq!(connected_mesh.triangulate_projected(border_loop).unwrap().len(), border_loop.len() - 2);
+        }
+    }
+
+    #[test]
+    fn fill_small_holes() {
+        for triangulation in [HoleTriangulation::MinimumWeight, HoleTriangulation::PlanarProjection] {
+            let (mut connected_mesh, removed) = read_holed_sphere(|c| c.z > 0.97 || c.x > 0.97);
+            let border_length: usize = connected_mesh.get_border_loops().iter().map(|x| x.len()).sum();
+
+            assert_eq!(connected_mesh.fill_holes(100, triangulation, false), 2);
+            assert_closed(&connected_mesh);
+            // A hole bordered by n edges is filled with n - 2 triangles
+            assert_eq!(connected_mesh.face_count() as usize, 1280 - removed + border_length - 4);
+        }
+    }
+
+    #[test]
+    fn fill_and_fair_large_hole() {
+        let (mut connected_mesh, _) = read_holed_sphere(|c| c.z > 0.6);
+        let border_loops = connected_mesh.get_border_loops();
+        assert_eq!(border_loops.len(), 1);
+        let position_count = connected_mesh.positions.len();
+
+        // Too large
+        assert_eq!(connected_mesh.fill_holes(border_loops[0].len() - 1, HoleTriangulation::PlanarProjection, true), 0);
+        assert_eq!(connected_mesh.get_border_loops().len(), 1);
+
+        assert_eq!(connected_mesh.fill_holes(border_loops[0].len(), HoleTriangulation::PlanarProjection, true), 1);
+        assert_closed(&connected_mesh);
+        // The patch is refined, and the membrane spans the border
+        assert!(connected_mesh.positions.len() > position_count + 10);
+        let border_height = border_loops[0].iter().map(|x| connected_mesh.positions[*x as usize].z).fold(f64::MAX, f64::min);
+        for position in connected_mesh.positions[position_count..].iter() {
+            assert!(position.z >= border_height - 1e-9 && position.z < 1.0);
+        }
+    }
+}
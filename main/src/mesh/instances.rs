@@ -0,0 +1,390 @@
+use nalgebra_glm as glm;
+use glm::{DMat3, DMat4, DVec3, DVec4, U32Vec3};
+use hashbrown::HashMap;
+use super::{Group, SharedMesh};
+use super::topology::UnionFind;
+
+/// Copies of a prototype mesh, as found by `SharedMesh::find_instances`
+#[derive(Debug, Clone)]
+pub struct Instances {
+    /// First occurrence, left where it was. Other occurrences may list their vertices and triangles in another order,
+    /// which isn't kept: they are all described by the prototype and their transform.
+    pub prototype: SharedMesh,
+    /// Rigid transforms from the prototype to every occurrence, starting with the identity for the prototype itself
+    pub transforms: Vec<DMat4>,
+}
+
+impl Instances {
+    /// Every occurrence in a single mesh, as it was before instancing
+    pub fn to_mesh(&self) -> SharedMesh {
+        let prototype = &self.prototype;
+        let vertex_count = prototype.positions.len();
+        let mut shared_mesh = SharedMesh {
+            groups: Vec::new(),
+            triangles: Vec::with_capacity(prototype.triangles.len() * self.transforms.len()),
+            positions: Vec::with_capacity(vertex_count * self.transforms.len()),
+            normals: prototype.normals.as_ref().map(|_| Vec::new()),
+            colors: prototype.colors.as_ref().map(|_| Vec::new()),
+            uvs: prototype.uvs.as_ref().map(|_| Vec::new()),
+        };
+        for transform in self.transforms.iter() {
+            let offset = shared_mesh.positions.len() as u32;
+            let first_index = shared_mesh.triangles.len() as u32 * 3;
+            shared_mesh.groups.extend(prototype.groups.iter().map(|group| Group::new(first_index + group.first_index(), group.index_count())));
+            shared_mesh.positions.extend(prototype.positions.iter().map(|p| (transform * DVec4::new(p.x, p.y, p.z, 1.0)).xyz()));
+            shared_mesh.triangles.extend(prototype.triangles.iter().map(|t| t.add_scalar(offset)));
+            if let (Some(normals), Some(prototype_normals)) = (shared_mesh.normals.as_mut(), prototype.normals.as_ref()) {
+                normals.extend(prototype_normals.iter().map(|n| (transform * glm::vec3_to_vec4(n)).xyz()));
+            }
+            if let (Some(colors), Some(prototype_colors)) = (shared_mesh.colors.as_mut(), prototype.colors.as_ref()) {
+                colors.extend_from_slice(prototype_colors);
+            }
+            if let (Some(uvs), Some(prototype_uvs)) = (shared_mesh.uvs.as_mut(), prototype.uvs.as_ref()) {
+                uvs.extend_from_slice(prototype_uvs);
+            }
+        }
+        shared_mesh
+    }
+}
+
+impl SharedMesh {
+    /// Splits the mesh into its connected components (triangles sharing vertices), in the order of their first triangle.
+    /// Components only keep the vertices they use, and the parts of the groups they cover.
+    pub fn split_components(&self) -> Vec<SharedMesh> {
+        let mut vertex_components = UnionFind::new(self.positions.len());
+        for triangle in self.triangles.iter() {
+            vertex_components.union(triangle[0], triangle[1]);
+            vertex_components.union(triangle[0], triangle[2]);
+        }
+
+        // Triangles of every component, numbered by first appearance
+        let mut component_indices: HashMap<u32, usize> = HashMap::new();
+        let mut component_triangles: Vec<Vec<usize>> = Vec::new();
+        for (t, triangle) in self.triangles.iter().enumerate() {
+            let root = vertex_components.find(triangle[0]);
+            let component = *component_indices.entry(root).or_insert_with(|| {
+                component_triangles.push(Vec::new());
+                component_triangles.len() - 1
+            });
+            component_triangles[component].push(t);
+        }
+
+        let mut new_indices = vec![u32::MAX; self.positions.len()];
+        component_triangles.iter().map(|triangles| self.extract_triangles(triangles, &mut new_indices)).collect()
+    }
+
+    /// Finds connected components that are copies of each other up to a rigid transform (no scaling nor mirroring),
+    /// within `tolerance`. Copies may list their vertices and triangles in any order, but must have the same triangles
+    /// with the same winding. Copies in the same order, as duplicated geometry is, are matched fastest.
+    /// Every component ends up in exactly one `Instances`, in the order of their first triangle.
+    pub fn find_instances(&self, tolerance: f64) -> Vec<Instances> {
+        // Components with the same signature are hashed together, then compared in their canonical poses
+        let mut buckets: HashMap<Vec<u32>, Vec<usize>> = HashMap::new();
+        let mut instances: Vec<(Instances, CanonicalPose)> = Vec::new();
+        for component in self.split_components() {
+            let candidates = buckets.entry(get_signature(&component)).or_default();
+            let found = candidates.iter().find_map(|&i| {
+                let (prototype, pose) = &instances[i];
+                pose.match_copy(&prototype.prototype, &component, tolerance).map(|transform| (i, transform))
+            });
+            match found {
+                Some((i, transform)) => instances[i].0.transforms.push(transform),
+                None => {
+                    candidates.push(instances.len());
+                    let pose = CanonicalPose::new(&component.positions);
+                    instances.push((Instances { prototype: component, transforms: vec![DMat4::identity()] }, pose));
+                }
+            }
+        }
+        instances.into_iter().map(|(instances, _)| instances).collect()
+    }
+
+    // Copy of some triangles with their own vertices. `new_indices` must be filled with u32::MAX, and is left so.
+    pub(crate) fn extract_triangles(&self, triangles: &[usize], new_indices: &mut [u32]) -> SharedMesh {
+        let mut vertices = Vec::new();
+        let new_triangles = triangles.iter().map(|&t| self.triangles[t].map(|v| {
+            if new_indices[v as usize] == u32::MAX {
+                new_indices[v as usize] = vertices.len() as u32;
+                vertices.push(v as usize);
+            }
+            new_indices[v as usize]
+        })).collect();
+        for &v in vertices.iter() {
+            new_indices[v] = u32::MAX;
+        }
+
+        // Triangles keep their order, so the part of a group in the component is contiguous
+        let groups = self.groups.iter().filter_map(|group| {
+            let range = group.triangle_range();
+            let first = triangles.partition_point(|&t| t < range.start);
+            let count = triangles.partition_point(|&t| t < range.end) - first;
+            if count > 0 { Some(Group::new(first as u32 * 3, count as u32 * 3)) } else { None }
+        }).collect();
+
+        SharedMesh {
+            groups,
+            triangles: new_triangles,
+            positions: vertices.iter().map(|&v| self.positions[v]).collect(),
+            normals: extract_attribute(&self.normals, &vertices),
+            colors: extract_attribute(&self.colors, &vertices),
+            uvs: extract_attribute(&self.uvs, &vertices),
+        }
+    }
+}
+
+// Sorted numbers of triangles around every vertex, which depend neither on the pose nor on the order of vertices
+fn get_signature(shared_mesh: &SharedMesh) -> Vec<u32> {
+    let mut valences = vec![0; shared_mesh.positions.len()];
+    for triangle in shared_mesh.triangles.iter() {
+        for x in 0..3 {
+            valences[triangle[x] as usize] += 1;
+        }
+    }
+    valences.sort_unstable();
+    valences
+}
+
+// Values of an attribute for some vertices, if it has values for all of them
+fn extract_attribute<T: Copy>(attribute: &Option<Vec<T>>, vertices: &[usize]) -> Option<Vec<T>> {
+    attribute.as_ref()
+        .filter(|values| vertices.iter().all(|&v| v < values.len()))
+        .map(|values| vertices.iter().map(|&v| values[v]).collect())
+}
+
+// Frame of a prototype, centered on the average of its vertices and oriented by two of its vertices chosen to be far
+// from the center and from each other. Copies are posed with the same vertices, which is unambiguous even for symmetric shapes.
+#[derive(Debug)]
+struct CanonicalPose {
+    center: DVec3,
+    rotation: DMat3,
+    vertices: [usize; 2],
+}
+
+impl CanonicalPose {
+    fn new(positions: &[DVec3]) -> Self {
+        let center = get_center(positions);
+        let first = (0..positions.len())
+            .max_by(|&a, &b| (positions[a] - center).magnitude_squared().total_cmp(&(positions[b] - center).magnitude_squared()))
+            .unwrap_or(0);
+        let axis = (positions[first] - center).try_normalize(f64::MIN_POSITIVE).unwrap_or(DVec3::x());
+        let perpendicular = |p: &DVec3| { let offset = p - center; offset - axis * axis.dot(&offset) };
+        let second = (0..positions.len())
+            .max_by(|&a, &b| perpendicular(&positions[a]).magnitude_squared().total_cmp(&perpendicular(&positions[b]).magnitude_squared()))
+            .unwrap_or(0);
+        let vertices = [first, second];
+        CanonicalPose { center, rotation: get_rotation(positions, &center, &vertices), vertices }
+    }
+
+    // Transform from the prototype to the component, if the component is a copy of it
+    fn match_copy(&self, prototype: &SharedMesh, component: &SharedMesh, tolerance: f64) -> Option<DMat4> {
+        if prototype.positions.len() != component.positions.len() || prototype.triangles.len() != component.triangles.len() {
+            return None;
+        }
+        let center = get_center(&component.positions);
+        let tolerance_squared = tolerance * tolerance;
+        if prototype.triangles == component.triangles {
+            // Vertex for vertex, posed with the same canonical vertices
+            let rotation = get_rotation(&component.positions, &center, &self.vertices) * self.rotation.transpose();
+            let is_copy = prototype.positions.iter().zip(component.positions.iter())
+                .all(|(p, q)| (rotation * (p - self.center) + center - q).magnitude_squared() <= tolerance_squared);
+            if is_copy {
+                return Some(get_transform(&rotation, &self.center, &center));
+            }
+        }
+
+        // In another order, the canonical vertices are looked for by their distances to the center and to each other,
+        // then every vertex is matched to the closest one at its place
+        let [first, second] = self.vertices.map(|v| prototype.positions[v]);
+        let (first_radius, second_radius) = ((first - self.center).magnitude(), (second - self.center).magnitude());
+        let separation = (first - second).magnitude();
+        let is_close = |a: f64, b: f64| (a - b).abs() <= 2.0 * tolerance;
+        let grid = PointGrid::new(&component.positions, tolerance);
+        let expected_triangles = get_sorted_triangles(component.triangles.iter().copied());
+        for q1 in (0..component.positions.len()).filter(|&q| is_close((component.positions[q] - center).magnitude(), first_radius)) {
+            for q2 in 0..component.positions.len() {
+                let p2 = component.positions[q2];
+                if !is_close((p2 - center).magnitude(), second_radius) || !is_close((p2 - component.positions[q1]).magnitude(), separation) {
+                    continue;
+                }
+                let rotation = get_rotation(&component.positions, &center, &[q1, q2]) * self.rotation.transpose();
+                let Some(mapping) = grid.match_points(prototype.positions.iter().map(|p| rotation * (p - self.center) + center)) else {
+                    continue;
+                };
+                let triangles = prototype.triangles.iter().map(|t| t.map(|v| mapping[v as usize]));
+                if get_sorted_triangles(triangles) == expected_triangles {
+                    return Some(get_transform(&rotation, &self.center, &center));
+                }
+            }
+        }
+        None
+    }
+}
+
+// Points hashed in cells as large as the tolerance, to find the closest ones within the tolerance
+struct PointGrid<'a> {
+    positions: &'a [DVec3],
+    cells: HashMap<[i64; 3], Vec<u32>>,
+    tolerance: f64,
+    cell_size: f64,
+}
+
+impl<'a> PointGrid<'a> {
+    fn new(positions: &'a [DVec3], tolerance: f64) -> Self {
+        let cell_size = tolerance.max(f64::MIN_POSITIVE);
+        let mut cells: HashMap<[i64; 3], Vec<u32>> = HashMap::new();
+        for (i, p) in positions.iter().enumerate() {
+            cells.entry(get_cell(p, cell_size)).or_default().push(i as u32);
+        }
+        PointGrid { positions, cells, tolerance, cell_size }
+    }
+
+    // Matches every point to a distinct position within the tolerance, returning the positions matched
+    fn match_points(&self, points: impl Iterator<Item = DVec3>) -> Option<Vec<u32>> {
+        let mut is_used = vec![false; self.positions.len()];
+        points.map(|point| {
+            let cell = get_cell(&point, self.cell_size);
+            let mut closest: Option<(u32, f64)> = None;
+            for offset in 0..27 {
+                let neighbor = [offset % 3, offset / 3 % 3, offset / 9].map(|x| x as i64 - 1);
+                let indices = self.cells.get(&[0, 1, 2].map(|x| cell[x].saturating_add(neighbor[x])));
+                for &i in indices.into_iter().flatten() {
+                    let distance = (self.positions[i as usize] - point).magnitude();
+                    if !is_used[i as usize] && distance <= self.tolerance && closest.is_none_or(|(_, d)| distance < d) {
+                        closest = Some((i, distance));
+                    }
+                }
+            }
+            let (i, _) = closest?;
+            is_used[i as usize] = true;
+            Some(i)
+        }).collect()
+    }
+}
+
+fn get_cell(p: &DVec3, cell_size: f64) -> [i64; 3] {
+    [p.x, p.y, p.z].map(|x| (x / cell_size).floor() as i64)
+}
+
+// Triangles starting with their smallest vertex, which keeps their winding, then sorted
+fn get_sorted_triangles(triangles: impl Iterator<Item = U32Vec3>) -> Vec<[u32; 3]> {
+    let mut triangles: Vec<[u32; 3]> = triangles.map(|t| {
+        let x = (0..3).min_by_key(|&x| t[x]).unwrap_or(0);
+        [t[x], t[(x + 1) % 3], t[(x + 2) % 3]]
+    }).collect();
+    triangles.sort_unstable();
+    triangles
+}
+
+fn get_transform(rotation: &DMat3, prototype_center: &DVec3, center: &DVec3) -> DMat4 {
+    let mut transform = glm::mat3_to_mat4(rotation);
+    let translation = center - rotation * prototype_center;
+    transform.set_column(3, &DVec4::new(translation.x, translation.y, translation.z, 1.0));
+    transform
+}
+
+fn get_center(positions: &[DVec3]) -> DVec3 {
+    positions.iter().fold(DVec3::zeros(), |sum, p| sum + p) / positions.len().max(1) as f64
+}
+
+// Orthonormal frame, with its first axis toward the first vertex and its second one toward the second vertex
+fn get_rotation(positions: &[DVec3], center: &DVec3, vertices: &[usize; 2]) -> DMat3 {
+    let x = (positions[vertices[0]] - center).try_normalize(f64::MIN_POSITIVE).unwrap_or(DVec3::x());
+    let offset = positions[vertices[1]] - center;
+    let y = (offset - x * x.dot(&offset)).try_normalize(f64::MIN_POSITIVE)
+        .unwrap_or_else(|| if x.x.abs() < 0.9 { DVec3::x() } else { DVec3::y() }.cross(&x).normalize());
+    DMat3::from_columns(&[x, y, x.cross(&y)])
+}
+
+#[cfg(test)]
+mod instances_tests {
+    use crate::mesh::*;
+    use glm::{DMat4, DVec4};
+    use crate::mesh::test_utils::read_sphere;
+
+    fn transform(shared_mesh: &SharedMesh, transform: &DMat4) -> SharedMesh {
+        SharedMesh {
+            positions: shared_mesh.positions.iter().map(|p| (transform * DVec4::new(p.x, p.y, p.z, 1.0)).xyz()).collect(),
+            triangles: shared_mesh.triangles.clone(),
+            groups: Vec::new(),
+            normals: None,
+            colors: None,
+            uvs: None,
+        }
+    }
+
+    #[test]
+    fn split_components() {
+        let sphere = read_sphere();
+        let mut shared_mesh = SharedMesh::combine(read_sphere(), transform(&sphere, &glm::translation(&DVec3::new(3., 0., 0.))));
+        // Unused vertex, and a group straddling both spheres
+        shared_mesh.positions.push(DVec3::zeros());
+        shared_mesh.groups = vec![Group::new(0, 3000), Group::new(3000, 2 * 3 * 1280 - 3000)];
+
+        let components = shared_mesh.split_components();
+        assert_eq!(components.len(), 2);
+        for (i, component) in components.iter().enumerate() {
+            assert_eq!(component.positions.len(), 642);
+            assert_eq!(component.triangles, components[0].triangles);
+            assert_eq!(component.triangles.len(), sphere.triangles.len());
+            let center = component.positions.iter().fold(DVec3::zeros(), |sum, p| sum + p) / 642.;
+            assert!((center - DVec3::new(3. * i as f64, 0., 0.)).magnitude() < 1e-6);
+        }
+        assert_eq!(components[0].groups, vec![Group::new(0, 3000), Group::new(3000, 3 * 1280 - 3000)]);
+        assert_eq!(components[1].groups, vec![Group::new(0, 3 * 1280)]);
+    }
+
+    #[test]
+    fn find_instances() {
+        let sphere = read_sphere();
+        let transforms = [
+            glm::translation(&DVec3::new(5., 0., 0.)),
+            glm::translation(&DVec3::new(0., -7., 2.)) * glm::rotation(1.2, &DVec3::new(1., 2., 3.)),
+            glm::rotation(std::f64::consts::PI, &DVec3::z()),
+        ];
+        let mut shared_mesh = read_sphere();
+        for matrix in transforms.iter() {
+            shared_mesh = SharedMesh::combine(shared_mesh, transform(&sphere, matrix));
+        }
+        // Neither a scaled nor a mirrored sphere is a copy
+        shared_mesh = SharedMesh::combine(shared_mesh, transform(&sphere, &glm::scaling(&DVec3::new(1.01, 1., 1.))));
+        shared_mesh = SharedMesh::combine(shared_mesh, transform(&sphere, &glm::scaling(&DVec3::new(-1., 1., 1.))));
+
+        let instances = shared_mesh.find_instances(1e-6);
+        assert_eq!(instances.iter().map(|i| i.transforms.len()).collect::<Vec<_>>(), vec![4, 1, 1]);
+        assert_eq!(instances[0].transforms[0], DMat4::identity());
+        for (found, expected) in instances[0].transforms[1..].iter().zip(transforms.iter()) {
+            assert!((found - expected).abs().max() < 1e-9, "{} != {}", found, expected);
+        }
+
+        // Instantiating the prototype gives the original spheres back
+        let flattened = instances[0].to_mesh();
+        assert_eq!(flattened.triangles.len(), 4 * 1280);
+        for (t1, t2) in flattened.triangles.iter().zip(shared_mesh.triangles.iter()) {
+            for x in 0..3 {
+                assert!((flattened.positions[t1[x] as usize] - shared_mesh.positions[t2[x] as usize]).magnitude() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn find_reordered_instances() {
+        let sphere = read_sphere();
+        // Same sphere moved, with its vertices, its triangles and the corners of its triangles in other orders
+        let mut reordered = transform(&sphere, &(glm::translation(&DVec3::new(4., 1., 0.)) * glm::rotation(0.7, &DVec3::new(0., 1., 1.))));
+        let vertex_count = reordered.positions.len() as u32;
+        reordered.positions.reverse();
+        reordered.triangles = sphere.triangles.iter().rev().map(|t| U32Vec3::new(t[1], t[2], t[0]).map(|v| vertex_count - 1 - v)).collect();
+        let mut mirrored = reordered.clone();
+        mirrored.triangles.iter_mut().for_each(|t| t.swap_rows(1, 2));
+
+        let shared_mesh = SharedMesh::combine(SharedMesh::combine(read_sphere(), reordered.clone()), mirrored);
+        let instances = shared_mesh.find_instances(1e-6);
+        assert_eq!(instances.iter().map(|i| i.transforms.len()).collect::<Vec<_>>(), vec![2, 1]);
+
+        // The prototype is moved onto the copy, though not necessarily with the same rotation since a sphere is symmetric
+        let flattened = instances[0].to_mesh();
+        for p in flattened.positions[sphere.positions.len()..].iter() {
+            assert!(reordered.positions.iter().any(|q| (p - q).magnitude() < 1e-6));
+        }
+    }
+}
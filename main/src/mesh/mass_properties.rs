@@ -0,0 +1,231 @@
+use nalgebra_glm as glm;
+use glm::{DMat3, DVec3, U32Vec3};
+use super::SharedMesh;
+use super::topology::*;
+
+/// Area, volume and inertia of a solid bounded by triangles
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MassProperties {
+    pub area: f64,
+    /// Negative when triangles face inwards
+    pub volume: f64,
+    /// Volume times density
+    pub mass: f64,
+    /// Center of mass, or the center of the surface when there is no volume
+    pub centroid: DVec3,
+    /// Inertia tensor about the center of mass
+    pub inertia: DMat3,
+    /// Whether every edge has exactly two triangles walking it in opposite directions.
+    /// Volume, mass and inertia of open or inconsistent surfaces are meaningless.
+    pub is_closed: bool,
+}
+
+impl MassProperties {
+    /// Same solid, made of a material of the given density
+    pub fn with_density(&self, density: f64) -> Self {
+        MassProperties {
+            mass: self.volume * density,
+            inertia: self.inertia * (density * self.volume / self.mass_or_volume()),
+            ..*self
+        }
+    }
+
+    // Inertia is proportional to mass, which is the volume until a density is given
+    fn mass_or_volume(&self) -> f64 {
+        if self.mass != 0.0 { self.mass } else { 1.0 }
+    }
+
+    /// Properties of several solids taken together, with the parallel axis theorem
+    pub fn combine(parts: &[MassProperties]) -> MassProperties {
+        let area = parts.iter().map(|part| part.area).sum();
+        let volume = parts.iter().map(|part| part.volume).sum();
+        let mass: f64 = parts.iter().map(|part| part.mass).sum();
+        let centroid = if mass != 0.0 {
+            parts.iter().fold(DVec3::zeros(), |sum, part| sum + part.centroid * part.mass) / mass
+        } else {
+            parts.iter().fold(DVec3::zeros(), |sum, part| sum + part.centroid * part.area) / f64::max(area, f64::MIN_POSITIVE)
+        };
+        let inertia = parts.iter().fold(DMat3::zeros(), |sum, part| {
+            let offset = part.centroid - centroid;
+            sum + part.inertia + (DMat3::identity() * offset.magnitude_squared() - offset * offset.transpose()) * part.mass
+        });
+        MassProperties { area, volume, mass, centroid, inertia, is_closed: parts.iter().all(|part| part.is_closed) }
+    }
+}
+
+impl SharedMesh {
+    /// Mass properties of the whole mesh, with a density of 1, using the divergence theorem
+    /// (Eberly, Polyhedral Mass Properties). See `MassProperties::with_density` for other materials.
+    /// Open meshes are not rejected: the `is_closed` field of the result is the only warning that its volume, mass
+    /// and inertia are meaningless, so check it before using them. The same goes for groups and components.
+    pub fn get_mass_properties(&self) -> MassProperties {
+        get_mass_properties(&self.positions, &self.triangles)
+    }
+
+    /// Mass properties of every group, with a density of 1
+    pub fn get_group_mass_properties(&self) -> Vec<MassProperties> {
+        self.groups.iter().map(|group| get_mass_properties(&self.positions, &self.triangles[group.triangle_range()])).collect()
+    }
+
+    /// Mass properties of every connected component (triangles connected through manifold edges), with a density of 1.
+    /// Components are in the order of their first triangle.
+    pub fn get_component_mass_properties(&self) -> Vec<MassProperties> {
+        let edges = build_edge_map(&self.triangles);
+        let (components, component_count) = get_components(&self.triangles, &edges);
+        let mut component_triangles = vec![Vec::new(); component_count];
+        for (triangle, component) in self.triangles.iter().zip(components.iter()) {
+            component_triangles[*component as usize].push(*triangle);
+        }
+        component_triangles.iter().map(|triangles| get_mass_properties(&self.positions, triangles)).collect()
+    }
+}
+
+// Sums of w0 + w1 + w2, of their squares and cubes, and per vertex terms of the products, for Eberly's integrals
+fn get_subexpressions(w0: f64, w1: f64, w2: f64) -> (f64, f64, f64, [f64; 3]) {
+    let temp0 = w0 + w1;
+    let f1 = temp0 + w2;
+    let temp1 = w0 * w0;
+    let temp2 = temp1 + w1 * temp0;
+    let f2 = temp2 + w2 * f1;
+    let f3 = w0 * temp1 + w1 * temp2 + w2 * f2;
+    (f1, f2, f3, [f2 + w0 * (f1 + w0), f2 + w1 * (f1 + w1), f2 + w2 * (f1 + w2)])
+}
+
+fn get_mass_properties(positions: &[DVec3], triangles: &[U32Vec3]) -> MassProperties {
+    let is_closed = is_closed(triangles, &build_edge_map(triangles));
+
+    // Integrals are computed relative to a point near the mesh, for precision far from the origin
+    let origin = match triangles.first() {
+        Some(triangle) => positions[triangle[0] as usize],
+        None => DVec3::zeros(),
+    };
+
+    // Integrals of 1, x, y, z, x², y², z², xy, yz and zx over the volume
+    let mut integrals = [0.0; 10];
+    let mut area = 0.0;
+    let mut area_centroid = DVec3::zeros();
+    for triangle in triangles {
+        let [p0, p1, p2] = [0, 1, 2].map(|x| positions[triangle[x] as usize] - origin);
+        let normal = (p1 - p0).cross(&(p2 - p0));
+        let triangle_area = 0.5 * normal.magnitude();
+        area += triangle_area;
+        area_centroid += (p0 + p1 + p2) * (triangle_area / 3.0);
+
+        let (f1x, f2x, f3x, gx) = get_subexpressions(p0.x, p1.x, p2.x);
+        let (_, f2y, f3y, gy) = get_subexpressions(p0.y, p1.y, p2.y);
+        let (_, f2z, f3z, gz) = get_subexpressions(p0.z, p1.z, p2.z);
+        integrals[0] += normal.x * f1x;
+        integrals[1] += normal.x * f2x;
+        integrals[2] += normal.y * f2y;
+        integrals[3] += normal.z * f2z;
+        integrals[4] += normal.x * f3x;
+        integrals[5] += normal.y * f3y;
+        integrals[6] += normal.z * f3z;
+        integrals[7] += normal.x * (p0.y * gx[0] + p1.y * gx[1] + p2.y * gx[2]);
+        integrals[8] += normal.y * (p0.z * gy[0] + p1.z * gy[1] + p2.z * gy[2]);
+        integrals[9] += normal.z * (p0.x * gz[0] + p1.x * gz[1] + p2.x * gz[2]);
+    }
+    let factors = [6.0, 24.0, 24.0, 24.0, 60.0, 60.0, 60.0, 120.0, 120.0, 120.0];
+    for (integral, factor) in integrals.iter_mut().zip(factors.iter()) {
+        *integral /= factor;
+    }
+
+    let volume = integrals[0];
+    if volume == 0.0 {
+        return MassProperties {
+            area,
+            volume,
+            mass: 0.0,
+            centroid: origin + if area > 0.0 { area_centroid / area } else { DVec3::zeros() },
+            inertia: DMat3::zeros(),
+            is_closed,
+        };
+    }
+
+    let c = DVec3::new(integrals[1], integrals[2], integrals[3]) / volume;
+    let xx = integrals[5] + integrals[6] - volume * (c.y * c.y + c.z * c.z);
+    let yy = integrals[4] + integrals[6] - volume * (c.z * c.z + c.x * c.x);
+    let zz = integrals[4] + integrals[5] - volume * (c.x * c.x + c.y * c.y);
+    let xy = -(integrals[7] - volume * c.x * c.y);
+    let yz = -(integrals[8] - volume * c.y * c.z);
+    let zx = -(integrals[9] - volume * c.z * c.x);
+    MassProperties {
+        area,
+        volume,
+        mass: volume,
+        centroid: origin + c,
+        inertia: DMat3::new(xx, xy, zx, xy, yy, yz, zx, yz, zz),
+        is_closed,
+    }
+}
+
+#[cfg(test)]
+mod mass_properties_tests {
+    use crate::mesh::*;
+    use crate::mesh::test_utils::{build_box, read_sphere};
+
+    fn assert_near(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn box_far_from_origin() {
+        let offset = DVec3::new(1000., -2000., 3000.);
+        let properties = build_box(offset, DVec3::new(1., 2., 3.)).get_mass_properties().with_density(2.0);
+
+        assert!(properties.is_closed);
+        assert_near(properties.area, 22.0);
+        assert_near(properties.volume, 6.0);
+        assert_near(properties.mass, 12.0);
+        assert!((properties.centroid - offset - DVec3::new(0.5, 1., 1.5)).magnitude() < 1e-9);
+        // m (b² + c²) / 12 around every axis
+        let expected = [12.0 * (4. + 9.) / 12., 12.0 * (1. + 9.) / 12., 12.0 * (1. + 4.) / 12.];
+        for (i, moment) in expected.iter().enumerate() {
+            for j in 0..3 {
+                assert!((properties.inertia[(i, j)] - if i == j { *moment } else { 0. }).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn sphere() {
+        let properties = read_sphere().get_mass_properties();
+        let volume = 4.0 / 3.0 * std::f64::consts::PI;
+        assert!(properties.is_closed);
+        assert!(properties.volume < volume && properties.volume > 0.97 * volume);
+        assert!(properties.centroid.magnitude() < 1e-6);
+        // 2/5 m r²
+        for i in 0..3 {
+            assert!((properties.inertia[(i, i)] / properties.mass - 0.4).abs() < 0.02);
+        }
+    }
+
+    #[test]
+    fn groups_components_and_combination() {
+        let mut shared_mesh = SharedMesh::combine(build_box(DVec3::zeros(), DVec3::new(1., 1., 1.)), build_box(DVec3::new(3., 0., 0.), DVec3::new(1., 1., 1.)));
+        shared_mesh.groups = vec![Group::new(0, 36), Group::new(36, 36)];
+
+        let groups = shared_mesh.get_group_mass_properties();
+        let components = shared_mesh.get_component_mass_properties();
+        assert_eq!(groups, components);
+        assert_eq!(components.len(), 2);
+        assert!((components[1].centroid - DVec3::new(3.5, 0.5, 0.5)).magnitude() < 1e-9);
+
+        // Parallel axis theorem: each cube is 1.5 away from the center along x
+        let whole = MassProperties::combine(&components);
+        let direct = shared_mesh.get_mass_properties();
+        assert!((whole.centroid - DVec3::new(2., 0.5, 0.5)).magnitude() < 1e-9);
+        assert!((whole.inertia - direct.inertia).abs().max() < 1e-9);
+        assert_near(whole.inertia[(1, 1)], 2.0 * (1.0 / 6.0 + 1.5 * 1.5));
+
+        // Denser second cube
+        let heavy = MassProperties::combine(&[components[0], components[1].with_density(3.0)]);
+        assert_near(heavy.mass, 4.0);
+        assert!((heavy.centroid - DVec3::new(2.75, 0.5, 0.5)).magnitude() < 1e-9);
+
+        // Open box
+        shared_mesh.triangles.truncate(23);
+        let components = shared_mesh.get_component_mass_properties();
+        assert!(components[0].is_closed && !components[1].is_closed);
+    }
+}
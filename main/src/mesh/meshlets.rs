@@ -0,0 +1,340 @@
+use nalgebra_glm as glm;
+use glm::DVec3;
+use super::SharedMesh;
+use crate::base::Box3;
+
+use std::io::{self, BufWriter};
+use std::io::prelude::*;
+
+// Meshlets are grown greedily, in the style of meshoptimizer: the next triangle is the one adjacent to the meshlet that adds
+// the fewest vertices, then the one that bends the normal cone the least. A meshlet is closed once the next triangle doesn't
+// fit anymore. When it has no adjacent triangle left, it continues from the next triangle in Morton order of the centroids,
+// which keeps meshlets of many small components (such as faces of CAD meshes, which don't share vertices) compact.
+
+const MAGIC: &[u8; 4] = b"NMML";
+
+/// Largest vertex count of a meshlet, as local indices are stored in a byte
+pub const MAX_MESHLET_VERTICES: usize = 256;
+
+/// Ranges of a meshlet in the buffers of `Meshlets`
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Meshlet {
+    /// First vertex in `Meshlets::vertices`
+    pub vertex_offset: u32,
+    /// First byte in `Meshlets::triangles`, 3 bytes per triangle
+    pub triangle_offset: u32,
+    pub vertex_count: u32,
+    pub triangle_count: u32,
+}
+
+/// Culling data of a meshlet, in single precision like it is uploaded.
+/// A meshlet can be skipped when its sphere is out of the frustum, or when it is back facing as a whole:
+/// `dot(normalize(cone_apex - camera_position), cone_axis) >= cone_cutoff`.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MeshletBounds {
+    pub center: [f32; 3],
+    pub radius: f32,
+    pub cone_apex: [f32; 3],
+    pub cone_axis: [f32; 3],
+    /// Sine of the half angle of the normal cone, or 1 when the normals are too spread for the meshlet to be culled
+    pub cone_cutoff: f32,
+}
+
+/// Meshlets of a mesh, as returned by `SharedMesh::build_meshlets`
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Meshlets {
+    pub meshlets: Vec<Meshlet>,
+    pub bounds: Vec<MeshletBounds>,
+    /// Vertices of the mesh used by every meshlet, in ranges given by `Meshlet`
+    pub vertices: Vec<u32>,
+    /// Local vertex indices of the triangles of every meshlet, relative to its first vertex
+    pub triangles: Vec<u8>,
+}
+
+impl SharedMesh {
+    /// Splits the triangles into meshlets of up to `max_vertices` vertices (at most `MAX_MESHLET_VERTICES`) and
+    /// `max_triangles` triangles, such as 64 and 124 for mesh shaders. `cone_weight` between 0 and 1 trades vertex reuse
+    /// for tighter normal cones, which cull better.
+    pub fn build_meshlets(&self, max_vertices: usize, max_triangles: usize, cone_weight: f64) -> Meshlets {
+        let max_vertices = max_vertices.clamp(3, MAX_MESHLET_VERTICES);
+        let max_triangles = max_triangles.max(1);
+        let normals: Vec<DVec3> = self.triangles.iter().map(|triangle| {
+            let [a, b, c] = [0, 1, 2].map(|x| self.positions[triangle[x] as usize]);
+            (b - a).cross(&(c - a)).try_normalize(0.0).unwrap_or_else(DVec3::zeros)
+        }).collect();
+
+        // Triangles of every vertex, in compressed rows
+        let mut offsets = vec![0; self.positions.len() + 1];
+        for triangle in self.triangles.iter() {
+            for x in 0..3 {
+                offsets[triangle[x] as usize + 1] += 1;
+            }
+        }
+        for v in 0..self.positions.len() {
+            offsets[v + 1] += offsets[v];
+        }
+        let mut vertex_triangles = vec![0; offsets[self.positions.len()]];
+        let mut next = offsets.clone();
+        for (t, triangle) in self.triangles.iter().enumerate() {
+            for x in 0..3 {
+                let v = triangle[x] as usize;
+                vertex_triangles[next[v]] = t;
+                next[v] += 1;
+            }
+        }
+
+        let seeds = self.get_morton_order();
+        let mut seed = 0;
+        let mut is_used = vec![false; self.triangles.len()];
+        let mut local_indices = vec![u8::MAX; self.positions.len()];
+        let mut is_local = vec![false; self.positions.len()];
+        let mut result = Meshlets::default();
+        let mut meshlet = Meshlet::default();
+        let mut cone = DVec3::zeros();
+        let mut candidates: Vec<usize> = Vec::new();
+
+        loop {
+            candidates.retain(|&t| !is_used[t]);
+            let axis = cone.try_normalize(0.0).unwrap_or_else(DVec3::zeros);
+            let best = candidates.iter().map(|&t| {
+                let new_vertices = (0..3).filter(|&x| !is_local[self.triangles[t][x] as usize]).count();
+                let spread = 1.0 - normals[t].dot(&axis);
+                (new_vertices as f64 + cone_weight * spread, t)
+            }).min_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1))).map(|(_, t)| t);
+
+            let t = match best {
+                Some(t) => t,
+                None => {
+                    while seed < seeds.len() && is_used[seeds[seed]] {
+                        seed += 1;
+                    }
+                    if seed == seeds.len() {
+                        break;
+                    }
+                    seeds[seed]
+                },
+            };
+
+            let triangle = self.triangles[t];
+            // Vertices repeated by degenerate triangles only count once
+            let new_vertices = (0..3).filter(|&x| !is_local[triangle[x] as usize] && !(0..x).any(|y| triangle[y] == triangle[x])).count();
+            if meshlet.vertex_count as usize + new_vertices > max_vertices || meshlet.triangle_count as usize >= max_triangles {
+                result.finish_meshlet(&mut meshlet, &mut is_local, &self.positions);
+                cone = DVec3::zeros();
+                candidates.clear();
+                continue;
+            }
+
+            is_used[t] = true;
+            for x in 0..3 {
+                let v = triangle[x] as usize;
+                if !is_local[v] {
+                    is_local[v] = true;
+                    local_indices[v] = meshlet.vertex_count as u8;
+                    meshlet.vertex_count += 1;
+                    result.vertices.push(v as u32);
+                    candidates.extend(vertex_triangles[offsets[v]..offsets[v + 1]].iter().filter(|&&t| !is_used[t]));
+                }
+                result.triangles.push(local_indices[v]);
+            }
+            meshlet.triangle_count += 1;
+            cone += normals[t];
+        }
+        if meshlet.triangle_count > 0 {
+            result.finish_meshlet(&mut meshlet, &mut is_local, &self.positions);
+        }
+        result
+    }
+
+    // Triangles sorted along a Z-order curve through their centroids
+    fn get_morton_order(&self) -> Vec<usize> {
+        let centroids: Vec<DVec3> = self.triangles.iter()
+            .map(|triangle| (self.positions[triangle[0] as usize] + self.positions[triangle[1] as usize] + self.positions[triangle[2] as usize]) / 3.0)
+            .collect();
+        let mut bounds = Box3::unfitted();
+        for centroid in centroids.iter() {
+            bounds.expand(centroid);
+        }
+        let size = bounds.size();
+        let mut keys: Vec<(u32, usize)> = centroids.iter().enumerate().map(|(t, centroid)| {
+            let mut key = 0;
+            for axis in 0..3 {
+                let position = if size[axis] > 0.0 { (centroid[axis] - bounds.min[axis]) / size[axis] } else { 0.0 };
+                let cell = (position * 1023.0).round() as u32;
+                for bit in 0..10 {
+                    key |= ((cell >> bit) & 1) << (3 * bit + axis);
+                }
+            }
+            (key, t)
+        }).collect();
+        keys.sort_unstable();
+        keys.into_iter().map(|(_, t)| t).collect()
+    }
+}
+
+impl Meshlets {
+    /// Writes every buffer after a header of counts, in little endian, with positions of the mesh as 32 bits floats so
+    /// that the stream is self-contained. Every section is a multiple of 4 bytes and can be uploaded as is:
+    /// - magic `NMML`, then the position, meshlet, vertex and triangle counts as u32
+    /// - positions, 3 f32 each
+    /// - meshlets, 4 u32 each as declared in `Meshlet`
+    /// - bounds, 12 f32 each (center and radius, cone apex and cutoff, cone axis and a padding 0), as 3 vec4
+    /// - vertices, u32 each
+    /// - triangles, 3 u8 each, padded with zeros to a multiple of 4 bytes
+    pub fn write<T: Write>(&self, positions: &[DVec3], writer: &mut BufWriter<T>) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        for count in [positions.len(), self.meshlets.len(), self.vertices.len(), self.triangles.len() / 3] {
+            writer.write_all(&(count as u32).to_le_bytes())?;
+        }
+        for position in positions.iter() {
+            write_floats(writer, &[position.x as f32, position.y as f32, position.z as f32])?;
+        }
+        for meshlet in self.meshlets.iter() {
+            for value in [meshlet.vertex_offset, meshlet.triangle_offset, meshlet.vertex_count, meshlet.triangle_count] {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+        for bounds in self.bounds.iter() {
+            write_floats(writer, &bounds.center)?;
+            write_floats(writer, &[bounds.radius])?;
+            write_floats(writer, &bounds.cone_apex)?;
+            write_floats(writer, &[bounds.cone_cutoff])?;
+            write_floats(writer, &bounds.cone_axis)?;
+            write_floats(writer, &[0.0])?;
+        }
+        for v in self.vertices.iter() {
+            writer.write_all(&v.to_le_bytes())?;
+        }
+        writer.write_all(&self.triangles)?;
+        writer.write_all(&[0; 3][..(4 - self.triangles.len() % 4) % 4])
+    }
+
+    fn finish_meshlet(&mut self, meshlet: &mut Meshlet, is_local: &mut [bool], positions: &[DVec3]) {
+        let vertices = &self.vertices[meshlet.vertex_offset as usize..];
+        let local_triangles = &self.triangles[meshlet.triangle_offset as usize..];
+        for v in vertices.iter() {
+            is_local[*v as usize] = false;
+        }
+        self.bounds.push(get_bounds(positions, vertices, local_triangles));
+        self.meshlets.push(*meshlet);
+        *meshlet = Meshlet { vertex_offset: self.vertices.len() as u32, triangle_offset: self.triangles.len() as u32, vertex_count: 0, triangle_count: 0 };
+    }
+}
+
+fn write_floats<T: Write>(writer: &mut BufWriter<T>, values: &[f32]) -> io::Result<()> {
+    for value in values.iter() {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn get_bounds(positions: &[DVec3], vertices: &[u32], local_triangles: &[u8]) -> MeshletBounds {
+    let mut bounds = Box3::unfitted();
+    for v in vertices.iter() {
+        bounds.expand(&positions[*v as usize]);
+    }
+    let center = bounds.center();
+    let radius = vertices.iter().map(|v| (positions[*v as usize] - center).magnitude()).fold(0.0, f64::max);
+
+    let triangles: Vec<(DVec3, DVec3)> = local_triangles.chunks(3).filter_map(|triangle| {
+        let [a, b, c] = [0, 1, 2].map(|x| positions[vertices[triangle[x] as usize] as usize]);
+        (b - a).cross(&(c - a)).try_normalize(0.0).map(|normal| ((a + b + c) / 3.0, normal))
+    }).collect();
+    let axis = triangles.iter().map(|(_, normal)| normal).sum::<DVec3>().try_normalize(0.0).unwrap_or_else(DVec3::zeros);
+    let min_dot = triangles.iter().map(|(_, normal)| normal.dot(&axis)).fold(1.0, f64::min);
+
+    let to_floats = |v: DVec3| [v.x as f32, v.y as f32, v.z as f32];
+    let mut result = MeshletBounds { center: to_floats(center), radius: radius as f32, cone_apex: to_floats(center), cone_axis: to_floats(axis), cone_cutoff: 1.0 };
+    // Beyond about 84°, the cone culls too rarely to be worth it
+    if triangles.is_empty() || min_dot <= 0.1 {
+        return result;
+    }
+    // The apex is moved back along the axis until it is behind the planes of all triangles
+    let offset = triangles.iter().map(|(centroid, normal)| (centroid - center).dot(normal) / normal.dot(&axis)).fold(f64::NEG_INFINITY, f64::max);
+    result.cone_apex = to_floats(center - axis * offset);
+    result.cone_cutoff = (1.0 - min_dot * min_dot).sqrt() as f32;
+    result
+}
+
+#[cfg(test)]
+mod meshlets_tests {
+    use crate::mesh::*;
+    use crate::mesh::test_utils::read_sphere;
+    use std::io::{BufReader, BufWriter};
+
+    // Triangles of every meshlet, as vertices of the mesh
+    fn get_meshlet_triangles(meshlets: &Meshlets) -> Vec<Vec<[u32; 3]>> {
+        meshlets.meshlets.iter().map(|meshlet| {
+            let vertices = &meshlets.vertices[meshlet.vertex_offset as usize..(meshlet.vertex_offset + meshlet.vertex_count) as usize];
+            let triangles = &meshlets.triangles[meshlet.triangle_offset as usize..(meshlet.triangle_offset + 3 * meshlet.triangle_count) as usize];
+            triangles.chunks(3).map(|triangle| [0, 1, 2].map(|x| vertices[triangle[x] as usize])).collect()
+        }).collect()
+    }
+
+    #[test]
+    fn build_sphere_meshlets() {
+        let shared_mesh = read_sphere();
+        let meshlets = shared_mesh.build_meshlets(64, 124, 0.5);
+        assert!(meshlets.meshlets.iter().all(|meshlet| meshlet.vertex_count <= 64 && meshlet.triangle_count <= 124 && meshlet.triangle_count > 0));
+        // 1280 triangles fit in 11 meshlets of 124 triangles, but vertices run out first
+        assert!(meshlets.meshlets.len() >= 11 && meshlets.meshlets.len() < 40);
+
+        let mut triangles: Vec<[u32; 3]> = get_meshlet_triangles(&meshlets).into_iter().flatten().collect();
+        let mut original: Vec<[u32; 3]> = shared_mesh.triangles.iter().map(|triangle| [triangle[0], triangle[1], triangle[2]]).collect();
+        triangles.sort_unstable();
+        original.sort_unstable();
+        assert_eq!(triangles, original);
+
+        // Vertices aren't repeated within meshlets
+        for meshlet in meshlets.meshlets.iter() {
+            let mut vertices = meshlets.vertices[meshlet.vertex_offset as usize..(meshlet.vertex_offset + meshlet.vertex_count) as usize].to_vec();
+            vertices.sort_unstable();
+            vertices.dedup();
+            assert_eq!(vertices.len(), meshlet.vertex_count as usize);
+        }
+    }
+
+    #[test]
+    fn culling_bounds() {
+        let shared_mesh = read_sphere();
+        let meshlets = shared_mesh.build_meshlets(64, 124, 0.5);
+        let camera = DVec3::new(0.3, -0.2, 5.0);
+        let mut culled = 0;
+        for (bounds, triangles) in meshlets.bounds.iter().zip(get_meshlet_triangles(&meshlets)) {
+            let center = DVec3::new(bounds.center[0] as f64, bounds.center[1] as f64, bounds.center[2] as f64);
+            let apex = DVec3::new(bounds.cone_apex[0] as f64, bounds.cone_apex[1] as f64, bounds.cone_apex[2] as f64);
+            let axis = DVec3::new(bounds.cone_axis[0] as f64, bounds.cone_axis[1] as f64, bounds.cone_axis[2] as f64);
+            for triangle in triangles.iter() {
+                for v in triangle.iter() {
+                    assert!((shared_mesh.positions[*v as usize] - center).magnitude() <= bounds.radius as f64 * (1.0 + 1e-6));
+                }
+            }
+            // Culled meshlets only have back facing triangles
+            if (apex - camera).normalize().dot(&axis) >= bounds.cone_cutoff as f64 {
+                culled += 1;
+                for triangle in triangles.iter() {
+                    let [a, b, c] = triangle.map(|v| shared_mesh.positions[v as usize]);
+                    assert!((b - a).cross(&(c - a)).dot(&(a - camera)) > 0.0);
+                }
+            }
+        }
+        assert!(culled > 0);
+    }
+
+    #[test]
+    fn write_buffers() {
+        let shared_mesh = read_sphere();
+        let meshlets = shared_mesh.build_meshlets(64, 124, 0.0);
+        let mut bytes = Vec::new();
+        meshlets.write(&shared_mesh.positions, &mut BufWriter::new(&mut bytes)).unwrap();
+
+        let triangle_bytes = meshlets.triangles.len().div_ceil(4) * 4;
+        assert_eq!(bytes.len(), 20 + 12 * 642 + 16 * meshlets.meshlets.len() + 48 * meshlets.bounds.len() + 4 * meshlets.vertices.len() + triangle_bytes);
+        assert_eq!(&bytes[..4], b"NMML");
+        assert_eq!(u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]), 642);
+        assert_eq!(u32::from_le_bytes([bytes[16], bytes[17], bytes[18], bytes[19]]), 1280);
+    }
+}
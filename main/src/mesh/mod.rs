@@ -1,5 +1,5 @@
 use nalgebra_glm as glm;
-use glm::{DVec3, U32Vec3};
+use glm::{DVec2, DVec3, U32Vec3};
 use super::utils::*;
 
 use std::hash::BuildHasherDefault;
@@ -17,5 +17,58 @@ pub use unsafe_mesh::UnsafeMesh as UnsafeMesh;
 pub mod shared_mesh;
 pub use shared_mesh::SharedMesh as SharedMesh; 
 
+pub mod progressive_mesh;
+pub use progressive_mesh::{ProgressiveMesh, VertexSplit};
+
+pub mod vertex_clustering;
+pub use vertex_clustering::VertexClustering;
+
+mod topology;
+
+pub mod repair;
+pub use repair::RepairReport;
+
+pub mod analysis;
+pub use analysis::TopologyReport;
+
+pub mod self_intersections;
+pub use self_intersections::SelfIntersection;
+
+pub mod deviation;
+pub use deviation::{DeviationReport, DistanceStats};
+
+pub mod mass_properties;
+pub use mass_properties::MassProperties;
+
+pub mod instances;
+pub use instances::Instances;
+
+pub mod slicing;
+pub use slicing::Contour;
+
+pub mod boolean;
+pub use boolean::{BooleanOperation, BooleanError};
+
+pub mod convex_hull;
+
+pub mod convex_decomposition;
+
+pub mod uv_unwrap;
+
+pub mod hidden;
+pub use hidden::HiddenRemoval;
+
+pub mod ambient_occlusion;
+pub use ambient_occlusion::AmbientOcclusionBlend;
+
+pub mod optimization;
+pub use optimization::{OptimizationReport, VertexCacheStatistics, VERTEX_CACHE_SIZE};
+
+pub mod meshlets;
+pub use meshlets::{Meshlet, MeshletBounds, Meshlets, MAX_MESHLET_VERTICES};
+
 include!("connected_mesh.rs");
-include!("builders.rs");
\ No newline at end of file
+include!("builders.rs");
+
+#[cfg(test)]
+pub(crate) mod test_utils;
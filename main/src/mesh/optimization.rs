@@ -0,0 +1,402 @@
+use nalgebra_glm as glm;
+use glm::{DVec3, U32Vec3};
+use super::SharedMesh;
+
+use std::collections::VecDeque;
+use std::ops::Range;
+
+// Triangle orders for GPUs, applied in this order by `optimize_for_gpu`:
+// - vertex cache: Forsyth's greedy ordering, which always adds the triangle whose vertices score the best, favoring vertices
+//   that are recent in a simulated LRU cache and vertices with few triangles left (so that they leave the cache for good)
+// - overdraw: the cache friendly order is cut into clusters wherever the cache would restart anyway, and wherever a cluster
+//   already amortizes its misses well enough. Clusters facing away from the center of the mesh are drawn first, as they
+//   tend to occlude the others.
+// - vertex fetch: vertices are renumbered in the order triangles first use them
+// Triangles are only reordered within groups, so that group ranges stay valid.
+
+/// Size of the simulated LRU cache of the Forsyth ordering
+const FORSYTH_CACHE_SIZE: usize = 32;
+
+/// Size of the FIFO cache used to report statistics, as found on most GPUs
+pub const VERTEX_CACHE_SIZE: usize = 16;
+
+/// Efficiency of the post-transform vertex cache for a triangle order
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VertexCacheStatistics {
+    /// Cache misses, which are vertices the GPU has to transform
+    pub transformed_vertices: usize,
+    /// Average cache miss ratio: transformed vertices per triangle, between 0.5 at best and 3
+    pub acmr: f64,
+    /// Average transformed to vertex ratio: transformed vertices per vertex used, 1 at best
+    pub atvr: f64,
+}
+
+/// Statistics before and after `SharedMesh::optimize_for_gpu`
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OptimizationReport {
+    pub before: VertexCacheStatistics,
+    pub after: VertexCacheStatistics,
+}
+
+impl SharedMesh {
+    /// Simulates a FIFO vertex cache of `cache_size` vertices over the triangles, in order
+    pub fn get_vertex_cache_statistics(&self, cache_size: usize) -> VertexCacheStatistics {
+        let mut cache = FifoCache::new(self.positions.len(), cache_size);
+        let transformed_vertices: usize = self.triangles.iter().map(|triangle| cache.add(triangle)).sum();
+        let mut is_used = vec![false; self.positions.len()];
+        for triangle in self.triangles.iter() {
+            for x in 0..3 {
+                is_used[triangle[x] as usize] = true;
+            }
+        }
+        let used_vertices = is_used.iter().filter(|is_used| **is_used).count();
+        VertexCacheStatistics {
+            transformed_vertices,
+            acmr: transformed_vertices as f64 / self.triangles.len().max(1) as f64,
+            atvr: transformed_vertices as f64 / used_vertices.max(1) as f64,
+        }
+    }
+
+    /// Runs the vertex cache, overdraw and vertex fetch optimizations, which is worth doing before export.
+    /// See `optimize_overdraw` for the threshold, 1.05 being a good trade-off.
+    /// Statistics are given for a cache of `VERTEX_CACHE_SIZE` vertices.
+    pub fn optimize_for_gpu(&mut self, overdraw_threshold: f64) -> OptimizationReport {
+        let before = self.get_vertex_cache_statistics(VERTEX_CACHE_SIZE);
+        self.optimize_vertex_cache();
+        self.optimize_overdraw(overdraw_threshold);
+        self.optimize_vertex_fetch();
+        OptimizationReport { before, after: self.get_vertex_cache_statistics(VERTEX_CACHE_SIZE) }
+    }
+
+    /// Reorders triangles so that their vertices are reused while they are still in the post-transform cache.
+    /// Index order coming from `ConnectedMesh` follows hash maps, which is about the worst there is for caches.
+    pub fn optimize_vertex_cache(&mut self) {
+        let mut triangles = Vec::with_capacity(self.triangles.len());
+        for range in self.get_triangle_ranges() {
+            let order = get_forsyth_order(&self.triangles[range.clone()], self.positions.len());
+            triangles.extend(order.iter().map(|t| self.triangles[range.start + t]));
+        }
+        self.triangles = triangles;
+    }
+
+    /// Reorders clusters of triangles so that the outer ones are drawn first, to reduce overdraw, expecting triangles
+    /// to be in vertex cache order already. Clusters may go up to `threshold` times the ACMR of the cache order, so 1.0
+    /// only cuts where the cache restarts anyway, and larger thresholds give smaller clusters.
+    pub fn optimize_overdraw(&mut self, threshold: f64) {
+        let mut triangles = Vec::with_capacity(self.triangles.len());
+        for range in self.get_triangle_ranges() {
+            let order = get_overdraw_order(&self.positions, &self.triangles[range.clone()], threshold);
+            triangles.extend(order.iter().map(|t| self.triangles[range.start + t]));
+        }
+        self.triangles = triangles;
+    }
+
+    /// Renumbers vertices in the order triangles first use them, so that vertex fetches are mostly sequential.
+    /// Vertices that no triangle uses are moved to the end. Attributes with a value per vertex are reordered along.
+    pub fn optimize_vertex_fetch(&mut self) {
+        let mut new_indices = vec![u32::MAX; self.positions.len()];
+        let mut old_indices = Vec::with_capacity(self.positions.len());
+        for triangle in self.triangles.iter_mut() {
+            for x in 0..3 {
+                let v = triangle[x] as usize;
+                if new_indices[v] == u32::MAX {
+                    new_indices[v] = old_indices.len() as u32;
+                    old_indices.push(v);
+                }
+                triangle[x] = new_indices[v];
+            }
+        }
+        old_indices.extend((0..self.positions.len()).filter(|&v| new_indices[v] == u32::MAX));
+
+        let vertex_count = self.positions.len();
+        self.positions = old_indices.iter().map(|&v| self.positions[v]).collect();
+        if let Some(normals) = self.normals.as_mut().filter(|normals| normals.len() == vertex_count) {
+            *normals = old_indices.iter().map(|&v| normals[v]).collect();
+        }
+        if let Some(colors) = self.colors.as_mut().filter(|colors| colors.len() == vertex_count) {
+            *colors = old_indices.iter().map(|&v| colors[v]).collect();
+        }
+        if let Some(uvs) = self.uvs.as_mut().filter(|uvs| uvs.len() == vertex_count) {
+            *uvs = old_indices.iter().map(|&v| uvs[v]).collect();
+        }
+    }
+
+    // Ranges between the bounds of all groups, which can be reordered without breaking any group
+    fn get_triangle_ranges(&self) -> Vec<Range<usize>> {
+        let mut bounds = vec![0, self.triangles.len()];
+        for group in self.groups.iter() {
+            let range = group.triangle_range();
+            bounds.push(range.start.min(self.triangles.len()));
+            bounds.push(range.end.min(self.triangles.len()));
+        }
+        bounds.sort_unstable();
+        bounds.dedup();
+        bounds.windows(2).map(|bounds| bounds[0]..bounds[1]).collect()
+    }
+}
+
+struct FifoCache {
+    entries: VecDeque<u32>,
+    is_cached: Vec<bool>,
+    size: usize,
+}
+
+impl FifoCache {
+    fn new(vertex_count: usize, size: usize) -> Self {
+        FifoCache { entries: VecDeque::with_capacity(size + 1), is_cached: vec![false; vertex_count], size: size.max(1) }
+    }
+
+    // Returns the number of misses
+    fn add(&mut self, triangle: &U32Vec3) -> usize {
+        let mut misses = 0;
+        for x in 0..3 {
+            let v = triangle[x];
+            if self.is_cached[v as usize] {
+                continue;
+            }
+            misses += 1;
+            self.is_cached[v as usize] = true;
+            self.entries.push_back(v);
+            if self.entries.len() > self.size {
+                let evicted = self.entries.pop_front().unwrap();
+                self.is_cached[evicted as usize] = false;
+            }
+        }
+        misses
+    }
+
+    fn clear(&mut self) {
+        for v in self.entries.drain(..) {
+            self.is_cached[v as usize] = false;
+        }
+    }
+}
+
+fn get_vertex_score(cache_position: Option<usize>, remaining: u32) -> f64 {
+    if remaining == 0 {
+        return -1.0;
+    }
+    let cache_score = match cache_position {
+        None => 0.0,
+        // Vertices of the last triangle are scored a bit lower, so that strips don't keep going in the same direction
+        Some(position) if position < 3 => 0.75,
+        Some(position) => (1.0 - (position - 3) as f64 / (FORSYTH_CACHE_SIZE - 3) as f64).powf(1.5),
+    };
+    cache_score + 2.0 * (remaining as f64).powf(-0.5)
+}
+
+// Order of the triangles for the vertex cache
+fn get_forsyth_order(triangles: &[U32Vec3], vertex_count: usize) -> Vec<usize> {
+    // Triangles of every vertex, in compressed rows
+    let mut offsets = vec![0; vertex_count + 1];
+    for triangle in triangles.iter() {
+        for x in 0..3 {
+            offsets[triangle[x] as usize + 1] += 1;
+        }
+    }
+    for v in 0..vertex_count {
+        offsets[v + 1] += offsets[v];
+    }
+    let mut vertex_triangles = vec![0; offsets[vertex_count]];
+    let mut next = offsets.clone();
+    for (t, triangle) in triangles.iter().enumerate() {
+        for x in 0..3 {
+            let v = triangle[x] as usize;
+            vertex_triangles[next[v]] = t;
+            next[v] += 1;
+        }
+    }
+
+    let mut remaining: Vec<u32> = (0..vertex_count).map(|v| (offsets[v + 1] - offsets[v]) as u32).collect();
+    let mut scores: Vec<f64> = remaining.iter().map(|remaining| get_vertex_score(None, *remaining)).collect();
+    let mut is_added = vec![false; triangles.len()];
+    let mut cache: Vec<u32> = Vec::with_capacity(FORSYTH_CACHE_SIZE + 3);
+    let mut order = Vec::with_capacity(triangles.len());
+    let mut best = None;
+    let mut cursor = 0;
+
+    while order.len() < triangles.len() {
+        // Dead ends restart from the first triangle left, in input order
+        let t = best.unwrap_or_else(|| {
+            while is_added[cursor] {
+                cursor += 1;
+            }
+            cursor
+        });
+        is_added[t] = true;
+        order.push(t);
+
+        let triangle = triangles[t];
+        let mut new_cache = Vec::with_capacity(FORSYTH_CACHE_SIZE + 3);
+        for x in 0..3 {
+            remaining[triangle[x] as usize] -= 1;
+            if !new_cache.contains(&triangle[x]) {
+                new_cache.push(triangle[x]);
+            }
+        }
+        new_cache.extend(cache.iter().filter(|v| !triangle.iter().any(|x| x == *v)));
+        for (position, v) in new_cache.iter().enumerate() {
+            let position = Some(position).filter(|&position| position < FORSYTH_CACHE_SIZE);
+            scores[*v as usize] = get_vertex_score(position, remaining[*v as usize]);
+        }
+        new_cache.truncate(FORSYTH_CACHE_SIZE);
+        cache = new_cache;
+
+        let mut best_score = f64::NEG_INFINITY;
+        best = None;
+        for v in cache.iter() {
+            for &t in vertex_triangles[offsets[*v as usize]..offsets[*v as usize + 1]].iter() {
+                if is_added[t] {
+                    continue;
+                }
+                let triangle = triangles[t];
+                let score = scores[triangle[0] as usize] + scores[triangle[1] as usize] + scores[triangle[2] as usize];
+                if score > best_score || (score == best_score && best.is_some_and(|best| t < best)) {
+                    best_score = score;
+                    best = Some(t);
+                }
+            }
+        }
+    }
+    order
+}
+
+// Order of the triangles for overdraw, keeping the order within clusters
+fn get_overdraw_order(positions: &[DVec3], triangles: &[U32Vec3], threshold: f64) -> Vec<usize> {
+    let mut cache = FifoCache::new(positions.len(), VERTEX_CACHE_SIZE);
+    let misses: Vec<usize> = triangles.iter().map(|triangle| cache.add(triangle)).collect();
+
+    // Hard boundaries, where the cache restarts
+    let mut hard_starts: Vec<usize> = (0..triangles.len()).filter(|&t| t == 0 || misses[t] == 3).collect();
+    hard_starts.push(triangles.len());
+
+    // Soft boundaries, once a cluster amortizes its misses close enough to the whole hard cluster
+    let mut starts = Vec::new();
+    for bounds in hard_starts.windows(2) {
+        let (start, end) = (bounds[0], bounds[1]);
+        let max_acmr = misses[start..end].iter().sum::<usize>() as f64 / (end - start) as f64 * threshold;
+        starts.push(start);
+        cache.clear();
+        let (mut cluster_start, mut cluster_misses) = (start, 0);
+        for (t, triangle) in triangles.iter().enumerate().take(end).skip(start) {
+            cluster_misses += cache.add(triangle);
+            if t + 1 < end && cluster_misses as f64 / (t + 1 - cluster_start) as f64 <= max_acmr {
+                starts.push(t + 1);
+                cache.clear();
+                cluster_start = t + 1;
+                cluster_misses = 0;
+            }
+        }
+    }
+    starts.push(triangles.len());
+
+    let get_area_vector = |triangle: &U32Vec3| -> (DVec3, DVec3) {
+        let [a, b, c] = [0, 1, 2].map(|x| positions[triangle[x] as usize]);
+        ((b - a).cross(&(c - a)), (a + b + c) / 3.0)
+    };
+    let (mut total_area, mut center) = (0.0, DVec3::zeros());
+    for triangle in triangles.iter() {
+        let (area_vector, centroid) = get_area_vector(triangle);
+        total_area += area_vector.magnitude();
+        center += centroid * area_vector.magnitude();
+    }
+    center /= total_area.max(f64::MIN_POSITIVE);
+
+    let mut clusters: Vec<(f64, Range<usize>)> = starts.windows(2).map(|bounds| {
+        let (mut area, mut normal, mut centroid) = (0.0, DVec3::zeros(), DVec3::zeros());
+        for triangle in triangles[bounds[0]..bounds[1]].iter() {
+            let (area_vector, triangle_centroid) = get_area_vector(triangle);
+            area += area_vector.magnitude();
+            normal += area_vector;
+            centroid += triangle_centroid * area_vector.magnitude();
+        }
+        let centroid = centroid / area.max(f64::MIN_POSITIVE);
+        let facing = (centroid - center).dot(&normal.try_normalize(0.0).unwrap_or_else(DVec3::zeros));
+        (facing, bounds[0]..bounds[1])
+    }).collect();
+    clusters.sort_by(|a, b| b.0.total_cmp(&a.0));
+    clusters.into_iter().flat_map(|(_, range)| range).collect()
+}
+
+#[cfg(test)]
+mod optimization_tests {
+    use crate::mesh::*;
+    use crate::mesh::test_utils::read_sphere;
+
+    fn read_shuffled_sphere() -> SharedMesh {
+        let mut shared_mesh = read_sphere();
+        // 7919 is prime, so this visits every triangle once
+        let count = shared_mesh.triangles.len();
+        shared_mesh.triangles = (0..count).map(|t| shared_mesh.triangles[t * 7919 % count]).collect();
+        shared_mesh
+    }
+
+    fn get_sorted_triangles(shared_mesh: &SharedMesh) -> Vec<[[u64; 3]; 3]> {
+        let mut triangles: Vec<[[u64; 3]; 3]> = shared_mesh.triangles.iter().map(|triangle| {
+            let mut corners = [0, 1, 2].map(|x| {
+                let p = shared_mesh.positions[triangle[x] as usize];
+                [p.x.to_bits(), p.y.to_bits(), p.z.to_bits()]
+            });
+            // Same winding, starting from the smallest corner
+            let first = (0..3).min_by_key(|&x| corners[x]).unwrap();
+            corners.rotate_left(first);
+            corners
+        }).collect();
+        triangles.sort_unstable();
+        triangles
+    }
+
+    #[test]
+    fn optimize_sphere() {
+        let mut shared_mesh = read_shuffled_sphere();
+        let original = get_sorted_triangles(&shared_mesh);
+        let report = shared_mesh.optimize_for_gpu(1.05);
+        assert!(report.before.acmr > 2.0);
+        assert!(report.after.acmr < 0.8);
+        assert!(report.after.atvr < 1.5 && report.after.atvr >= 1.0);
+        assert_eq!(report.after, shared_mesh.get_vertex_cache_statistics(VERTEX_CACHE_SIZE));
+        assert_eq!(get_sorted_triangles(&shared_mesh), original);
+
+        // Vertices come in the order of first use
+        let mut next = 0;
+        for triangle in shared_mesh.triangles.iter() {
+            for x in 0..3 {
+                assert!(triangle[x] <= next);
+                next = next.max(triangle[x] + 1);
+            }
+        }
+    }
+
+    #[test]
+    fn overdraw_clusters() {
+        let mut shared_mesh = read_shuffled_sphere();
+        shared_mesh.optimize_vertex_cache();
+        let cache_order = shared_mesh.get_vertex_cache_statistics(VERTEX_CACHE_SIZE);
+
+        // Only cutting where the cache restarts barely changes the ACMR
+        let mut hard = shared_mesh.clone();
+        hard.optimize_overdraw(1.0);
+        assert!(hard.get_vertex_cache_statistics(VERTEX_CACHE_SIZE).acmr < cache_order.acmr * 1.05);
+
+        let mut soft = shared_mesh.clone();
+        soft.optimize_overdraw(1.5);
+        assert!(soft.get_vertex_cache_statistics(VERTEX_CACHE_SIZE).acmr < cache_order.acmr * 1.5);
+        assert_eq!(get_sorted_triangles(&soft), get_sorted_triangles(&shared_mesh));
+    }
+
+    #[test]
+    fn keep_groups_and_attributes() {
+        let mut shared_mesh = read_shuffled_sphere();
+        let count = shared_mesh.triangles.len() as u32;
+        shared_mesh.groups = vec![Group::new(0, 3 * 300), Group::new(3 * 300, 3 * (count - 300))];
+        shared_mesh.colors = Some(shared_mesh.positions.clone());
+        let first_group = get_sorted_triangles(&SharedMesh { triangles: shared_mesh.triangles[..300].to_vec(), ..shared_mesh.clone() });
+
+        shared_mesh.optimize_for_gpu(1.05);
+        assert_eq!(shared_mesh.groups, vec![Group::new(0, 3 * 300), Group::new(3 * 300, 3 * (count - 300))]);
+        assert_eq!(get_sorted_triangles(&SharedMesh { triangles: shared_mesh.triangles[..300].to_vec(), ..shared_mesh.clone() }), first_group);
+        assert_eq!(shared_mesh.colors.as_ref(), Some(&shared_mesh.positions));
+    }
+}
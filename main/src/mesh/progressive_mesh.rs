@@ -0,0 +1,414 @@
+use nalgebra_glm as glm;
+use glm::{DVec3, U32Vec3};
+use super::SharedMesh;
+
+use std::io::{self, BufReader, BufWriter};
+use std::io::prelude::*;
+
+const MAGIC: &[u8; 4] = b"NMPM";
+
+/// Inverse of an edge collapse.
+/// Vertices and faces introduced by a split are appended after the ones already known, so buffers only ever grow.
+#[derive(Debug, Clone)]
+pub struct VertexSplit {
+    /// Vertex that was kept by the collapse
+    pub vertex: u32,
+    /// Vertex that was collapsed into `vertex`
+    pub split_vertex: u32,
+    /// Position of `vertex` before the collapse
+    pub position: DVec3,
+    /// Positions of the vertices introduced by this split (including `split_vertex`)
+    pub new_positions: Vec<DVec3>,
+    /// Faces restored by this split
+    pub faces: Vec<U32Vec3>,
+    /// Corners switching back from `vertex` to `split_vertex`, packed as `face << 2 | corner`
+    pub moved_corners: Vec<u32>,
+}
+
+/// A mesh that can be refined or coarsened one vertex split at a time, from a base mesh.
+#[derive(Clone)]
+pub struct ProgressiveMesh {
+    positions: Vec<DVec3>,
+    triangles: Vec<U32Vec3>,
+    is_face_active: Vec<bool>,
+    face_count: u32,
+    base_vertex_count: u32,
+    base_face_count: u32,
+    splits: Vec<VertexSplit>,
+    // Per split, index of the first face it restores and position of its vertex once collapsed
+    first_faces: Vec<u32>,
+    collapsed_positions: Vec<DVec3>,
+    level: usize,
+    expected_split_count: u32,
+}
+
+impl ProgressiveMesh {
+    pub fn new(positions: Vec<DVec3>, triangles: Vec<U32Vec3>) -> Self {
+        ProgressiveMesh {
+            base_vertex_count: positions.len() as u32,
+            base_face_count: triangles.len() as u32,
+            face_count: triangles.len() as u32,
+            is_face_active: vec![true; triangles.len()],
+            positions,
+            triangles,
+            splits: Vec::new(),
+            first_faces: Vec::new(),
+            collapsed_positions: Vec::new(),
+            level: 0,
+            expected_split_count: 0,
+        }
+    }
+
+    /// Appends a vertex split. The mesh is refined up to and including this split.
+    pub fn push_split(&mut self, split: VertexSplit) {
+        self.set_level(self.splits.len());
+
+        self.collapsed_positions.push(match self.positions.get(split.vertex as usize) {
+            Some(position) => *position,
+            None => split.position, // Vertex is introduced by this split, it has no collapsed position
+        });
+        self.first_faces.push(self.triangles.len() as u32);
+        self.positions.extend(split.new_positions.iter());
+        self.triangles.extend(split.faces.iter());
+        self.is_face_active.resize(self.triangles.len(), false);
+        self.splits.push(split);
+
+        self.refine();
+    }
+
+    pub fn face_count(&self) -> u32 {
+        self.face_count
+    }
+
+    pub fn base_face_count(&self) -> u32 {
+        self.base_face_count
+    }
+
+    /// Face count once every known split is applied
+    pub fn max_face_count(&self) -> u32 {
+        self.triangles.len() as u32
+    }
+
+    /// Number of splits currently applied
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    pub fn split_count(&self) -> usize {
+        self.splits.len()
+    }
+
+    /// Number of splits announced by the stream header, which may not all be received yet
+    pub fn expected_split_count(&self) -> u32 {
+        self.expected_split_count
+    }
+
+    pub fn positions(&self) -> &[DVec3] {
+        &self.positions
+    }
+
+    /// All faces known so far, including inactive ones
+    pub fn triangles(&self) -> &[U32Vec3] {
+        &self.triangles
+    }
+
+    pub fn is_face_active(&self, face: u32) -> bool {
+        self.is_face_active[face as usize]
+    }
+
+    pub(crate) fn collapsed_position(&self, split_index: usize) -> DVec3 {
+        self.collapsed_positions[split_index]
+    }
+
+    /// Applies the next vertex split, if any
+    pub fn refine(&mut self) -> bool {
+        if self.level == self.splits.len() {
+            return false;
+        }
+        let split = &self.splits[self.level];
+        self.positions[split.vertex as usize] = split.position;
+        for corner in split.moved_corners.iter() {
+            self.triangles[(corner >> 2) as usize][(corner & 3) as usize] = split.split_vertex;
+        }
+        let first_face = self.first_faces[self.level] as usize;
+        for is_face_active in self.is_face_active[first_face..first_face + split.faces.len()].iter_mut() {
+            *is_face_active = true;
+        }
+        self.face_count += split.faces.len() as u32;
+        self.level += 1;
+        true
+    }
+
+    /// Reverts the last applied vertex split, if any
+    pub fn coarsen(&mut self) -> bool {
+        if self.level == 0 {
+            return false;
+        }
+        self.level -= 1;
+        let split = &self.splits[self.level];
+        self.positions[split.vertex as usize] = self.collapsed_positions[self.level];
+        for corner in split.moved_corners.iter() {
+            self.triangles[(corner >> 2) as usize][(corner & 3) as usize] = split.vertex;
+        }
+        let first_face = self.first_faces[self.level] as usize;
+        for is_face_active in self.is_face_active[first_face..first_face + split.faces.len()].iter_mut() {
+            *is_face_active = false;
+        }
+        self.face_count -= split.faces.len() as u32;
+        true
+    }
+
+    pub fn set_level(&mut self, level: usize) {
+        while self.level < level && self.refine() {}
+        while self.level > level && self.coarsen() {}
+    }
+
+    /// Refines or coarsens to the smallest level having at least the given face count (or the finest level if none does)
+    pub fn set_face_count(&mut self, target_face_count: u32) {
+        while self.face_count < target_face_count && self.refine() {}
+        while self.level > 0 && self.face_count - self.splits[self.level - 1].faces.len() as u32 >= target_face_count {
+            self.coarsen();
+        }
+    }
+
+    /// Snapshot of the current level. Vertices not yet referenced by any face are kept.
+    pub fn to_shared_mesh(&self) -> SharedMesh {
+        let triangles = self.triangles.iter()
+            .zip(self.is_face_active.iter())
+            .filter(|(_, is_face_active)| **is_face_active)
+            .map(|(triangle, _)| *triangle)
+            .collect();
+        SharedMesh {
+            groups: Vec::new(),
+            triangles,
+            positions: self.positions.clone(),
+            normals: None,
+            colors: None,
+            uvs: None,
+        }
+    }
+
+    /// Writes the base mesh followed by every vertex split, in refinement order.
+    /// Positions are written as 32 bits floats, like STL.
+    pub fn write<T: Write>(&self, writer: &mut BufWriter<T>) -> io::Result<()> {
+        let mut base = self.clone();
+        base.set_level(0);
+
+        writer.write_all(MAGIC)?;
+        write_u32(writer, self.base_vertex_count)?;
+        write_u32(writer, self.base_face_count)?;
+        write_u32(writer, self.splits.len() as u32)?;
+        for position in base.positions[..self.base_vertex_count as usize].iter() {
+            write_position(writer, position)?;
+        }
+        for triangle in base.triangles[..self.base_face_count as usize].iter() {
+            write_triangle(writer, triangle)?;
+        }
+
+        for split in self.splits.iter() {
+            write_u32(writer, split.vertex)?;
+            write_u32(writer, split.split_vertex)?;
+            write_position(writer, &split.position)?;
+            write_u32(writer, split.new_positions.len() as u32)?;
+            for position in split.new_positions.iter() {
+                write_position(writer, position)?;
+            }
+            write_u32(writer, split.faces.len() as u32)?;
+            for triangle in split.faces.iter() {
+                write_triangle(writer, triangle)?;
+            }
+            write_u32(writer, split.moved_corners.len() as u32)?;
+            for corner in split.moved_corners.iter() {
+                write_u32(writer, *corner)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the header and base mesh of a stream written by `write`.
+    /// Vertex splits can then be read one by one with `read_split` as data arrives.
+    pub fn read_base<T: Read>(reader: &mut BufReader<T>) -> io::Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a progressive mesh"));
+        }
+        let vertex_count = read_u32(reader)?;
+        let face_count = read_u32(reader)?;
+        let split_count = read_u32(reader)?;
+        let positions = (0..vertex_count).map(|_| read_position(reader)).collect::<io::Result<Vec<DVec3>>>()?;
+        let triangles = (0..face_count).map(|_| read_triangle(reader)).collect::<io::Result<Vec<U32Vec3>>>()?;
+        if triangles.iter().any(|triangle| triangle.iter().any(|i| *i >= vertex_count)) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Base face references an unknown vertex"));
+        }
+
+        let mut progressive_mesh = ProgressiveMesh::new(positions, triangles);
+        progressive_mesh.expected_split_count = split_count;
+        Ok(progressive_mesh)
+    }
+
+    /// Reads the next vertex split and refines the mesh up to it.
+    /// Returns false once every split announced by the header has been read.
+    /// A split referencing vertices, faces or corners that don't exist is an `InvalidData` error.
+    pub fn read_split<T: Read>(&mut self, reader: &mut BufReader<T>) -> io::Result<bool> {
+        if self.splits.len() as u32 >= self.expected_split_count {
+            return Ok(false);
+        }
+        let vertex = read_u32(reader)?;
+        let split_vertex = read_u32(reader)?;
+        let position = read_position(reader)?;
+        let count = read_u32(reader)?;
+        let new_positions = (0..count).map(|_| read_position(reader)).collect::<io::Result<Vec<DVec3>>>()?;
+        let count = read_u32(reader)?;
+        let faces = (0..count).map(|_| read_triangle(reader)).collect::<io::Result<Vec<U32Vec3>>>()?;
+        let count = read_u32(reader)?;
+        let moved_corners = (0..count).map(|_| read_u32(reader)).collect::<io::Result<Vec<u32>>>()?;
+
+        // Indices come straight from the stream, check them before they are used
+        let vertex_count = (self.positions.len() + new_positions.len()) as u32;
+        let face_count = (self.triangles.len() + faces.len()) as u32;
+        if vertex >= vertex_count || split_vertex >= vertex_count
+            || faces.iter().any(|face| face.iter().any(|i| *i >= vertex_count)) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Vertex split references an unknown vertex"));
+        }
+        if moved_corners.iter().any(|corner| corner >> 2 >= face_count || corner & 3 > 2) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Vertex split references an unknown corner"));
+        }
+
+        self.push_split(VertexSplit { vertex, split_vertex, position, new_positions, faces, moved_corners });
+        Ok(true)
+    }
+}
+
+fn write_u32<T: Write>(writer: &mut BufWriter<T>, value: u32) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_position<T: Write>(writer: &mut BufWriter<T>, position: &DVec3) -> io::Result<()> {
+    writer.write_all(&(position.x as f32).to_le_bytes())?;
+    writer.write_all(&(position.y as f32).to_le_bytes())?;
+    writer.write_all(&(position.z as f32).to_le_bytes())
+}
+
+fn write_triangle<T: Write>(writer: &mut BufWriter<T>, triangle: &U32Vec3) -> io::Result<()> {
+    for i in triangle.iter() {
+        write_u32(writer, *i)?;
+    }
+    Ok(())
+}
+
+fn read_u32<T: Read>(reader: &mut BufReader<T>) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_f32<T: Read>(reader: &mut BufReader<T>) -> io::Result<f32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(f32::from_le_bytes(bytes))
+}
+
+fn read_position<T: Read>(reader: &mut BufReader<T>) -> io::Result<DVec3> {
+    Ok(DVec3::new(read_f32(reader)? as f64, read_f32(reader)? as f64, read_f32(reader)? as f64))
+}
+
+fn read_triangle<T: Read>(reader: &mut BufReader<T>) -> io::Result<U32Vec3> {
+    Ok(U32Vec3::new(read_u32(reader)?, read_u32(reader)?, read_u32(reader)?))
+}
+
+#[cfg(test)]
+mod progressive_mesh_tests {
+    use crate::mesh::*;
+    use nalgebra_glm::{DVec3, U32Vec3};
+    use crate::mesh::test_utils::read_sphere;
+    use std::io::{BufReader, BufWriter};
+
+    fn geometry(triangles: &[U32Vec3], positions: &[DVec3]) -> Vec<[u64; 9]> {
+        let mut geometry: Vec<[u64; 9]> = triangles.iter()
+            .map(|t| {
+                let (a, b, c) = (positions[t[0] as usize], positions[t[1] as usize], positions[t[2] as usize]);
+                [a.x.to_bits(), a.y.to_bits(), a.z.to_bits(), b.x.to_bits(), b.y.to_bits(), b.z.to_bits(), c.x.to_bits(), c.y.to_bits(), c.z.to_bits()]
+            })
+            .collect();
+        geometry.sort();
+        geometry
+    }
+
+    fn active_geometry(progressive_mesh: &ProgressiveMesh) -> Vec<[u64; 9]> {
+        geometry(&progressive_mesh.to_shared_mesh().triangles, progressive_mesh.positions())
+    }
+
+    #[test]
+    fn refine_and_coarsen() {
+        let shared_mesh = read_sphere();
+        let mut connected_mesh = ConnectedMesh::from(&shared_mesh);
+        let progressive_mesh = connected_mesh.decimate_progressive(100);
+
+        let mut progressive_mesh = progressive_mesh;
+        assert_eq!(progressive_mesh.level(), 0);
+        assert!(progressive_mesh.face_count() <= 100);
+        assert_eq!(progressive_mesh.face_count(), connected_mesh.face_count());
+        let coarse = active_geometry(&progressive_mesh);
+
+        // Fully refined mesh has the original geometry
+        progressive_mesh.set_level(progressive_mesh.split_count());
+        assert_eq!(progressive_mesh.face_count() as usize, shared_mesh.triangles.len());
+        assert_eq!(active_geometry(&progressive_mesh), geometry(&shared_mesh.triangles, &shared_mesh.positions));
+
+        progressive_mesh.set_face_count(500);
+        assert!(progressive_mesh.face_count() >= 500);
+        assert!(progressive_mesh.face_count() < 503);
+
+        progressive_mesh.set_level(0);
+        assert_eq!(active_geometry(&progressive_mesh), coarse);
+    }
+
+    #[test]
+    fn stream() {
+        let mut connected_mesh = ConnectedMesh::from(&read_sphere());
+        let mut progressive_mesh = connected_mesh.decimate_progressive(100);
+
+        let mut bytes = Vec::new();
+        progressive_mesh.write(&mut BufWriter::new(&mut bytes)).unwrap();
+
+        let mut reader = BufReader::new(&bytes[..]);
+        let mut streamed = ProgressiveMesh::read_base(&mut reader).unwrap();
+        assert_eq!(streamed.face_count(), progressive_mesh.face_count());
+        assert_eq!(streamed.expected_split_count() as usize, progressive_mesh.split_count());
+
+        while streamed.read_split(&mut reader).unwrap() {
+            progressive_mesh.refine();
+            assert_eq!(streamed.face_count(), progressive_mesh.face_count());
+        }
+        assert_eq!(streamed.level(), progressive_mesh.split_count());
+        assert_eq!(streamed.to_shared_mesh().triangles, progressive_mesh.to_shared_mesh().triangles);
+    }
+
+    #[test]
+    fn corrupt_stream() {
+        let mut connected_mesh = ConnectedMesh::from(&read_sphere());
+        let progressive_mesh = connected_mesh.decimate_progressive(100);
+
+        let mut bytes = Vec::new();
+        progressive_mesh.write(&mut BufWriter::new(&mut bytes)).unwrap();
+
+        // Kept vertex of the first split, after the header and the base mesh
+        let base = ProgressiveMesh::read_base(&mut BufReader::new(&bytes[..])).unwrap();
+        let offset = 16 + 12 * base.positions().len() + 12 * base.triangles().len();
+        let mut corrupt = bytes.clone();
+        corrupt[offset..offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        let mut reader = BufReader::new(&corrupt[..]);
+        let mut streamed = ProgressiveMesh::read_base(&mut reader).unwrap();
+        assert_eq!(streamed.read_split(&mut reader).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+
+        // Truncated streams fail instead of panicking
+        for length in (0..bytes.len()).step_by(97) {
+            let mut reader = BufReader::new(&bytes[..length]);
+            if let Ok(mut streamed) = ProgressiveMesh::read_base(&mut reader) {
+                while let Ok(true) = streamed.read_split(&mut reader) {}
+            }
+        }
+    }
+}
@@ -0,0 +1,469 @@
+use crate::spatial::Bvh;
+
+// Faces being remeshed, as [position, normal, color, uv] corners, with the faces around every position
+struct Remesher {
+    positions: Vec<DVec3>,
+    faces: Vec<[[u32; 4]; 3]>,
+    is_face_removed: Vec<bool>,
+    vertex_faces: Vec<Vec<u32>>,
+    // Positions on a border or feature edge, which never move
+    is_locked: Vec<bool>,
+    is_border: Vec<bool>,
+    feature_edges: HashSet<u64>,
+    attributes: CornerAttributes,
+}
+
+impl ConnectedMesh {
+    /// Isotropic remeshing (Botsch & Kobbelt): each iteration splits edges longer than 4/3 of the target length,
+    /// collapses edges shorter than 4/5 of it, flips edges to bring valences closer to 6 and relaxes vertices tangentially,
+    /// projecting them back onto the original surface.
+    /// Borders and feature edges (where normals or other attributes are split) are split to the target length as well,
+    /// but their vertices never move, so that their shape is kept exactly. Attributes of new vertices are interpolated linearly.
+    /// The mesh is left as is unless the target length is strictly positive.
+    pub fn remesh(&mut self, target_edge_length: f64, iterations: u32) {
+        // Every edge would be too long otherwise, and splits would never end
+        if target_edge_length.is_nan() || target_edge_length <= 0.0 {
+            return;
+        }
+        let mut remesher = Remesher::new(self);
+        let triangles = remesher.faces.iter().map(|face| U32Vec3::new(face[0][0], face[1][0], face[2][0])).collect();
+        let surface = Bvh::new(remesher.positions.clone(), triangles);
+
+        let high = 4.0 / 3.0 * target_edge_length;
+        let low = 4.0 / 5.0 * target_edge_length;
+        for _ in 0..iterations {
+            remesher.split_long_edges(high);
+            remesher.collapse_short_edges(low, high);
+            remesher.equalize_valences();
+            remesher.relax(&surface);
+        }
+
+        *self = remesher.build();
+    }
+
+    // An edge is a seam if any attribute is split along it, even with equal normals
+    fn is_seam_edge(&self, node_index: u32) -> bool {
+        match self.get_twin(node_index) {
+            Some(twin) => {
+                let node_b = self.nodes[node_index as usize].relative;
+                let twin_b = self.nodes[twin as usize].relative;
+                self.get_corner(node_index) != self.get_corner(twin_b) || self.get_corner(node_b) != self.get_corner(twin)
+            },
+            None => true,
+        }
+    }
+}
+
+impl Remesher {
+    fn new(connected_mesh: &ConnectedMesh) -> Self {
+        let mut remesher = Remesher {
+            positions: connected_mesh.positions.clone(),
+            faces: Vec::with_capacity(connected_mesh.face_count as usize),
+            is_face_removed: Vec::new(),
+            vertex_faces: vec![Vec::new(); connected_mesh.positions.len()],
+            is_locked: vec![false; connected_mesh.positions.len()],
+            is_border: vec![false; connected_mesh.positions.len()],
+            feature_edges: HashSet::new(),
+            attributes: CornerAttributes::new(connected_mesh),
+        };
+
+        for i in 0..connected_mesh.nodes.len() as u32 {
+            if !connected_mesh.is_first_node_of_face(i) {
+                continue;
+            }
+            let face = connected_mesh.get_face_nodes(i);
+            for x in 0..3 {
+                if connected_mesh.is_feature_edge(face[x]) || connected_mesh.is_seam_edge(face[x]) {
+                    let pos_a = connected_mesh.nodes[face[x] as usize].position;
+                    let pos_b = connected_mesh.nodes[face[(x + 1) % 3] as usize].position;
+                    remesher.feature_edges.insert(Edge::new(pos_a, pos_b).get_key());
+                    remesher.is_locked[pos_a as usize] = true;
+                    remesher.is_locked[pos_b as usize] = true;
+                    if connected_mesh.get_twin(face[x]).is_none() {
+                        remesher.is_border[pos_a as usize] = true;
+                        remesher.is_border[pos_b as usize] = true;
+                    }
+                }
+            }
+            remesher.add_face(face.map(|node| connected_mesh.get_corner(node)));
+        }
+        remesher
+    }
+
+    fn build(self) -> ConnectedMesh {
+        let corners: Vec<[[u32; 4]; 3]> = self.faces.iter()
+            .zip(self.is_face_removed.iter())
+            .filter(|(_, is_removed)| !**is_removed)
+            .map(|(face, _)| *face)
+            .collect();
+        self.attributes.build(self.positions, &corners)
+    }
+
+    fn add_face(&mut self, face: [[u32; 4]; 3]) -> u32 {
+        let face_index = self.faces.len() as u32;
+        for corner in face.iter() {
+            self.vertex_faces[corner[0] as usize].push(face_index);
+        }
+        self.faces.push(face);
+        self.is_face_removed.push(false);
+        face_index
+    }
+
+    fn add_vertex(&mut self, position: DVec3, is_locked: bool, is_border: bool) -> u32 {
+        self.positions.push(position);
+        self.vertex_faces.push(Vec::new());
+        self.is_locked.push(is_locked);
+        self.is_border.push(is_border);
+        (self.positions.len() - 1) as u32
+    }
+
+    // Returns the faces containing the edge, with the corner at which the edge starts in each of them
+    fn get_edge_faces(&self, pos_a: u32, pos_b: u32) -> Vec<(u32, usize)> {
+        let mut edge_faces = Vec::new();
+        for face_index in self.vertex_faces[pos_a as usize].iter() {
+            let face = &self.faces[*face_index as usize];
+            for x in 0..3 {
+                let (start, end) = (face[x][0], face[(x + 1) % 3][0]);
+                if (start == pos_a && end == pos_b) || (start == pos_b && end == pos_a) {
+                    edge_faces.push((*face_index, x));
+                }
+            }
+        }
+        edge_faces
+    }
+
+    fn get_neighbors(&self, position: u32) -> Vec<u32> {
+        let mut neighbors = Vec::new();
+        for face_index in self.vertex_faces[position as usize].iter() {
+            for corner in self.faces[*face_index as usize].iter() {
+                if corner[0] != position && !neighbors.contains(&corner[0]) {
+                    neighbors.push(corner[0]);
+                }
+            }
+        }
+        neighbors
+    }
+
+    // Edges of the remaining faces, sorted by key so that passes are deterministic
+    fn get_edges(&self) -> Vec<u64> {
+        let mut edges = HashSet::<u64>::new();
+        for (face, is_removed) in self.faces.iter().zip(self.is_face_removed.iter()) {
+            if !is_removed {
+                for x in 0..3 {
+                    edges.insert(Edge::new(face[x][0], face[(x + 1) % 3][0]).get_key());
+                }
+            }
+        }
+        let mut edges: Vec<u64> = edges.into_iter().collect();
+        edges.sort_unstable();
+        edges
+    }
+
+    fn get_length(&self, edge: u64) -> f64 {
+        let (pos_a, pos_b) = split_edge_key(edge);
+        (self.positions[pos_a as usize] - self.positions[pos_b as usize]).magnitude()
+    }
+
+    fn get_face_normal(&self, face: &[[u32; 4]; 3]) -> DVec3 {
+        let pos_a = self.positions[face[0][0] as usize];
+        let pos_b = self.positions[face[1][0] as usize];
+        let pos_c = self.positions[face[2][0] as usize];
+        (pos_b - pos_a).cross(&(pos_c - pos_a))
+    }
+
+    fn split_long_edges(&mut self, high: f64) {
+        // New halves can still be too long, hence the repeated passes
+        loop {
+            let long_edges: Vec<u64> = self.get_edges().into_iter().filter(|edge| self.get_length(*edge) > high).collect();
+            if long_edges.is_empty() {
+                break;
+            }
+            for edge in long_edges {
+                self.split_edge(edge);
+            }
+        }
+    }
+
+    fn split_edge(&mut self, edge: u64) {
+        let (pos_a, pos_b) = split_edge_key(edge);
+        let is_feature = self.feature_edges.remove(&edge);
+        let middle = (self.positions[pos_a as usize] + self.positions[pos_b as usize]) / 2.0;
+        let pos_m = self.add_vertex(middle, is_feature, is_feature && self.is_border[pos_a as usize] && self.is_border[pos_b as usize]);
+        if is_feature {
+            self.feature_edges.insert(Edge::new(pos_a, pos_m).get_key());
+            self.feature_edges.insert(Edge::new(pos_m, pos_b).get_key());
+        }
+
+        for (face_index, x) in self.get_edge_faces(pos_a, pos_b) {
+            let face = self.faces[face_index as usize];
+            let (start, end, opposite) = (face[x], face[(x + 1) % 3], face[(x + 2) % 3]);
+            let corner_m = self.attributes.blend(pos_m, &[(start, 3), (end, 3)]);
+
+            self.faces[face_index as usize] = [start, corner_m, opposite];
+            self.vertex_faces[end[0] as usize].retain(|x| *x != face_index);
+            self.vertex_faces[pos_m as usize].push(face_index);
+            self.add_face([corner_m, end, opposite]);
+        }
+    }
+
+    fn collapse_short_edges(&mut self, low: f64, high: f64) {
+        for edge in self.get_edges() {
+            if self.get_length(edge) >= low {
+                continue;
+            }
+            let (pos_a, pos_b) = split_edge_key(edge);
+            if self.vertex_faces[pos_a as usize].is_empty() || self.vertex_faces[pos_b as usize].is_empty() {
+                continue; // Already collapsed
+            }
+            // Locked positions stay where they are, so an edge can only collapse towards one of its ends if it is locked
+            let (pos_kept, pos_removed, position) = match (self.is_locked[pos_a as usize], self.is_locked[pos_b as usize]) {
+                (true, true) => continue,
+                (true, false) => (pos_a, pos_b, self.positions[pos_a as usize]),
+                (false, true) => (pos_b, pos_a, self.positions[pos_b as usize]),
+                (false, false) => (pos_a, pos_b, (self.positions[pos_a as usize] + self.positions[pos_b as usize]) / 2.0),
+            };
+            if self.can_collapse(pos_kept, pos_removed, &position, high) {
+                self.collapse_edge(pos_kept, pos_removed, position);
+            }
+        }
+    }
+
+    fn can_collapse(&self, pos_kept: u32, pos_removed: u32, position: &DVec3, high: f64) -> bool {
+        let edge_faces = self.get_edge_faces(pos_kept, pos_removed);
+        if edge_faces.len() != 2 {
+            return false;
+        }
+
+        // Link condition: both ends must only share the two vertices opposite to the edge, or the mesh would fold onto itself
+        let neighbors_kept = self.get_neighbors(pos_kept);
+        let neighbors_removed = self.get_neighbors(pos_removed);
+        if neighbors_kept.iter().filter(|x| neighbors_removed.contains(x)).count() != 2 {
+            return false;
+        }
+
+        // No edge must become too long, or splits and collapses would oscillate
+        for neighbor in neighbors_kept.iter().chain(neighbors_removed.iter()) {
+            if *neighbor != pos_kept && *neighbor != pos_removed && (self.positions[*neighbor as usize] - position).magnitude() > high {
+                return false;
+            }
+        }
+
+        // No face must be flipped
+        for face_index in self.vertex_faces[pos_kept as usize].iter().chain(self.vertex_faces[pos_removed as usize].iter()) {
+            if edge_faces.iter().any(|x| x.0 == *face_index) {
+                continue;
+            }
+            let face = &self.faces[*face_index as usize];
+            let normal = self.get_face_normal(face);
+            let mut positions = face.map(|corner| self.positions[corner[0] as usize]);
+            for (x, corner) in face.iter().enumerate() {
+                if corner[0] == pos_kept || corner[0] == pos_removed {
+                    positions[x] = *position;
+                }
+            }
+            let new_normal = (positions[1] - positions[0]).cross(&(positions[2] - positions[0]));
+            if new_normal.dot(&normal) <= 0.0 {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn collapse_edge(&mut self, pos_kept: u32, pos_removed: u32, position: DVec3) {
+        let edge_faces = self.get_edge_faces(pos_kept, pos_removed);
+
+        // Attributes of an unlocked vertex are the same all around it, so they can be blended once for all its faces
+        let merged_corner = if self.is_locked[pos_kept as usize] {
+            None
+        } else {
+            let (face_index, x) = edge_faces[0];
+            let face = &self.faces[face_index as usize];
+            Some(self.attributes.blend(pos_kept, &[(face[x], 3), (face[(x + 1) % 3], 3)]))
+        };
+
+        for (face_index, _) in edge_faces.iter() {
+            self.is_face_removed[*face_index as usize] = true;
+            for corner in self.faces[*face_index as usize] {
+                self.vertex_faces[corner[0] as usize].retain(|x| x != face_index);
+            }
+        }
+
+        let removed_faces = std::mem::take(&mut self.vertex_faces[pos_removed as usize]);
+        for face_index in self.vertex_faces[pos_kept as usize].iter().chain(removed_faces.iter()) {
+            for corner in self.faces[*face_index as usize].iter_mut() {
+                if corner[0] == pos_kept || corner[0] == pos_removed {
+                    *corner = match merged_corner {
+                        Some(merged_corner) => merged_corner,
+                        None => [pos_kept, corner[1], corner[2], corner[3]],
+                    };
+                }
+            }
+        }
+        self.vertex_faces[pos_kept as usize].extend(removed_faces);
+        self.positions[pos_kept as usize] = position;
+    }
+
+    // Flips edges when it brings the valences of the four vertices involved closer to 6 (4 on borders)
+    fn equalize_valences(&mut self) {
+        for edge in self.get_edges() {
+            if self.feature_edges.contains(&edge) {
+                continue;
+            }
+            let (pos_a, pos_b) = split_edge_key(edge);
+            let edge_faces = self.get_edge_faces(pos_a, pos_b);
+            if edge_faces.len() != 2 {
+                continue;
+            }
+            let ((face_ab, x_ab), (face_ba, x_ba)) = if self.faces[edge_faces[0].0 as usize][edge_faces[0].1][0] == pos_a {
+                (edge_faces[0], edge_faces[1])
+            } else {
+                (edge_faces[1], edge_faces[0])
+            };
+            let corners_ab = self.faces[face_ab as usize];
+            let corners_ba = self.faces[face_ba as usize];
+            let corner_a = corners_ab[x_ab];
+            let corner_b = corners_ab[(x_ab + 1) % 3];
+            let corner_c = corners_ab[(x_ab + 2) % 3];
+            let corner_d = corners_ba[(x_ba + 2) % 3];
+            let (pos_c, pos_d) = (corner_c[0], corner_d[0]);
+
+            let neighbors_c = self.get_neighbors(pos_c);
+            if pos_c == pos_d || neighbors_c.contains(&pos_d) {
+                continue;
+            }
+
+            let deviation = |position: u32, valence: usize| {
+                let target = if self.is_border[position as usize] { 4 } else { 6 };
+                (valence as i32 - target).abs()
+            };
+            let valences = [pos_a, pos_b, pos_c, pos_d].map(|position| self.get_neighbors(position).len());
+            let before = deviation(pos_a, valences[0]) + deviation(pos_b, valences[1]) + deviation(pos_c, valences[2]) + deviation(pos_d, valences[3]);
+            let after = deviation(pos_a, valences[0] - 1) + deviation(pos_b, valences[1] - 1) + deviation(pos_c, valences[2] + 1) + deviation(pos_d, valences[3] + 1);
+            if after >= before {
+                continue;
+            }
+
+            // The quad must be convex, or one of the new faces would be flipped
+            let new_face_acd = [corner_a, corner_d, corner_c];
+            let new_face_dbc = [corner_d, corner_b, corner_c];
+            let normal = self.get_face_normal(&corners_ab) + self.get_face_normal(&corners_ba);
+            if self.get_face_normal(&new_face_acd).dot(&normal) <= 0.0 || self.get_face_normal(&new_face_dbc).dot(&normal) <= 0.0 {
+                continue;
+            }
+
+            self.faces[face_ab as usize] = new_face_acd;
+            self.faces[face_ba as usize] = new_face_dbc;
+            self.vertex_faces[pos_a as usize].retain(|x| *x != face_ba);
+            self.vertex_faces[pos_b as usize].retain(|x| *x != face_ab);
+            self.vertex_faces[pos_c as usize].push(face_ba);
+            self.vertex_faces[pos_d as usize].push(face_ab);
+        }
+    }
+
+    // Moves unlocked vertices towards the centroid of their neighbors, in their tangent plane, then back onto the surface
+    fn relax(&mut self, surface: &Bvh) {
+        let mut positions = self.positions.clone();
+        for position in 0..self.positions.len() as u32 {
+            if self.is_locked[position as usize] || self.vertex_faces[position as usize].is_empty() {
+                continue;
+            }
+            let neighbors = self.get_neighbors(position);
+            let centroid = neighbors.iter().fold(DVec3::default(), |sum, x| sum + self.positions[*x as usize]) / neighbors.len() as f64;
+            let normal = self.vertex_faces[position as usize].iter()
+                .fold(DVec3::default(), |sum, face_index| sum + self.get_face_normal(&self.faces[*face_index as usize]));
+            let p = self.positions[position as usize];
+            let relaxed = match normal.try_normalize(0.0) {
+                Some(normal) => centroid + normal * normal.dot(&(p - centroid)),
+                None => centroid,
+            };
+            positions[position as usize] = surface.get_closest_point(&relaxed, f64::MAX).map_or(relaxed, |closest| closest.point);
+        }
+        self.positions = positions;
+    }
+}
+
+fn split_edge_key(edge: u64) -> (u32, u32) {
+    ((edge >> 32) as u32, edge as u32)
+}
+
+#[cfg(test)]
+mod remesh_tests {
+    use super::*;
+    use crate::mesh::test_utils::read_sphere;
+
+    // Unit square in the XY plane, fanned into slivers like a triangulation of a long edge would
+    fn build_slivers() -> ConnectedMesh {
+        let mut positions = vec![DVec3::new(0., 0., 0.), DVec3::new(1., 0., 0.)];
+        let mut triangles = Vec::new();
+        for i in 0..20 {
+            positions.push(DVec3::new(1.0 - i as f64 / 19.0, 1., 0.));
+            if i > 0 {
+                triangles.push(U32Vec3::new(0, i + 1, i + 2));
+            }
+        }
+        triangles.push(U32Vec3::new(0, 1, 2));
+        ConnectedMesh::from(&SharedMesh { positions, triangles, groups: Vec::new(), normals: None, colors: None, uvs: None })
+    }
+
+    fn get_edge_lengths(shared_mesh: &SharedMesh) -> Vec<f64> {
+        shared_mesh.triangles.iter()
+            .flat_map(|t| (0..3).map(move |x| (t[x], t[(x + 1) % 3])))
+            .map(|(a, b)| (shared_mesh.positions[a as usize] - shared_mesh.positions[b as usize]).magnitude())
+            .collect()
+    }
+
+    fn get_min_angle(shared_mesh: &SharedMesh) -> f64 {
+        let mut min_angle = f64::MAX;
+        for t in shared_mesh.triangles.iter() {
+            for x in 0..3 {
+                let p = shared_mesh.positions[t[x] as usize];
+                let u = shared_mesh.positions[t[(x + 1) % 3] as usize] - p;
+                let v = shared_mesh.positions[t[(x + 2) % 3] as usize] - p;
+                min_angle = min_angle.min(u.angle(&v));
+            }
+        }
+        min_angle.to_degrees()
+    }
+
+    #[test]
+    fn remesh_to_target_length() {
+        let mut connected_mesh = ConnectedMesh::from(&read_sphere());
+        connected_mesh.remesh(0.1, 5);
+
+        let shared_mesh = SharedMesh::from(&connected_mesh);
+        let lengths = get_edge_lengths(&shared_mesh);
+        let mean = lengths.iter().sum::<f64>() / lengths.len() as f64;
+        assert!((mean - 0.1).abs() < 0.02, "mean edge length is {}", mean);
+        // Projected onto the original (faceted) sphere
+        for position in shared_mesh.positions.iter() {
+            assert!(position.magnitude() <= 1.0 + 1e-9 && position.magnitude() > 0.98);
+        }
+    }
+
+    #[test]
+    fn invalid_target_length() {
+        for target_edge_length in [0.0, -0.1, f64::NAN] {
+            let mut connected_mesh = ConnectedMesh::from(&read_sphere());
+            connected_mesh.remesh(target_edge_length, 5);
+            assert_eq!(connected_mesh.face_count(), 1280);
+        }
+    }
+
+    #[test]
+    fn remesh_slivers() {
+        let mut connected_mesh = build_slivers();
+        let before = SharedMesh::from(&connected_mesh);
+        connected_mesh.remesh(0.1, 5);
+
+        let shared_mesh = SharedMesh::from(&connected_mesh);
+        assert!(get_min_angle(&shared_mesh) > 4.0 * get_min_angle(&before));
+        for position in shared_mesh.positions.iter() {
+            assert_eq!(position.z, 0.0);
+            assert!(position.x >= 0.0 && position.x <= 1.0 && position.y >= 0.0 && position.y <= 1.0);
+        }
+        // Border vertices stay in place
+        for position in before.positions.iter() {
+            assert!(shared_mesh.positions.contains(position));
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use nalgebra_glm as glm;
+use glm::U32Vec3;
+use super::{Group, SharedMesh};
+use super::topology::*;
+
+use hashbrown::{HashMap, HashSet};
+
+// Triangles with twice their area below this fraction of their longest edge squared are degenerate
+const DEGENERATE_TOLERANCE: f64 = 1e-12;
+
+/// What `SharedMesh::repair` changed
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct RepairReport {
+    /// Triangles removed because they had repeated vertices or no area
+    pub degenerate_triangles: usize,
+    /// Triangles removed because an earlier triangle used the same three vertices
+    pub duplicate_triangles: usize,
+    /// Edges that were shared by more than two triangles
+    pub non_manifold_edges: usize,
+    /// Vertices added to separate the fans of non-manifold vertices and edges
+    pub split_vertices: usize,
+    /// Triangles whose winding was reversed
+    pub flipped_triangles: usize,
+    /// Components that couldn't be oriented consistently (Möbius strips and the like)
+    pub non_orientable_components: usize,
+}
+
+impl SharedMesh {
+    /// Fixes the defects that `ConnectedMesh` can't represent, in order:
+    /// - removes degenerate triangles and duplicate triangles (whatever their winding)
+    /// - splits non-manifold vertices, so that every vertex has a single fan of triangles.
+    ///   Triangles are only connected through edges with exactly two triangles, so this also splits non-manifold edges.
+    /// - orients triangles consistently within every connected component, with outward normals for closed shells
+    ///
+    /// Groups are updated to the remaining triangles. Attributes of split vertices are copied.
+    pub fn repair(&mut self) -> RepairReport {
+        let mut report = RepairReport::default();
+        self.remove_bad_triangles(&mut report);
+        self.split_non_manifold_vertices(&mut report);
+        self.orient_triangles(&mut report);
+        report
+    }
+
+    pub(crate) fn is_degenerate(&self, triangle: &U32Vec3) -> bool {
+        if triangle[0] == triangle[1] || triangle[1] == triangle[2] || triangle[2] == triangle[0] {
+            return true;
+        }
+        let [a, b, c] = [0, 1, 2].map(|x| self.positions[triangle[x] as usize]);
+        let longest = (b - a).magnitude_squared().max((c - b).magnitude_squared()).max((a - c).magnitude_squared());
+        (b - a).cross(&(c - a)).magnitude() <= DEGENERATE_TOLERANCE * longest
+    }
+
+    fn remove_bad_triangles(&mut self, report: &mut RepairReport) {
+        let mut seen = HashSet::with_capacity(self.triangles.len());
+        let mut kept = Vec::with_capacity(self.triangles.len());
+        // Number of triangles kept before every triangle, to remap groups
+        let mut kept_before = Vec::with_capacity(self.triangles.len() + 1);
+        for triangle in &self.triangles {
+            kept_before.push(kept.len() as u32);
+            if self.is_degenerate(triangle) {
+                report.degenerate_triangles += 1;
+                continue;
+            }
+            let mut key = [triangle[0], triangle[1], triangle[2]];
+            key.sort_unstable();
+            if !seen.insert(key) {
+                report.duplicate_triangles += 1;
+                continue;
+            }
+            kept.push(*triangle);
+        }
+        kept_before.push(kept.len() as u32);
+
+        for group in &mut self.groups {
+            let range = group.triangle_range();
+            let (start, end) = (kept_before[range.start.min(self.triangles.len())], kept_before[range.end.min(self.triangles.len())]);
+            *group = Group::new(3 * start, 3 * (end - start));
+        }
+        self.triangles = kept;
+    }
+
+    fn split_non_manifold_vertices(&mut self, report: &mut RepairReport) {
+        let edges = build_edge_map(&self.triangles);
+        report.non_manifold_edges = edges.values().filter(|faces| faces.len() > 2).count();
+
+        let mut fans = get_corner_fans(&self.triangles, &edges);
+        // The first fan found around a vertex keeps it, the other fans get a copy
+        let mut vertex_of_fan: HashMap<u32, u32> = HashMap::new();
+        let mut is_used = vec![false; self.positions.len()];
+        for corner in 0..self.triangles.len() as u32 * 3 {
+            let (t, x) = ((corner / 3) as usize, (corner % 3) as usize);
+            let vertex = self.triangles[t][x];
+            let fan = fans.find(corner);
+            let fan_vertex = match vertex_of_fan.get(&fan) {
+                Some(&fan_vertex) => fan_vertex,
+                None => {
+                    let fan_vertex = if is_used[vertex as usize] {
+                        report.split_vertices += 1;
+                        self.copy_vertex(vertex)
+                    } else {
+                        is_used[vertex as usize] = true;
+                        vertex
+                    };
+                    vertex_of_fan.insert(fan, fan_vertex);
+                    fan_vertex
+                }
+            };
+            self.triangles[t][x] = fan_vertex;
+        }
+    }
+
+    pub(crate) fn copy_vertex(&mut self, vertex: u32) -> u32 {
+        let i = vertex as usize;
+        self.positions.push(self.positions[i]);
+        if let Some(normals) = self.normals.as_mut().filter(|normals| i < normals.len()) {
+            normals.push(normals[i]);
+        }
+        if let Some(colors) = self.colors.as_mut().filter(|colors| i < colors.len()) {
+            colors.push(colors[i]);
+        }
+        if let Some(uvs) = self.uvs.as_mut().filter(|uvs| i < uvs.len()) {
+            uvs.push(uvs[i]);
+        }
+        self.positions.len() as u32 - 1
+    }
+
+    fn orient_triangles(&mut self, report: &mut RepairReport) {
+        let edges = build_edge_map(&self.triangles);
+        let (components, component_count) = get_components(&self.triangles, &edges);
+
+        let mut is_closed = vec![true; component_count];
+        for faces in edges.values().filter(|faces| faces.len() != 2) {
+            for &(t, _) in faces {
+                is_closed[components[t as usize] as usize] = false;
+            }
+        }
+        let (flips, is_orientable) = get_relative_flips(&self.triangles, &edges, &components, component_count);
+
+        let mut sizes = vec![0; component_count];
+        let mut flipped = vec![0; component_count];
+        let mut volumes = vec![0.0; component_count];
+        for (t, triangle) in self.triangles.iter().enumerate() {
+            let component = components[t] as usize;
+            let flip = flips[t];
+            let [a, b, c] = [0, 1, 2].map(|x| self.positions[triangle[x] as usize]);
+            let volume = a.dot(&b.cross(&c));
+            sizes[component] += 1;
+            if flip {
+                flipped[component] += 1;
+                volumes[component] -= volume;
+            } else {
+                volumes[component] += volume;
+            }
+        }
+
+        // Closed shells face outwards, other components keep the winding of most of their triangles
+        let inverts: Vec<bool> = (0..component_count).map(|c| {
+            if is_closed[c] && is_orientable[c] {
+                volumes[c] < 0.0
+            } else {
+                2 * flipped[c] > sizes[c]
+            }
+        }).collect();
+
+        for (t, triangle) in self.triangles.iter_mut().enumerate() {
+            if flips[t] != inverts[components[t] as usize] {
+                triangle.swap_rows(1, 2);
+                report.flipped_triangles += 1;
+            }
+        }
+        report.non_orientable_components = is_orientable.iter().filter(|&&orientable| !orientable).count();
+    }
+}
+
+#[cfg(test)]
+mod repair_tests {
+    use crate::mesh::*;
+
+    // Unit cube with outward triangles, vertex x + 2y + 4z at (x, y, z)
+    const CUBE: [[u32; 3]; 12] = [
+        [0, 2, 3], [0, 3, 1], [4, 5, 7], [4, 7, 6], [0, 1, 5], [0, 5, 4],
+        [2, 6, 7], [2, 7, 3], [0, 4, 6], [0, 6, 2], [1, 3, 7], [1, 7, 5],
+    ];
+
+    fn get_cube_positions(offset: DVec3) -> Vec<DVec3> {
+        (0..8).map(|i| offset + DVec3::new((i & 1) as f64, ((i >> 1) & 1) as f64, ((i >> 2) & 1) as f64)).collect()
+    }
+
+    fn build_mesh(positions: Vec<DVec3>, triangles: &[[u32; 3]]) -> SharedMesh {
+        let triangles = triangles.iter().map(|t| U32Vec3::new(t[0], t[1], t[2])).collect();
+        SharedMesh { positions, triangles, groups: Vec::new(), normals: None, colors: None, uvs: None }
+    }
+
+    // Two cubes, the second one using vertices of the first as given by `shared` (vertex of second cube, vertex of first cube)
+    fn build_two_cubes(offset: DVec3, shared: &[(u32, u32)]) -> SharedMesh {
+        let mut positions = get_cube_positions(DVec3::zeros());
+        positions.extend(get_cube_positions(offset));
+        let mut triangles = CUBE.to_vec();
+        triangles.extend(CUBE.iter().map(|t| t.map(|v| shared.iter().find(|s| s.0 == v).map_or(v + 8, |s| s.1))));
+        build_mesh(positions, &triangles)
+    }
+
+    fn get_volume(shared_mesh: &SharedMesh) -> f64 {
+        shared_mesh.triangles.iter().map(|t| {
+            let [a, b, c] = [0, 1, 2].map(|x| shared_mesh.positions[t[x] as usize]);
+            a.dot(&b.cross(&c)) / 6.0
+        }).sum()
+    }
+
+    fn assert_closed_manifold(shared_mesh: &SharedMesh) {
+        let connected_mesh = ConnectedMesh::from(shared_mesh);
+        for i in 0..connected_mesh.nodes.len() as u32 {
+            let twin = connected_mesh.get_twin(i).expect("Border or non-manifold edge");
+            // Twins walk the edge in opposite directions
+            assert_eq!(connected_mesh.nodes[twin as usize].position, connected_mesh.nodes[connected_mesh.nodes[i as usize].relative as usize].position);
+        }
+    }
+
+    #[test]
+    fn removes_degenerate_and_duplicate_triangles() {
+        let mut positions = get_cube_positions(DVec3::zeros());
+        positions.push(DVec3::new(0.5, 0., 0.));
+        let mut triangles = CUBE.to_vec();
+        triangles.extend([[3, 2, 0], [0, 0, 1], [0, 8, 1], [0, 3, 2]]);
+        let mut shared_mesh = build_mesh(positions, &triangles);
+        shared_mesh.groups = vec![Group::new(0, 18), Group::new(18, 30), Group::new(48, 0)];
+
+        let report = shared_mesh.repair();
+
+        assert_eq!(report.degenerate_triangles, 2);
+        assert_eq!(report.duplicate_triangles, 2);
+        assert_eq!(report.flipped_triangles, 0);
+        assert_eq!(shared_mesh.triangles.len(), 12);
+        assert_eq!(shared_mesh.groups, vec![Group::new(0, 18), Group::new(18, 18), Group::new(36, 0)]);
+        assert_closed_manifold(&shared_mesh);
+    }
+
+    #[test]
+    fn orients_closed_shells_outwards() {
+        let mut triangles: Vec<[u32; 3]> = CUBE.iter().map(|t| [t[0], t[2], t[1]]).collect();
+        triangles[3] = CUBE[3];
+        let mut shared_mesh = build_mesh(get_cube_positions(DVec3::zeros()), &triangles);
+
+        let report = shared_mesh.repair();
+
+        assert_eq!(report.flipped_triangles, 11);
+        assert_eq!(report.non_orientable_components, 0);
+        assert!((get_volume(&shared_mesh) - 1.0).abs() < 1e-9);
+        assert_closed_manifold(&shared_mesh);
+        assert_eq!(shared_mesh.repair(), RepairReport::default());
+    }
+
+    #[test]
+    fn splits_non_manifold_vertices_and_edges() {
+        // Cubes touching on a vertex, then on an edge
+        let mut shared_mesh = build_two_cubes(DVec3::new(1., 1., 1.), &[(0, 7)]);
+        let report = shared_mesh.repair();
+        assert_eq!(report.non_manifold_edges, 0);
+        assert_eq!(report.split_vertices, 1);
+        assert_eq!(shared_mesh.positions.len(), 17);
+        assert_closed_manifold(&shared_mesh);
+
+        let mut shared_mesh = build_two_cubes(DVec3::new(1., 1., 0.), &[(0, 3), (4, 7)]);
+        // Second cube inside out
+        for triangle in shared_mesh.triangles.iter_mut().skip(12) {
+            triangle.swap_rows(0, 1);
+        }
+        let report = shared_mesh.repair();
+        assert_eq!(report.non_manifold_edges, 1);
+        assert_eq!(report.split_vertices, 2);
+        assert_eq!(report.flipped_triangles, 12);
+        assert!((get_volume(&shared_mesh) - 2.0).abs() < 1e-9);
+        assert_closed_manifold(&shared_mesh);
+    }
+
+    #[test]
+    fn orients_open_surfaces_by_majority() {
+        // Open box (no top), with one triangle flipped
+        let mut triangles: Vec<[u32; 3]> = CUBE.iter().enumerate().filter(|(i, _)| *i != 2 && *i != 3).map(|(_, t)| *t).collect();
+        triangles[5] = [triangles[5][0], triangles[5][2], triangles[5][1]];
+        let mut shared_mesh = build_mesh(get_cube_positions(DVec3::zeros()), &triangles);
+
+        let report = shared_mesh.repair();
+
+        assert_eq!(report.flipped_triangles, 1);
+        assert_eq!(shared_mesh.triangles[5], U32Vec3::new(2, 7, 3));
+    }
+}
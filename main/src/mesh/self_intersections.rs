@@ -0,0 +1,191 @@
+use nalgebra_glm as glm;
+use glm::DVec3;
+use super::SharedMesh;
+use crate::base::Box3;
+use crate::spatial::Bvh;
+use crate::spatial::predicates::*;
+
+/// Pair of intersecting triangles, with the smallest index first
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SelfIntersection {
+    pub triangles: [u32; 2],
+    /// Where the triangles cross, computed in floating point. None for coplanar overlaps, which have no segment.
+    pub segment: Option<[DVec3; 2]>,
+}
+
+impl SharedMesh {
+    /// Finds the pairs of triangles that intersect, with exact predicates.
+    /// Triangles sharing vertices only intersect when they overlap beyond what they share (a fold over a shared edge,
+    /// or a triangle going through its neighbor), so vertices must be welded for adjacent triangles to be recognized.
+    /// Degenerate triangles are ignored. Pairs are sorted.
+    pub fn find_self_intersections(&self, compute_segments: bool) -> Vec<SelfIntersection> {
+        let bvh = Bvh::from(self);
+        let mut intersections = Vec::new();
+        let mut candidates = Vec::new();
+        for (t1, triangle) in self.triangles.iter().enumerate() {
+            let vertices = self.get_triangle_positions(t1);
+            if self.is_degenerate(triangle) {
+                continue;
+            }
+            let mut bounds = Box3::unfitted();
+            for vertex in vertices.iter() {
+                bounds.expand(vertex);
+            }
+            candidates.clear();
+            bvh.visit_box_leaves(&bounds, |t2| {
+                if t2 as usize > t1 {
+                    candidates.push(t2);
+                }
+            });
+            candidates.sort_unstable();
+
+            for &t2 in &candidates {
+                let other = &self.triangles[t2 as usize];
+                if self.is_degenerate(other) || !self.do_triangles_intersect(t1, t2 as usize) {
+                    continue;
+                }
+                intersections.push(SelfIntersection {
+                    triangles: [t1 as u32, t2],
+                    segment: if compute_segments { get_intersection_segment(&vertices, &self.get_triangle_positions(t2 as usize)) } else { None },
+                });
+            }
+        }
+        intersections
+    }
+
+    fn get_triangle_positions(&self, triangle: usize) -> [DVec3; 3] {
+        let triangle = &self.triangles[triangle];
+        [0, 1, 2].map(|x| self.positions[triangle[x] as usize])
+    }
+
+    fn do_triangles_intersect(&self, t1: usize, t2: usize) -> bool {
+        let (triangle1, triangle2) = (&self.triangles[t1], &self.triangles[t2]);
+        // Corner of the second triangle at every vertex of the first one, if shared
+        let shared = [0, 1, 2].map(|x| (0..3).find(|&y| triangle2[y] == triangle1[x]));
+        let p = self.get_triangle_positions(t1);
+        let q = self.get_triangle_positions(t2);
+        match shared.iter().filter(|corner| corner.is_some()).count() {
+            0 => triangles_intersect(&p, &q),
+            // The intersection starts at the shared vertex, and if it goes further it ends on an opposite edge
+            1 => {
+                let x = shared.iter().position(|corner| corner.is_some()).unwrap();
+                let y = shared[x].unwrap();
+                segment_intersects_triangle(&p[(x + 1) % 3], &p[(x + 2) % 3], &q[0], &q[1], &q[2])
+                    || segment_intersects_triangle(&q[(y + 1) % 3], &q[(y + 2) % 3], &p[0], &p[1], &p[2])
+            },
+            // Triangles sharing an edge only overlap when they are coplanar and on the same side of the edge
+            2 => {
+                let x = shared.iter().position(|corner| corner.is_none()).unwrap();
+                let y = (0..3).find(|&y| !triangle1.iter().any(|&v| v == triangle2[y])).unwrap();
+                let (a, b) = (&p[(x + 1) % 3], &p[(x + 2) % 3]);
+                if orient3d(a, b, &p[x], &q[y]) != 0.0 {
+                    return false;
+                }
+                let axis = get_dominant_axis(&p[0], &p[1], &p[2]);
+                let [a, b, c, d] = [a, b, &p[x], &q[y]].map(|point| project(point, axis));
+                let (oc, od) = (orient2d(&a, &b, &c), orient2d(&a, &b, &d));
+                (oc > 0.0 && od > 0.0) || (oc < 0.0 && od < 0.0)
+            },
+            _ => true,
+        }
+    }
+}
+
+// Intersection of the line where the planes of both triangles meet with each triangle, as two intervals along the line
+fn get_intersection_segment(p: &[DVec3; 3], q: &[DVec3; 3]) -> Option<[DVec3; 2]> {
+    let (normal_p, normal_q) = ((p[1] - p[0]).cross(&(p[2] - p[0])), (q[1] - q[0]).cross(&(q[2] - q[0])));
+    let direction = normal_p.cross(&normal_q);
+    if direction == DVec3::zeros() {
+        return None;
+    }
+    let (p_start, p_end) = get_interval_on_plane(p, &normal_q, &q[0], &direction)?;
+    let (q_start, q_end) = get_interval_on_plane(q, &normal_p, &p[0], &direction)?;
+    let start = if direction.dot(&p_start) >= direction.dot(&q_start) { p_start } else { q_start };
+    let end = if direction.dot(&p_end) <= direction.dot(&q_end) { p_end } else { q_end };
+    Some([start, end])
+}
+
+// Extreme points along `direction` of where the triangle crosses the plane
+fn get_interval_on_plane(triangle: &[DVec3; 3], normal: &DVec3, origin: &DVec3, direction: &DVec3) -> Option<(DVec3, DVec3)> {
+    let distances = triangle.map(|vertex| normal.dot(&(vertex - origin)));
+    let mut points = Vec::with_capacity(3);
+    for i in 0..3 {
+        let j = (i + 1) % 3;
+        if distances[i] == 0.0 {
+            points.push(triangle[i]);
+        } else if (distances[i] < 0.0 && distances[j] > 0.0) || (distances[i] > 0.0 && distances[j] < 0.0) {
+            points.push(triangle[i] + (triangle[j] - triangle[i]) * (distances[i] / (distances[i] - distances[j])));
+        }
+    }
+    let start = points.iter().min_by(|a, b| direction.dot(a).total_cmp(&direction.dot(b)))?;
+    let end = points.iter().max_by(|a, b| direction.dot(a).total_cmp(&direction.dot(b)))?;
+    Some((*start, *end))
+}
+
+#[cfg(test)]
+mod self_intersections_tests {
+    use crate::mesh::*;
+    use crate::mesh::test_utils::read_sphere;
+    use std::io::{BufReader, BufWriter};
+
+    #[test]
+    fn closed_mesh_has_no_self_intersections() {
+        assert_eq!(read_sphere().find_self_intersections(true), Vec::new());
+    }
+
+    #[test]
+    fn crossing_spheres() {
+        // Two spheres overlapping on a circle of radius sqrt(3)/2
+        let moved = read_sphere();
+        let mut shared_mesh = SharedMesh::combine(read_sphere(), SharedMesh {
+            positions: moved.positions.iter().map(|p| p + DVec3::new(1., 0., 0.)).collect(),
+            ..moved
+        });
+        let intersections = shared_mesh.find_self_intersections(true);
+        assert!(!intersections.is_empty());
+        for intersection in &intersections {
+            let [t1, t2] = intersection.triangles;
+            assert!(t1 < 1280 && t2 >= 1280);
+            let segment = intersection.segment.unwrap();
+            for point in segment.iter() {
+                assert!((point.x - 0.5).abs() < 0.05 && ((point.y * point.y + point.z * point.z).sqrt() - 0.75f64.sqrt()).abs() < 0.05);
+            }
+        }
+
+        let mut obj = Vec::new();
+        let segments: Vec<[DVec3; 2]> = intersections.iter().filter_map(|intersection| intersection.segment).collect();
+        crate::io::obj::write_segments(&segments, &mut BufWriter::new(&mut obj));
+        assert_eq!(String::from_utf8(obj).unwrap().lines().filter(|line| line.starts_with("l ")).count(), segments.len());
+
+        // Moving the spheres apart removes every intersection
+        for position in shared_mesh.positions.iter_mut().skip(642) {
+            position.x += 2.0;
+        }
+        assert!(shared_mesh.find_self_intersections(false).is_empty());
+    }
+
+    #[test]
+    fn folds_and_pokes_between_neighbors() {
+        // Square split along its diagonal, then folded onto itself
+        let positions = vec![DVec3::new(0., 0., 0.), DVec3::new(1., 0., 0.), DVec3::new(1., 1., 0.), DVec3::new(0., 1., 0.), DVec3::new(0.8, 0.6, -1.)];
+        let square = vec![U32Vec3::new(0, 1, 2), U32Vec3::new(0, 2, 3)];
+        let mut shared_mesh = SharedMesh { positions, triangles: square, groups: Vec::new(), normals: None, colors: None, uvs: None };
+        assert!(shared_mesh.find_self_intersections(false).is_empty());
+
+        shared_mesh.positions[3] = DVec3::new(0.8, 0.2, 0.);
+        let intersections = shared_mesh.find_self_intersections(true);
+        assert_eq!(intersections.len(), 1);
+        assert_eq!(intersections[0].segment, None);
+
+        shared_mesh.positions[3] = DVec3::new(0., 1., 0.);
+        // Hanging below an edge of the square
+        shared_mesh.triangles.push(U32Vec3::new(2, 4, 1));
+        assert!(shared_mesh.find_self_intersections(false).is_empty());
+        // Sharing a corner with both triangles of the square, and going through the first one
+        shared_mesh.positions[4] = DVec3::new(0.8, 0.3, -1.);
+        shared_mesh.positions.push(DVec3::new(0.8, 0.3, 1.));
+        shared_mesh.triangles[2] = U32Vec3::new(2, 4, 5);
+        let intersections = shared_mesh.find_self_intersections(false);
+        assert_eq!(intersections.iter().map(|i| i.triangles).collect::<Vec<_>>(), vec![[0, 2]]);
+    }
+}
@@ -1,14 +1,16 @@
 use nalgebra_glm as glm;
-use glm::{DVec3, U32Vec3};
+use glm::{DVec2, DVec3, U32Vec3};
 use super::Group;
 use std::convert::TryInto;
 
+#[derive(Debug, Clone)]
 pub struct SharedMesh {
     pub groups: Vec<Group>,
     pub triangles: Vec<U32Vec3>,
     pub positions: Vec<DVec3>,
     pub normals: Option<Vec<DVec3>>,
     pub colors: Option<Vec<DVec3>>,
+    pub uvs: Option<Vec<DVec2>>,
 }
 
 impl SharedMesh {
@@ -32,6 +34,7 @@ impl Default for SharedMesh {
             positions: Vec::new(),
             normals: Some(Vec::new()),
             colors: Some(Vec::new()),
+            uvs: None,
         }
     }
 }
\ No newline at end of file
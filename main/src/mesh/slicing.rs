@@ -0,0 +1,409 @@
+use nalgebra_glm as glm;
+use glm::{DVec2, DVec3, U32Vec3};
+use hashbrown::{HashMap, HashSet};
+use super::{Group, SharedMesh};
+use super::topology::get_edge_key;
+use crate::base::{Box3, Plane};
+
+// Distance to a plane under which vertices count as on it, relative to the size of the mesh
+const PLANE_EPSILON: f64 = 1e-12;
+
+// Vertices on a plane, up to rounding errors, count as above it. Crossings are then either such a vertex, or an edge going strictly
+// from one side to the other, identified by the edge key (the key of a vertex is its own edge key with itself).
+// Triangles sharing an edge or a vertex agree on their crossings, so contours always join exactly.
+
+/// Polyline where a mesh crosses a plane
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contour {
+    /// The first point isn't repeated at the end of closed contours. Seen from the side the normal of the plane points to,
+    /// outer contours of outward oriented solids run counterclockwise, and contours of their holes clockwise.
+    pub points: Vec<DVec3>,
+    /// Contours of open or non-manifold meshes may end on a border
+    pub is_closed: bool,
+}
+
+impl SharedMesh {
+    /// Cross-sections of the mesh by each of the planes
+    pub fn slice(&self, planes: &[Plane]) -> Vec<Vec<Contour>> {
+        planes.iter().map(|plane| {
+            let distances = self.get_plane_distances(plane);
+            let segments = self.get_crossing_segments(&distances);
+            chain_segments(&segments).into_iter().map(|(crossings, is_closed)| Contour {
+                points: crossings.iter().map(|&crossing| self.get_crossing_position(crossing, &distances)).collect(),
+                is_closed,
+            }).collect()
+        }).collect()
+    }
+
+    /// Cuts the mesh in two halves, below and above the plane. Vertex attributes are interpolated along cut edges.
+    /// With `cap`, closed contours are filled with triangles facing out of each half, so that closed meshes give closed halves.
+    /// Caps share vertices with the cut unless the mesh has normals, in which case they get their own flat vertices.
+    /// Contours that can't be triangulated, such as ones touching themselves, are left open. The caps get a group of their own
+    /// when the mesh has groups.
+    pub fn split_by_plane(&self, plane: &Plane, cap: bool) -> [SharedMesh; 2] {
+        let distances = self.get_plane_distances(plane);
+        let vertex_count = self.positions.len();
+        let mut cut = SharedMesh {
+            groups: Vec::new(),
+            triangles: Vec::with_capacity(self.triangles.len()),
+            positions: self.positions.clone(),
+            normals: self.normals.clone().filter(|normals| normals.len() == vertex_count),
+            colors: self.colors.clone().filter(|colors| colors.len() == vertex_count),
+            uvs: self.uvs.clone().filter(|uvs| uvs.len() == vertex_count),
+        };
+
+        // Triangles of both halves, in the order of the triangles they come from
+        let mut crossing_vertices: HashMap<u64, u32> = HashMap::new();
+        let mut is_above = Vec::with_capacity(self.triangles.len());
+        let mut sources = Vec::with_capacity(self.triangles.len());
+        for (t, triangle) in self.triangles.iter().enumerate() {
+            let above = [0, 1, 2].map(|x| distances[triangle[x] as usize] >= 0.0);
+            let lone = (0..3).find(|&x| above[x] != above[(x + 1) % 3] && above[x] != above[(x + 2) % 3]);
+            let Some(x) = lone else {
+                cut.triangles.push(*triangle);
+                is_above.push(above[0]);
+                sources.push(t);
+                continue;
+            };
+            // The lone vertex keeps a triangle, and the other two a quad
+            let (v, next, previous) = (triangle[x], triangle[(x + 1) % 3], triangle[(x + 2) % 3]);
+            let p = cut.get_crossing_vertex(get_crossing(v, next, &distances), &distances, &mut crossing_vertices);
+            let q = cut.get_crossing_vertex(get_crossing(previous, v, &distances), &distances, &mut crossing_vertices);
+            for (new_triangle, side) in [([v, p, q], above[x]), ([p, next, previous], !above[x]), ([p, previous, q], !above[x])] {
+                // Crossings on vertices make some of these triangles degenerate
+                if new_triangle[0] != new_triangle[1] && new_triangle[1] != new_triangle[2] && new_triangle[2] != new_triangle[0] {
+                    cut.triangles.push(U32Vec3::new(new_triangle[0], new_triangle[1], new_triangle[2]));
+                    is_above.push(side);
+                    sources.push(t);
+                }
+            }
+        }
+        cut.groups = self.groups.iter().map(|group| {
+            let range = group.triangle_range();
+            let first = sources.partition_point(|&t| t < range.start);
+            let count = sources.partition_point(|&t| t < range.end) - first;
+            Group::new(first as u32 * 3, count as u32 * 3)
+        }).collect();
+
+        if cap {
+            let first_cap = cut.triangles.len();
+            let segments = self.get_crossing_segments(&distances);
+            let contours: Vec<Vec<u32>> = chain_segments(&segments).into_iter()
+                .filter(|(crossings, is_closed)| *is_closed && crossings.len() >= 3)
+                .map(|(crossings, _)| crossings.iter().map(|crossing| match crossing_vertices.get(crossing) {
+                    Some(vertex) => *vertex,
+                    None => (*crossing >> 32) as u32,
+                }).collect())
+                .collect();
+            cut.cap_contours(plane, &contours, &mut is_above);
+            if !self.groups.is_empty() && cut.triangles.len() > first_cap {
+                cut.groups.push(Group::new(first_cap as u32 * 3, (cut.triangles.len() - first_cap) as u32 * 3));
+            }
+        }
+
+        let mut new_indices = vec![u32::MAX; cut.positions.len()];
+        [false, true].map(|side| {
+            let triangles: Vec<usize> = (0..cut.triangles.len()).filter(|&t| is_above[t] == side).collect();
+            cut.extract_triangles(&triangles, &mut new_indices)
+        })
+    }
+
+    // Signed distances of the vertices, with the ones within rounding errors of the plane snapped onto it,
+    // so that cuts don't make slivers or nearly coincident points
+    fn get_plane_distances(&self, plane: &Plane) -> Vec<f64> {
+        let mut bounds = Box3::unfitted();
+        for position in self.positions.iter() {
+            bounds.expand(position);
+        }
+        let epsilon = PLANE_EPSILON * bounds.diagonal().max(f64::MIN_POSITIVE);
+        self.positions.iter().map(|p| {
+            let distance = plane.signed_distance(p);
+            if distance.abs() <= epsilon { 0.0 } else { distance }
+        }).collect()
+    }
+
+    // Directed segments between the crossings of each triangle, with the inside of the mesh on their left seen from above
+    // the plane: they go from where the triangle goes below the plane to where it goes back above it
+    fn get_crossing_segments(&self, distances: &[f64]) -> Vec<[u64; 2]> {
+        let mut segments = Vec::new();
+        for triangle in self.triangles.iter() {
+            let (mut start, mut end) = (None, None);
+            for x in 0..3 {
+                let (a, b) = (triangle[x], triangle[(x + 1) % 3]);
+                match (distances[a as usize] >= 0.0, distances[b as usize] >= 0.0) {
+                    (true, false) => start = Some(get_crossing(a, b, distances)),
+                    (false, true) => end = Some(get_crossing(a, b, distances)),
+                    _ => {},
+                }
+            }
+            // Triangles touching the plane with a single vertex have no segment
+            if let (Some(start), Some(end)) = (start, end) {
+                if start != end {
+                    segments.push([start, end]);
+                }
+            }
+        }
+        segments
+    }
+
+    fn get_crossing_position(&self, crossing: u64, distances: &[f64]) -> DVec3 {
+        let (a, b) = ((crossing >> 32) as usize, (crossing & 0xFFFF_FFFF) as usize);
+        if a == b {
+            return self.positions[a];
+        }
+        let t = distances[a] / (distances[a] - distances[b]);
+        self.positions[a] + (self.positions[b] - self.positions[a]) * t
+    }
+
+    // Vertex at a crossing, interpolated the first time an edge crossing is met
+    fn get_crossing_vertex(&mut self, crossing: u64, distances: &[f64], crossing_vertices: &mut HashMap<u64, u32>) -> u32 {
+        let (a, b) = ((crossing >> 32) as usize, (crossing & 0xFFFF_FFFF) as usize);
+        if a == b {
+            return a as u32;
+        }
+        if let Some(vertex) = crossing_vertices.get(&crossing) {
+            return *vertex;
+        }
+        let t = distances[a] / (distances[a] - distances[b]);
+        self.positions.push(self.positions[a] + (self.positions[b] - self.positions[a]) * t);
+        if let Some(normals) = self.normals.as_mut() {
+            let normal = normals[a] + (normals[b] - normals[a]) * t;
+            normals.push(normal.try_normalize(0.0).unwrap_or(normal));
+        }
+        if let Some(colors) = self.colors.as_mut() {
+            colors.push(colors[a] + (colors[b] - colors[a]) * t);
+        }
+        if let Some(uvs) = self.uvs.as_mut() {
+            uvs.push(uvs[a] + (uvs[b] - uvs[a]) * t);
+        }
+        let vertex = self.positions.len() as u32 - 1;
+        crossing_vertices.insert(crossing, vertex);
+        vertex
+    }
+
+    // Triangulates the contours in the plane, facing up under the plane and down above it
+    fn cap_contours(&mut self, plane: &Plane, contours: &[Vec<u32>], is_above: &mut Vec<bool>) {
+        let axis = if plane.normal.x.abs() < 0.9 { DVec3::x() } else { DVec3::y() };
+        let u = plane.normal.cross(&axis).normalize();
+        let v = plane.normal.cross(&u);
+
+        // Points of the triangulation, once per vertex
+        let mut points = Vec::new();
+        let mut point_vertices = Vec::new();
+        let mut vertex_points: HashMap<u32, usize> = HashMap::new();
+        let contours: Vec<Vec<usize>> = contours.iter().map(|contour| {
+            let mut indices: Vec<usize> = contour.iter().map(|&vertex| *vertex_points.entry(vertex).or_insert_with(|| {
+                let p = self.positions[vertex as usize];
+                points.push((p.dot(&u), p.dot(&v)));
+                point_vertices.push(vertex);
+                points.len() - 1
+            })).collect();
+            indices.push(indices[0]);
+            indices
+        }).collect();
+        let Ok(triangles) = cdt::triangulate_contours(&points, &contours) else {
+            return;
+        };
+
+        // Vertices of each cap, which are the ones of the cut unless the cap needs its own normals
+        let has_normals = self.normals.is_some();
+        let cap_vertices = [plane.normal, -plane.normal].map(|normal| {
+            if !has_normals {
+                return point_vertices.clone();
+            }
+            point_vertices.iter().map(|&vertex| {
+                let copy = self.copy_vertex(vertex);
+                self.normals.as_mut().unwrap()[copy as usize] = normal;
+                if let Some(uvs) = self.uvs.as_mut() {
+                    let (x, y) = points[vertex_points[&vertex]];
+                    uvs[copy as usize] = DVec2::new(x, y);
+                }
+                copy
+            }).collect()
+        });
+
+        let is_counterclockwise = |(a, b, c): &(usize, usize, usize)| {
+            let (a, b, c) = (points[*a], points[*b], points[*c]);
+            (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0) > 0.0
+        };
+        for triangle in triangles.iter() {
+            let (a, b, c) = if is_counterclockwise(triangle) { *triangle } else { (triangle.0, triangle.2, triangle.1) };
+            for (side, vertices) in cap_vertices.iter().enumerate() {
+                let [a, b, c] = [a, b, c].map(|point| vertices[point]);
+                self.triangles.push(if side == 0 { U32Vec3::new(a, b, c) } else { U32Vec3::new(a, c, b) });
+                is_above.push(side == 1);
+            }
+        }
+    }
+}
+
+// Crossing of an edge going from one side of the plane to the other
+fn get_crossing(a: u32, b: u32, distances: &[f64]) -> u64 {
+    if distances[a as usize] == 0.0 {
+        get_edge_key(a, a)
+    } else if distances[b as usize] == 0.0 {
+        get_edge_key(b, b)
+    } else {
+        get_edge_key(a, b)
+    }
+}
+
+// Joins segments end to start into polylines of crossings. Open polylines start where no segment ends.
+fn chain_segments(segments: &[[u64; 2]]) -> Vec<(Vec<u64>, bool)> {
+    let mut outgoing: HashMap<u64, Vec<usize>> = HashMap::with_capacity(segments.len());
+    for (s, segment) in segments.iter().enumerate().rev() {
+        outgoing.entry(segment[0]).or_default().push(s);
+    }
+    let ends: HashSet<u64> = segments.iter().map(|segment| segment[1]).collect();
+    let open_starts = (0..segments.len()).filter(|&s| !ends.contains(&segments[s][0]));
+
+    let mut is_used = vec![false; segments.len()];
+    let mut polylines = Vec::new();
+    for first in open_starts.chain(0..segments.len()) {
+        if is_used[first] {
+            continue;
+        }
+        is_used[first] = true;
+        let mut crossings = vec![segments[first][0]];
+        let mut current = first;
+        let is_closed = loop {
+            let end = segments[current][1];
+            if end == crossings[0] {
+                break true;
+            }
+            crossings.push(end);
+            let next = outgoing.get_mut(&end).and_then(|starts| {
+                while let Some(s) = starts.pop() {
+                    if !is_used[s] {
+                        return Some(s);
+                    }
+                }
+                None
+            });
+            match next {
+                Some(next) => {
+                    is_used[next] = true;
+                    current = next;
+                },
+                None => break false,
+            }
+        };
+        polylines.push((crossings, is_closed));
+    }
+    polylines
+}
+
+#[cfg(test)]
+mod slicing_tests {
+    use crate::mesh::*;
+    use crate::base::Plane;
+    use crate::mesh::test_utils::read_sphere;
+
+    // Torus made of a grid of `size` x `size` quads, with a ring of vertices on z = 0
+    fn build_torus(size: u32) -> SharedMesh {
+        let mut positions = Vec::new();
+        let mut triangles = Vec::new();
+        for i in 0..size {
+            for j in 0..size {
+                let (u, v) = (i as f64 / size as f64 * std::f64::consts::TAU, j as f64 / size as f64 * std::f64::consts::TAU);
+                positions.push(DVec3::new((2. + v.cos()) * u.cos(), (2. + v.cos()) * u.sin(), v.sin()));
+                let (a, b, c, d) = (i * size + j, ((i + 1) % size) * size + j, ((i + 1) % size) * size + (j + 1) % size, i * size + (j + 1) % size);
+                triangles.push(U32Vec3::new(a, b, c));
+                triangles.push(U32Vec3::new(a, c, d));
+            }
+        }
+        SharedMesh { positions, triangles, groups: Vec::new(), normals: None, colors: None, uvs: None }
+    }
+
+    // Area enclosed by a contour, positive when it runs counterclockwise around the normal
+    fn get_signed_area(contour: &Contour, normal: &DVec3) -> f64 {
+        let points = &contour.points;
+        (0..points.len()).map(|i| points[i].cross(&points[(i + 1) % points.len()]).dot(normal)).sum::<f64>() / 2.0
+    }
+
+    #[test]
+    fn slice_sphere() {
+        let sphere = read_sphere();
+        let heights = [-0.9, -0.25, 0.0, 0.5];
+        let planes: Vec<Plane> = heights.iter().map(|&z| Plane::new(DVec3::new(0., 0., z), DVec3::z())).collect();
+        let sections = sphere.slice(&planes);
+        assert_eq!(sections.len(), heights.len());
+        for (contours, z) in sections.iter().zip(heights.iter()) {
+            assert_eq!(contours.len(), 1);
+            let contour = &contours[0];
+            assert!(contour.is_closed);
+            let radius = (1.0 - z * z).sqrt();
+            for point in contour.points.iter() {
+                assert!((point.z - z).abs() < 1e-12);
+                assert!(point.xy().magnitude() <= radius + 1e-5 && point.xy().magnitude() > 0.9 * radius);
+            }
+            let area = get_signed_area(contour, &DVec3::z());
+            assert!(area > 0.9 * std::f64::consts::PI * radius * radius && area < std::f64::consts::PI * radius * radius);
+        }
+    }
+
+    #[test]
+    fn slice_through_vertices() {
+        // A ring of vertices lies exactly on the plane, and the section has a hole
+        let torus = build_torus(16);
+        assert!(torus.get_mass_properties().volume > 0.0);
+        let contours = torus.slice(&[Plane::new(DVec3::zeros(), DVec3::z())]).remove(0);
+        assert_eq!(contours.len(), 2);
+        let mut areas: Vec<f64> = contours.iter().map(|contour| get_signed_area(contour, &DVec3::z())).collect();
+        areas.sort_by(f64::total_cmp);
+        assert!(contours.iter().all(|contour| contour.is_closed));
+        assert!(areas[0] < 0.0 && areas[0] > -std::f64::consts::PI);
+        assert!(areas[1] > 8.0 && areas[1] < 9.0 * std::f64::consts::PI);
+        // Contours only go through vertices of the ring
+        let outer = contours.iter().find(|contour| get_signed_area(contour, &DVec3::z()) > 0.0).unwrap();
+        assert_eq!(outer.points.len(), 16);
+    }
+
+    #[test]
+    fn split_and_cap() {
+        for shared_mesh in [read_sphere(), build_torus(16)] {
+            let volume = shared_mesh.get_mass_properties().volume;
+            for plane in [Plane::new(DVec3::new(0., 0., 0.3), DVec3::new(0.2, 0.1, 1.)), Plane::new(DVec3::zeros(), DVec3::z())] {
+                let [below, above] = shared_mesh.split_by_plane(&plane, true);
+                for half in [&below, &above] {
+                    let report = half.analyze();
+                    assert!(report.is_watertight && report.is_orientable, "{:?}", report);
+                }
+                assert!(below.positions.iter().all(|p| plane.signed_distance(p) < 1e-12));
+                assert!(above.positions.iter().all(|p| plane.signed_distance(p) > -1e-12));
+                let (below_volume, above_volume) = (below.get_mass_properties().volume, above.get_mass_properties().volume);
+                assert!(below_volume > 0.0 && above_volume > 0.0);
+                assert!((below_volume + above_volume - volume).abs() < 1e-9);
+
+                let [below, above] = shared_mesh.split_by_plane(&plane, false);
+                assert!(!below.analyze().is_watertight && !above.analyze().is_watertight);
+            }
+        }
+    }
+
+    #[test]
+    fn split_attributes_and_groups() {
+        let mut shared_mesh = read_sphere();
+        shared_mesh.normals = Some(shared_mesh.positions.clone());
+        shared_mesh.colors = Some(shared_mesh.positions.iter().map(|p| DVec3::new(p.z, 0., 0.)).collect());
+        shared_mesh.groups = vec![Group::new(0, 1500), Group::new(1500, 3 * 1280 - 1500)];
+
+        let plane = Plane::new(DVec3::new(0., 0., 0.2), DVec3::z());
+        let [below, above] = shared_mesh.split_by_plane(&plane, true);
+        for half in [&below, &above] {
+            let (normals, colors) = (half.normals.as_ref().unwrap(), half.colors.as_ref().unwrap());
+            assert_eq!(normals.len(), half.positions.len());
+            // Colors follow the height, and caps have flat normals
+            for ((position, normal), color) in half.positions.iter().zip(normals.iter()).zip(colors.iter()) {
+                assert!((color.x - position.z).abs() < 1e-9);
+                assert!((normal.magnitude() - 1.0).abs() < 1e-5);
+            }
+            assert!(normals.iter().any(|n| n.z.abs() == 1.0));
+            // Groups of the sphere, then the cap
+            assert!(half.groups.len() >= 2);
+            assert!(half.triangles[half.groups.last().unwrap().triangle_range()].iter()
+                .all(|t| (0..3).all(|x| normals[t[x] as usize].z.abs() == 1.0)));
+            assert_eq!(half.groups.iter().map(|group| group.index_count() as usize).sum::<usize>(), 3 * half.triangles.len());
+        }
+    }
+}
@@ -0,0 +1,186 @@
+/// Weighting of neighbors in Laplacian smoothing
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LaplacianWeights {
+    /// Every neighbor counts the same. Fast, but also moves vertices along the surface towards regular triangles.
+    Uniform,
+    /// Cotangent weights, which approximate the curvature flow and mostly move vertices along the surface normal.
+    Cotangent,
+}
+
+impl ConnectedMesh {
+    /// Moves every vertex towards the weighted average of its neighbors by `lambda` (usually in ]0, 1]), `iterations` times.
+    /// The optional mask gives a weight per position (0 to keep it in place, 1 for a full step).
+    /// Panics if the mask doesn't have exactly one weight per position.
+    /// When `pin_borders` is set, vertices on borders don't move.
+    /// Laplacian smoothing shrinks the mesh, see `smooth_taubin` for a shrink-free alternative.
+    pub fn smooth_laplacian(&mut self, iterations: u32, lambda: f64, weights: LaplacianWeights, mask: Option<&[f64]>, pin_borders: bool) {
+        let rings = self.get_vertex_rings();
+        let step_weights = self.get_smoothing_step_weights(mask, pin_borders);
+        for _ in 0..iterations {
+            self.smooth_step(lambda, weights, &rings, &step_weights);
+        }
+    }
+
+    /// Taubin λ|μ smoothing: each iteration is a Laplacian step by `lambda` followed by an inflating step by `mu`,
+    /// with `mu` negative and slightly larger than `lambda` in magnitude (for instance 0.5 and -0.53).
+    /// This acts as a low-pass filter that removes noise without shrinking the mesh.
+    /// Mask and borders are handled as in `smooth_laplacian`, and the mask must have one weight per position as well.
+    pub fn smooth_taubin(&mut self, iterations: u32, lambda: f64, mu: f64, weights: LaplacianWeights, mask: Option<&[f64]>, pin_borders: bool) {
+        let rings = self.get_vertex_rings();
+        let step_weights = self.get_smoothing_step_weights(mask, pin_borders);
+        for _ in 0..iterations {
+            self.smooth_step(lambda, weights, &rings, &step_weights);
+            self.smooth_step(mu, weights, &rings, &step_weights);
+        }
+    }
+
+    // Returns the fraction of a step every position moves by
+    fn get_smoothing_step_weights(&self, mask: Option<&[f64]>, pin_borders: bool) -> Vec<f64> {
+        let mut step_weights = match mask {
+            Some(mask) => {
+                assert_eq!(mask.len(), self.positions.len(), "Smoothing mask must have one weight per position");
+                mask.to_vec()
+            },
+            None => vec![1.0; self.positions.len()],
+        };
+        if pin_borders {
+            for i in 0..self.nodes.len() as u32 {
+                if !self.nodes[i as usize].is_removed && self.get_twin(i).is_none() {
+                    step_weights[self.nodes[i as usize].position as usize] = 0.0;
+                    step_weights[self.nodes[self.nodes[i as usize].relative as usize].position as usize] = 0.0;
+                }
+            }
+        }
+        step_weights
+    }
+
+    fn smooth_step(&mut self, factor: f64, weights: LaplacianWeights, rings: &[Option<VertexRing>], step_weights: &[f64]) {
+        // Weighted sum of neighbors and sum of weights, per position
+        let mut sums = vec![(DVec3::default(), 0.0); self.positions.len()];
+        match weights {
+            LaplacianWeights::Uniform => {
+                for (sum, ring) in sums.iter_mut().zip(rings.iter()) {
+                    if let Some(ring) = ring {
+                        *sum = (self.sum_positions(&ring.neighbors), ring.neighbors.len() as f64);
+                    }
+                }
+            },
+            LaplacianWeights::Cotangent => {
+                for i in 0..self.nodes.len() as u32 {
+                    if !self.is_first_node_of_face(i) {
+                        continue;
+                    }
+                    let face = self.get_face_nodes(i).map(|node| self.nodes[node as usize].position as usize);
+                    // Every edge is seen once per face, which gives the cotangents of both angles opposite to it
+                    for x in 0..3 {
+                        let (pos_a, pos_b) = (face[x], face[(x + 1) % 3]);
+                        let u = self.positions[pos_a] - self.positions[face[(x + 2) % 3]];
+                        let v = self.positions[pos_b] - self.positions[face[(x + 2) % 3]];
+                        // Negative weights (obtuse angles) make smoothing unstable
+                        let weight = (0.5 * u.dot(&v) / u.cross(&v).magnitude().max(f64::EPSILON)).max(0.0);
+                        sums[pos_a].0 += self.positions[pos_b] * weight;
+                        sums[pos_a].1 += weight;
+                        sums[pos_b].0 += self.positions[pos_a] * weight;
+                        sums[pos_b].1 += weight;
+                    }
+                }
+            },
+        }
+
+        for (i, (sum, total_weight)) in sums.iter().enumerate() {
+            if *total_weight > 0.0 && step_weights[i] != 0.0 {
+                let position = &mut self.positions[i];
+                *position += (sum / *total_weight - *position) * (factor * step_weights[i]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod smooth_tests {
+    use super::*;
+    use crate::mesh::test_utils::read_sphere;
+
+    // Sphere with deterministic radial noise
+    fn read_noisy_sphere() -> ConnectedMesh {
+        let mut connected_mesh = ConnectedMesh::from(&read_sphere());
+        for (i, position) in connected_mesh.positions.iter_mut().enumerate() {
+            *position *= 1.0 + 0.05 * (i as f64 * 12.9898).sin();
+        }
+        connected_mesh
+    }
+
+    // Returns the mean radius and its standard deviation
+    fn get_radius_stats(connected_mesh: &ConnectedMesh) -> (f64, f64) {
+        let radii: Vec<f64> = connected_mesh.positions.iter().map(|p| p.magnitude()).collect();
+        let mean = radii.iter().sum::<f64>() / radii.len() as f64;
+        let variance = radii.iter().map(|r| (r - mean) * (r - mean)).sum::<f64>() / radii.len() as f64;
+        (mean, variance.sqrt())
+    }
+
+    // Square grid in the XY plane with bumps along Z, except on borders
+    fn build_bumpy_grid(size: u32) -> ConnectedMesh {
+        let mut positions = Vec::new();
+        for y in 0..=size {
+            for x in 0..=size {
+                let is_border = x == 0 || y == 0 || x == size || y == size;
+                positions.push(DVec3::new(x as f64, y as f64, if is_border { 0. } else { ((x * 7 + y * 3) % 5) as f64 * 0.1 }));
+            }
+        }
+        let mut triangles = Vec::new();
+        for y in 0..size {
+            for x in 0..size {
+                let i = y * (size + 1) + x;
+                triangles.push(U32Vec3::new(i, i + 1, i + size + 2));
+                triangles.push(U32Vec3::new(i, i + size + 2, i + size + 1));
+            }
+        }
+        ConnectedMesh::from(&SharedMesh { positions, triangles, groups: Vec::new(), normals: None, colors: None, uvs: None })
+    }
+
+    #[test]
+    fn laplacian_removes_noise() {
+        for weights in [LaplacianWeights::Uniform, LaplacianWeights::Cotangent] {
+            let mut connected_mesh = read_noisy_sphere();
+            let (_, noise) = get_radius_stats(&connected_mesh);
+            connected_mesh.smooth_laplacian(5, 0.5, weights, None, false);
+            let (_, smoothed_noise) = get_radius_stats(&connected_mesh);
+            assert!(smoothed_noise < noise / 3.0, "{:?}: {} -> {}", weights, noise, smoothed_noise);
+        }
+    }
+
+    #[test]
+    fn taubin_does_not_shrink() {
+        let mut laplacian = read_noisy_sphere();
+        let mut taubin = read_noisy_sphere();
+        let (radius, noise) = get_radius_stats(&taubin);
+
+        laplacian.smooth_laplacian(20, 0.5, LaplacianWeights::Uniform, None, false);
+        taubin.smooth_taubin(20, 0.5, -0.53, LaplacianWeights::Uniform, None, false);
+
+        let (laplacian_radius, _) = get_radius_stats(&laplacian);
+        let (taubin_radius, taubin_noise) = get_radius_stats(&taubin);
+        assert!(taubin_noise < noise / 2.0, "{} -> {}", noise, taubin_noise);
+        assert!((taubin_radius - radius).abs() < 0.01, "{} -> {} ({})", radius, taubin_radius, laplacian_radius);
+        assert!((laplacian_radius - radius).abs() > 5.0 * (taubin_radius - radius).abs());
+    }
+
+    #[test]
+    fn pinned_and_masked_vertices_stay() {
+        let mut connected_mesh = build_bumpy_grid(6);
+        let original = connected_mesh.positions.clone();
+        let mut mask = vec![1.0; original.len()];
+        mask[8] = 0.0;
+
+        connected_mesh.smooth_laplacian(10, 0.5, LaplacianWeights::Cotangent, Some(&mask), true);
+
+        for (i, (position, original)) in connected_mesh.positions.iter().zip(original.iter()).enumerate() {
+            let (x, y) = (i % 7, i / 7);
+            if x == 0 || y == 0 || x == 6 || y == 6 || i == 8 {
+                assert_eq!(position, original);
+            } else {
+                assert!(position.z.abs() < 0.2);
+            }
+        }
+    }
+}
@@ -0,0 +1,510 @@
+use std::ops::{Add, Mul};
+
+// Blends values of an attribute channel (normals, colors or uvs).
+// Values blended from the same sources with the same weights are shared, so that attribute seams are preserved.
+struct AttributeBlender<T> {
+    values: Vec<T>,
+    source_count: usize,
+    // Sorted source indices with their weights in sixths, padded with u32::MAX
+    blended: HashMap<[(u32, u8); 3], u32>,
+}
+
+impl<T: Copy + Add<Output = T> + Mul<f64, Output = T>> AttributeBlender<T> {
+    fn new(values: Vec<T>) -> Self {
+        AttributeBlender {
+            source_count: values.len(),
+            values,
+            blended: HashMap::new(),
+        }
+    }
+
+    fn blend(&mut self, corners: &[([u32; 4], u8)], channel: usize) -> u32 {
+        let mut key = [(u32::MAX, 0u8); 3];
+        let mut count = 0;
+        for (corner, weight) in corners.iter() {
+            match key[..count].iter_mut().find(|x| x.0 == corner[channel]) {
+                Some(source) => source.1 += weight,
+                None => {
+                    key[count] = (corner[channel], *weight);
+                    count += 1;
+                }
+            }
+        }
+
+        // All corners share the same value
+        if count == 1 {
+            return key[0].0;
+        }
+
+        key[..count].sort_unstable();
+        let values = &mut self.values;
+        *self.blended.entry(key).or_insert_with(|| {
+            let value = key[..count].iter()
+                .map(|(index, weight)| values[*index as usize] * (*weight as f64 / 6.0))
+                .reduce(|a, b| a + b)
+                .unwrap();
+            values.push(value);
+            (values.len() - 1) as u32
+        })
+    }
+}
+
+// Attribute channels of a mesh being rebuilt, with blending of new corners
+struct CornerAttributes {
+    normals: Option<AttributeBlender<DVec3>>,
+    colors: Option<AttributeBlender<DVec3>>,
+    uvs: Option<AttributeBlender<DVec2>>,
+}
+
+impl CornerAttributes {
+    fn new(connected_mesh: &ConnectedMesh) -> Self {
+        CornerAttributes {
+            normals: connected_mesh.normals.clone().map(AttributeBlender::new),
+            colors: connected_mesh.colors.clone().map(AttributeBlender::new),
+            uvs: connected_mesh.uvs.clone().map(AttributeBlender::new),
+        }
+    }
+
+    // Returns a new corner at the given position, with attributes blended from the given corners (weights in sixths)
+    fn blend(&mut self, position: u32, corners: &[([u32; 4], u8)]) -> [u32; 4] {
+        let mut corner = [position, 0, 0, 0];
+        if let Some(normals) = &mut self.normals {
+            corner[1] = normals.blend(corners, 1);
+        }
+        if let Some(colors) = &mut self.colors {
+            corner[2] = colors.blend(corners, 2);
+        }
+        if let Some(uvs) = &mut self.uvs {
+            corner[3] = uvs.blend(corners, 3);
+        }
+        corner
+    }
+
+    // Builds the subdivided mesh, with blended normals normalized
+    fn build(self, positions: Vec<DVec3>, corners: &[[[u32; 4]; 3]]) -> ConnectedMesh {
+        let normals = self.normals.map(|mut normals| {
+            for normal in normals.values[normals.source_count..].iter_mut() {
+                if let Some(normalized) = normal.try_normalize(0.0) {
+                    *normal = normalized;
+                }
+            }
+            normals.values
+        });
+        ConnectedMesh::from_corners(positions, normals, self.colors.map(|x| x.values), self.uvs.map(|x| x.values), corners)
+    }
+}
+
+// Positions around a vertex, and among them the ones reached through feature edges
+#[derive(Default)]
+struct VertexRing {
+    neighbors: Vec<u32>,
+    feature_neighbors: Vec<u32>,
+}
+
+enum VertexKind {
+    Smooth,
+    Crease(u32, u32),
+    Corner,
+}
+
+impl VertexRing {
+    fn add(&mut self, position: u32, is_feature: bool) {
+        if !self.neighbors.contains(&position) {
+            self.neighbors.push(position);
+        }
+        if is_feature && !self.feature_neighbors.contains(&position) {
+            self.feature_neighbors.push(position);
+        }
+    }
+
+    // A single feature edge (dart) is smoothed like a regular vertex, more than two make a corner
+    fn get_kind(&self) -> VertexKind {
+        match self.feature_neighbors[..] {
+            [] | [_] => VertexKind::Smooth,
+            [a, b] => VertexKind::Crease(a, b),
+            _ => VertexKind::Corner,
+        }
+    }
+}
+
+impl ConnectedMesh {
+    /// Loop subdivision: each iteration splits every triangle in four and smooths the result.
+    /// Borders and feature edges (where normals are split) are subdivided as creases, and vertices where more than two
+    /// of them meet are kept in place. Normals, colors and uvs are interpolated linearly.
+    pub fn subdivide_loop(&mut self, iterations: u32) {
+        for _ in 0..iterations {
+            self.subdivide_loop_once();
+        }
+    }
+
+    /// Kobbelt's √3 subdivision: each iteration inserts a vertex at the center of every triangle and flips the original edges,
+    /// which triples the triangle count. Creases are handled as in `subdivide_loop`, but since they can't be flipped,
+    /// they are trisected every second iteration instead. Normals, colors and uvs are interpolated linearly.
+    pub fn subdivide_sqrt3(&mut self, iterations: u32) {
+        for iteration in 0..iterations {
+            self.subdivide_sqrt3_once(iteration % 2 == 1);
+        }
+    }
+
+    fn subdivide_loop_once(&mut self) {
+        let rings = self.get_vertex_rings();
+
+        let mut positions = self.positions.clone();
+        for (position, ring) in rings.iter().enumerate() {
+            let ring = match ring {
+                Some(ring) => ring,
+                None => continue,
+            };
+            let p = self.positions[position];
+            positions[position] = match ring.get_kind() {
+                VertexKind::Smooth => {
+                    let n = ring.neighbors.len() as f64;
+                    let beta = (5.0 / 8.0 - (3.0 / 8.0 + 0.25 * (2.0 * std::f64::consts::PI / n).cos()).powi(2)) / n;
+                    p * (1.0 - n * beta) + self.sum_positions(&ring.neighbors) * beta
+                },
+                VertexKind::Crease(a, b) => p * 0.75 + (self.positions[a as usize] + self.positions[b as usize]) * 0.125,
+                VertexKind::Corner => p,
+            };
+        }
+
+        let mut attributes = CornerAttributes::new(self);
+        let mut edge_points = HashMap::<u64, u32>::new();
+        let mut corners = Vec::<[[u32; 4]; 3]>::with_capacity(4 * self.face_count as usize);
+
+        for i in 0..self.nodes.len() as u32 {
+            if !self.is_first_node_of_face(i) {
+                continue;
+            }
+            let face = self.get_face_nodes(i);
+            let face_corners = face.map(|node| self.get_corner(node));
+
+            let mut midpoints = [[0u32; 4]; 3];
+            for x in 0..3 {
+                let key = Edge::new(face_corners[x][0], face_corners[(x + 1) % 3][0]).get_key();
+                let position = *edge_points.entry(key).or_insert_with(|| {
+                    positions.push(self.get_loop_edge_point(face[x]));
+                    (positions.len() - 1) as u32
+                });
+                midpoints[x] = attributes.blend(position, &[(face_corners[x], 3), (face_corners[(x + 1) % 3], 3)]);
+            }
+
+            corners.push([face_corners[0], midpoints[0], midpoints[2]]);
+            corners.push([midpoints[0], face_corners[1], midpoints[1]]);
+            corners.push([midpoints[2], midpoints[1], face_corners[2]]);
+            corners.push(midpoints);
+        }
+
+        *self = attributes.build(positions, &corners);
+    }
+
+    // Position of the vertex inserted on the edge going from the given node to its relative
+    fn get_loop_edge_point(&self, node_index: u32) -> DVec3 {
+        let node = &self.nodes[node_index as usize];
+        let node_b = &self.nodes[node.relative as usize];
+        let pos_a = self.positions[node.position as usize];
+        let pos_b = self.positions[node_b.position as usize];
+        match self.get_twin(node_index) {
+            Some(twin) if !self.is_feature_edge(node_index) => {
+                let pos_c = self.positions[self.nodes[node_b.relative as usize].position as usize];
+                let twin_c = self.nodes[self.nodes[twin as usize].relative as usize].relative;
+                let pos_d = self.positions[self.nodes[twin_c as usize].position as usize];
+                (pos_a + pos_b) * 0.375 + (pos_c + pos_d) * 0.125
+            },
+            _ => (pos_a + pos_b) * 0.5,
+        }
+    }
+
+    fn subdivide_sqrt3_once(&mut self, trisect_features: bool) {
+        let rings = self.get_vertex_rings();
+
+        let mut positions = self.positions.clone();
+        for (position, ring) in rings.iter().enumerate() {
+            let ring = match ring {
+                Some(ring) => ring,
+                None => continue,
+            };
+            let p = self.positions[position];
+            positions[position] = match ring.get_kind() {
+                VertexKind::Smooth => {
+                    let n = ring.neighbors.len() as f64;
+                    let alpha = (4.0 - 2.0 * (2.0 * std::f64::consts::PI / n).cos()) / 9.0;
+                    p * (1.0 - alpha) + self.sum_positions(&ring.neighbors) * (alpha / n)
+                },
+                VertexKind::Crease(a, b) if trisect_features => {
+                    (self.positions[a as usize] * 4.0 + p * 19.0 + self.positions[b as usize] * 4.0) / 27.0
+                },
+                _ => p,
+            };
+        }
+
+        let mut attributes = CornerAttributes::new(self);
+
+        // Feature edges of every face (one bit per edge), and center vertex of faces that get one, indexed by first node.
+        // When feature edges are trisected, faces along them are not split at their center (unless they have several)
+        // and their edges are not flipped.
+        let mut feature_masks = vec![0u8; self.nodes.len()];
+        let mut centers = vec![[u32::MAX; 4]; self.nodes.len()];
+        for i in 0..self.nodes.len() as u32 {
+            if !self.is_first_node_of_face(i) {
+                continue;
+            }
+            let face = self.get_face_nodes(i);
+            let mut mask = 0u8;
+            for (x, node) in face.iter().enumerate() {
+                if self.is_feature_edge(*node) {
+                    mask |= 1 << x;
+                }
+            }
+            feature_masks[i as usize] = mask;
+            if !trisect_features || mask.count_ones() != 1 {
+                positions.push(self.sum_positions(&face.map(|node| self.nodes[node as usize].position)) / 3.0);
+                let face_corners = face.map(|node| (self.get_corner(node), 2));
+                centers[i as usize] = attributes.blend((positions.len() - 1) as u32, &face_corners);
+            }
+        }
+
+        let mut trisection_points = HashMap::<u64, [u32; 2]>::new();
+        let mut corners = Vec::<[[u32; 4]; 3]>::with_capacity(3 * self.face_count as usize);
+
+        for i in 0..self.nodes.len() as u32 {
+            if !self.is_first_node_of_face(i) {
+                continue;
+            }
+            let face = self.get_face_nodes(i);
+            let face_corners = face.map(|node| self.get_corner(node));
+            let mask = feature_masks[i as usize];
+            let center = centers[i as usize];
+
+            if trisect_features && mask != 0 {
+                let mut polygon = Vec::with_capacity(9);
+                for x in 0..3 {
+                    polygon.push(face_corners[x]);
+                    if mask & (1 << x) != 0 {
+                        let (pos_a, pos_b) = (face_corners[x][0], face_corners[(x + 1) % 3][0]);
+                        let points = *trisection_points.entry(Edge::new(pos_a, pos_b).get_key()).or_insert_with(|| {
+                            let (pos_lo, pos_hi) = (pos_a.min(pos_b), pos_a.max(pos_b));
+                            for point in self.get_trisection_points(&rings, pos_lo, pos_hi).iter() {
+                                positions.push(*point);
+                            }
+                            [(positions.len() - 2) as u32, (positions.len() - 1) as u32]
+                        });
+                        let (near_a, near_b) = if pos_a < pos_b { (points[0], points[1]) } else { (points[1], points[0]) };
+                        polygon.push(attributes.blend(near_a, &[(face_corners[x], 4), (face_corners[(x + 1) % 3], 2)]));
+                        polygon.push(attributes.blend(near_b, &[(face_corners[x], 2), (face_corners[(x + 1) % 3], 4)]));
+                    }
+                }
+
+                if mask.count_ones() == 1 {
+                    // Fan from the corner opposite to the feature edge
+                    let opposite = face_corners[(mask.trailing_zeros() as usize + 2) % 3];
+                    let start = polygon.iter().position(|corner| *corner == opposite).unwrap();
+                    polygon.rotate_left(start);
+                    for j in 1..polygon.len() - 1 {
+                        corners.push([polygon[0], polygon[j], polygon[j + 1]]);
+                    }
+                } else {
+                    for j in 0..polygon.len() {
+                        corners.push([polygon[j], polygon[(j + 1) % polygon.len()], center]);
+                    }
+                }
+                continue;
+            }
+
+            for x in 0..3 {
+                let twin_face = match self.get_twin(face[x]) {
+                    Some(twin) if mask & (1 << x) == 0 => Some(self.get_face_corner(twin).0),
+                    _ => None,
+                };
+                match twin_face {
+                    // Flip the original edge, which now joins the centers of its two faces
+                    Some(twin_face) if !trisect_features || feature_masks[twin_face as usize] == 0 => {
+                        corners.push([face_corners[x], centers[twin_face as usize], center]);
+                    },
+                    _ => corners.push([face_corners[x], face_corners[(x + 1) % 3], center]),
+                }
+            }
+        }
+
+        *self = attributes.build(positions, &corners);
+    }
+
+    // Points at one and two thirds of a crease edge (from the smallest position to the largest), with Kobbelt's boundary rule.
+    // Crease ends that are not crease vertices are extrapolated linearly.
+    fn get_trisection_points(&self, rings: &[Option<VertexRing>], pos_a: u32, pos_b: u32) -> [DVec3; 2] {
+        let p_a = self.positions[pos_a as usize];
+        let p_b = self.positions[pos_b as usize];
+        let get_previous = |position: u32, other: u32| {
+            match rings[position as usize].as_ref().map(|ring| ring.get_kind()) {
+                Some(VertexKind::Crease(a, b)) => self.positions[if a == other { b } else { a } as usize],
+                _ => self.positions[position as usize] * 2.0 - self.positions[other as usize],
+            }
+        };
+        let p_previous = get_previous(pos_a, pos_b);
+        let p_next = get_previous(pos_b, pos_a);
+        [
+            (p_previous + p_a * 16.0 + p_b * 10.0) / 27.0,
+            (p_a * 10.0 + p_b * 16.0 + p_next) / 27.0,
+        ]
+    }
+
+    // Returns the ring of every position still referenced by a face
+    fn get_vertex_rings(&self) -> Vec<Option<VertexRing>> {
+        let mut rings: Vec<Option<VertexRing>> = (0..self.positions.len()).map(|_| None).collect();
+        for i in 0..self.nodes.len() as u32 {
+            let position = self.nodes[i as usize].position as usize;
+            if self.nodes[i as usize].is_removed || rings[position].is_some() {
+                continue;
+            }
+            let mut ring = VertexRing::default();
+            loop_siblings!(i, self.nodes, sibling, {
+                if !self.nodes[sibling as usize].is_removed {
+                    let next = self.nodes[sibling as usize].relative;
+                    let previous = self.nodes[next as usize].relative;
+                    ring.add(self.nodes[next as usize].position, self.is_feature_edge(sibling));
+                    ring.add(self.nodes[previous as usize].position, self.is_feature_edge(previous));
+                }
+            });
+            rings[position] = Some(ring);
+        }
+        rings
+    }
+
+    fn get_face_nodes(&self, node_index: u32) -> [u32; 3] {
+        let node_b = self.nodes[node_index as usize].relative;
+        [node_index, node_b, self.nodes[node_b as usize].relative]
+    }
+
+    fn sum_positions(&self, positions: &[u32]) -> DVec3 {
+        positions.iter().fold(DVec3::default(), |sum, position| sum + self.positions[*position as usize])
+    }
+}
+
+#[cfg(test)]
+mod subdivision_tests {
+    use super::*;
+    use hashbrown::HashSet;
+    use crate::mesh::test_utils::read_sphere;
+
+    // Cube from -1 to 1 with one normal per side, so that all its edges are creases
+    fn build_flat_cube() -> ConnectedMesh {
+        let mut positions = Vec::new();
+        for i in 0..8 {
+            positions.push(DVec3::new(
+                if i & 1 == 0 { -1. } else { 1. },
+                if i & 2 == 0 { -1. } else { 1. },
+                if i & 4 == 0 { -1. } else { 1. }));
+        }
+        let sides = [
+            ([0, 2, 3, 1], DVec3::new(0., 0., -1.)),
+            ([4, 5, 7, 6], DVec3::new(0., 0., 1.)),
+            ([0, 1, 5, 4], DVec3::new(0., -1., 0.)),
+            ([2, 6, 7, 3], DVec3::new(0., 1., 0.)),
+            ([0, 4, 6, 2], DVec3::new(-1., 0., 0.)),
+            ([1, 3, 7, 5], DVec3::new(1., 0., 0.)),
+        ];
+        let mut normals = Vec::new();
+        let mut corners = Vec::new();
+        for (quad, normal) in sides.iter() {
+            let n = normals.len() as u32;
+            normals.push(*normal);
+            corners.push([[quad[0], n, 0, 0], [quad[1], n, 0, 0], [quad[2], n, 0, 0]]);
+            corners.push([[quad[0], n, 0, 0], [quad[2], n, 0, 0], [quad[3], n, 0, 0]]);
+        }
+        ConnectedMesh::from_corners(positions, Some(normals), None, None, &corners)
+    }
+
+    // Unit square in the XY plane made of two triangles, with uvs matching positions
+    fn build_square() -> ConnectedMesh {
+        let positions = vec![DVec3::new(0., 0., 0.), DVec3::new(1., 0., 0.), DVec3::new(1., 1., 0.), DVec3::new(0., 1., 0.)];
+        let uvs = positions.iter().map(|p| DVec2::new(p.x, p.y)).collect();
+        let corners = [
+            [[0, 0, 0, 0], [1, 0, 0, 1], [2, 0, 0, 2]],
+            [[0, 0, 0, 0], [2, 0, 0, 2], [3, 0, 0, 3]]];
+        ConnectedMesh::from_corners(positions, None, None, Some(uvs), &corners)
+    }
+
+    fn assert_on_cube(shared_mesh: &SharedMesh) {
+        for position in shared_mesh.positions.iter() {
+            assert!((position.amax() - 1.0).abs() < 1e-9, "{} is not on the cube", position);
+        }
+        let normals = shared_mesh.normals.as_ref().unwrap();
+        assert_eq!(normals.len(), shared_mesh.positions.len());
+        for normal in normals.iter() {
+            assert!((normal.magnitude() - 1.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn loop_closed_mesh() {
+        let mut connected_mesh = ConnectedMesh::from(&read_sphere());
+        connected_mesh.subdivide_loop(2);
+        assert_eq!(connected_mesh.face_count(), 1280 * 16);
+
+        let shared_mesh = SharedMesh::from(&connected_mesh);
+        // Each edge of the original sphere got a vertex, and so on
+        assert_eq!(shared_mesh.positions.len(), 642 + 1920 + 7680);
+        for position in shared_mesh.positions.iter() {
+            assert!((position.magnitude() - 1.0).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn sqrt3_closed_mesh() {
+        let mut connected_mesh = ConnectedMesh::from(&read_sphere());
+        connected_mesh.subdivide_sqrt3(2);
+        assert_eq!(connected_mesh.face_count(), 1280 * 9);
+
+        let shared_mesh = SharedMesh::from(&connected_mesh);
+        assert_eq!(shared_mesh.positions.len(), 642 + 1280 + 3840);
+        for position in shared_mesh.positions.iter() {
+            assert!((position.magnitude() - 1.0).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn loop_keeps_creases() {
+        let mut connected_mesh = build_flat_cube();
+        connected_mesh.subdivide_loop(2);
+        assert_eq!(connected_mesh.face_count(), 12 * 16);
+        assert_on_cube(&SharedMesh::from(&connected_mesh));
+    }
+
+    #[test]
+    fn sqrt3_keeps_creases() {
+        let mut connected_mesh = build_flat_cube();
+        connected_mesh.subdivide_sqrt3(3);
+        assert_on_cube(&SharedMesh::from(&connected_mesh));
+
+        // Two iterations refine crease edges in three, like the interior
+        let mut connected_mesh = build_flat_cube();
+        connected_mesh.subdivide_sqrt3(2);
+        let shared_mesh = SharedMesh::from(&connected_mesh);
+        // Vertices are split by normals along cube edges, hence the deduplication
+        let on_cube_edges: HashSet<[u64; 3]> = shared_mesh.positions.iter()
+            .filter(|p| p.iter().filter(|x| (x.abs() - 1.0).abs() < 1e-9).count() >= 2)
+            .map(|p| [p.x.to_bits(), p.y.to_bits(), p.z.to_bits()])
+            .collect();
+        // 8 corners, plus 2 points on each of the 12 edges
+        assert_eq!(on_cube_edges.len(), 8 + 24);
+    }
+
+    #[test]
+    fn subdivide_with_borders() {
+        let mut loop_mesh = build_square();
+        loop_mesh.subdivide_loop(2);
+        let mut sqrt3_mesh = build_square();
+        sqrt3_mesh.subdivide_sqrt3(2);
+
+        for (connected_mesh, face_count) in [(loop_mesh, 2 * 16), (sqrt3_mesh, 2 * 9)] {
+            assert_eq!(connected_mesh.face_count(), face_count);
+            let shared_mesh = SharedMesh::from(&connected_mesh);
+            let uvs = shared_mesh.uvs.as_ref().unwrap();
+            assert_eq!(uvs.len(), shared_mesh.positions.len());
+            for (position, uv) in shared_mesh.positions.iter().zip(uvs.iter()) {
+                assert_eq!(position.z, 0.0);
+                assert!(position.x >= 0.0 && position.x <= 1.0 && position.y >= 0.0 && position.y <= 1.0);
+                assert!(uv.x >= 0.0 && uv.x <= 1.0 && uv.y >= 0.0 && uv.y <= 1.0);
+            }
+        }
+    }
+}
@@ -0,0 +1,22 @@
+use nalgebra_glm as glm;
+use glm::{DVec3, U32Vec3};
+use std::io::BufReader;
+use super::SharedMesh;
+
+// Fixtures shared by the tests of the crate
+
+// Icosphere of radius 1, with 642 vertices and 1280 triangles
+pub(crate) fn read_sphere() -> SharedMesh {
+    let bytes = include_bytes!("../../samples/sphere_flat_lp.obj");
+    crate::io::obj::read(&mut BufReader::new(&bytes[..]))
+}
+
+// Box with outward triangles, vertex x + 2y + 4z at (x, y, z) scaled by the size
+pub(crate) fn build_box(offset: DVec3, size: DVec3) -> SharedMesh {
+    let positions = (0..8).map(|i| offset + DVec3::new((i & 1) as f64 * size.x, ((i >> 1) & 1) as f64 * size.y, ((i >> 2) & 1) as f64 * size.z)).collect();
+    let triangles = [
+        [0, 2, 3], [0, 3, 1], [4, 5, 7], [4, 7, 6], [0, 1, 5], [0, 5, 4],
+        [2, 6, 7], [2, 7, 3], [0, 4, 6], [0, 6, 2], [1, 3, 7], [1, 7, 5],
+    ].iter().map(|t| U32Vec3::new(t[0], t[1], t[2])).collect();
+    SharedMesh { positions, triangles, groups: Vec::new(), normals: None, colors: None, uvs: None }
+}
@@ -0,0 +1,137 @@
+use nalgebra_glm as glm;
+use glm::U32Vec3;
+
+use hashbrown::HashMap;
+use std::collections::VecDeque;
+
+// Edges and vertex fans of an indexed triangle list, shared by the repair and analysis passes
+
+pub(crate) fn get_edge_key(a: u32, b: u32) -> u64 {
+    ((a.min(b) as u64) << 32) | a.max(b) as u64
+}
+
+/// Triangles around every undirected edge, as (triangle, corner where the edge starts)
+pub(crate) fn build_edge_map(triangles: &[U32Vec3]) -> HashMap<u64, Vec<(u32, u8)>> {
+    let mut edges: HashMap<u64, Vec<(u32, u8)>> = HashMap::with_capacity(triangles.len() * 3 / 2);
+    for (t, triangle) in triangles.iter().enumerate() {
+        for x in 0..3 {
+            let key = get_edge_key(triangle[x], triangle[(x + 1) % 3]);
+            edges.entry(key).or_default().push((t as u32, x as u8));
+        }
+    }
+    edges
+}
+
+pub(crate) struct UnionFind {
+    parents: Vec<u32>,
+}
+
+impl UnionFind {
+    pub fn new(size: usize) -> Self {
+        UnionFind { parents: (0..size as u32).collect() }
+    }
+
+    pub fn find(&mut self, mut x: u32) -> u32 {
+        while self.parents[x as usize] != x {
+            // Path halving
+            let grand_parent = self.parents[self.parents[x as usize] as usize];
+            self.parents[x as usize] = grand_parent;
+            x = grand_parent;
+        }
+        x
+    }
+
+    pub fn union(&mut self, a: u32, b: u32) {
+        let (a, b) = (self.find(a), self.find(b));
+        // The smallest root wins, so that results don't depend on the union order
+        if a < b {
+            self.parents[b as usize] = a;
+        } else {
+            self.parents[a as usize] = b;
+        }
+    }
+}
+
+/// Groups the corners (3 * triangle + x) around every vertex into fans, that is the triangles around the vertex
+/// that are connected through manifold edges (edges with exactly two triangles).
+/// A manifold vertex has a single fan, a vertex with several fans is a non-manifold vertex.
+pub(crate) fn get_corner_fans(triangles: &[U32Vec3], edges: &HashMap<u64, Vec<(u32, u8)>>) -> UnionFind {
+    let mut fans = UnionFind::new(triangles.len() * 3);
+    for faces in edges.values() {
+        if let [(t1, x1), (t2, _)] = faces[..] {
+            // Connects the corners of both triangles on each end of the edge
+            let (a, b) = (triangles[t1 as usize][x1 as usize], triangles[t1 as usize][(x1 as usize + 1) % 3]);
+            for vertex in [a, b] {
+                let c1 = (0..3).find(|&x| triangles[t1 as usize][x] == vertex).unwrap();
+                let c2 = (0..3).find(|&x| triangles[t2 as usize][x] == vertex).unwrap();
+                fans.union(3 * t1 + c1 as u32, 3 * t2 + c2 as u32);
+            }
+        }
+    }
+    fans
+}
+
+/// Whether every edge has exactly two triangles walking it in opposite directions
+pub(crate) fn is_closed(triangles: &[U32Vec3], edges: &HashMap<u64, Vec<(u32, u8)>>) -> bool {
+    edges.values().all(|faces| match faces[..] {
+        [(t1, x1), (t2, x2)] => triangles[t1 as usize][x1 as usize] != triangles[t2 as usize][x2 as usize],
+        _ => false,
+    })
+}
+
+/// Connected components of triangles through manifold edges, as one component index per triangle (in order of first triangle)
+pub(crate) fn get_components(triangles: &[U32Vec3], edges: &HashMap<u64, Vec<(u32, u8)>>) -> (Vec<u32>, usize) {
+    let mut union_find = UnionFind::new(triangles.len());
+    for faces in edges.values() {
+        if let [(t1, _), (t2, _)] = faces[..] {
+            union_find.union(t1, t2);
+        }
+    }
+    let mut component_of_root = HashMap::new();
+    let components = (0..triangles.len() as u32).map(|t| {
+        let root = union_find.find(t);
+        let count = component_of_root.len() as u32;
+        *component_of_root.entry(root).or_insert(count)
+    }).collect();
+    (components, component_of_root.len())
+}
+
+/// Orients the triangles of every component relative to its first triangle, walking through manifold edges.
+/// Returns whether every triangle must be flipped, and whether every component could be oriented consistently.
+pub(crate) fn get_relative_flips(triangles: &[U32Vec3], edges: &HashMap<u64, Vec<(u32, u8)>>, components: &[u32], component_count: usize) -> (Vec<bool>, Vec<bool>) {
+    // Neighbors through manifold edges, and whether both triangles walk the edge in opposite directions
+    let mut neighbors: Vec<Vec<(u32, bool)>> = vec![Vec::new(); triangles.len()];
+    for faces in edges.values() {
+        if let [(t1, x1), (t2, x2)] = faces[..] {
+            let agree = triangles[t1 as usize][x1 as usize] != triangles[t2 as usize][x2 as usize];
+            neighbors[t1 as usize].push((t2, agree));
+            neighbors[t2 as usize].push((t1, agree));
+        }
+    }
+
+    let mut flips: Vec<Option<bool>> = vec![None; triangles.len()];
+    let mut is_orientable = vec![true; component_count];
+    let mut queue = VecDeque::new();
+    for start in 0..triangles.len() {
+        if flips[start].is_some() {
+            continue;
+        }
+        flips[start] = Some(false);
+        queue.push_back(start);
+        while let Some(t) = queue.pop_front() {
+            let flip = flips[t].unwrap();
+            for &(neighbor, agree) in &neighbors[t] {
+                let expected = flip ^ !agree;
+                match flips[neighbor as usize] {
+                    None => {
+                        flips[neighbor as usize] = Some(expected);
+                        queue.push_back(neighbor as usize);
+                    },
+                    Some(neighbor_flip) if neighbor_flip != expected => is_orientable[components[t] as usize] = false,
+                    _ => (),
+                }
+            }
+        }
+    }
+    (flips.into_iter().map(Option::unwrap).collect(), is_orientable)
+}
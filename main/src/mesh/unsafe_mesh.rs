@@ -50,6 +50,8 @@ impl From<&UnsafeMesh> for SharedMesh {
                 triangles: ptr_to_vec(unsafe_mesh.triangles_ptr, unsafe_mesh.triangles_len as usize),
                 positions: ptr_to_vec(unsafe_mesh.positions_ptr, unsafe_mesh.positions_len as usize),
                 normals: match unsafe_mesh.normals_ptr.is_null() { false => Some(ptr_to_vec(unsafe_mesh.normals_ptr, unsafe_mesh.normals_len as usize)), true => None },
+                colors: None,
+                uvs: None,
             };
         }
     }
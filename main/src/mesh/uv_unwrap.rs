@@ -0,0 +1,379 @@
+use nalgebra_glm as glm;
+use glm::{DVec2, DVec3};
+use hashbrown::HashMap;
+use std::collections::VecDeque;
+use super::SharedMesh;
+use super::topology::*;
+
+// Charts are grown from seed triangles over manifold edges while normals stay close to the seed's, then flattened with
+// least squares conformal maps (Lévy et al.), and packed on shelves into the unit square.
+
+/// Conjugate gradient iterations per unknown of a chart, at most
+const SOLVER_ITERATIONS_PER_UNKNOWN: usize = 4;
+
+impl SharedMesh {
+    /// Copy of the mesh with generated texture coordinates, replacing existing ones. Vertices are split along chart seams.
+    /// Charts gather triangles connected through shared edges whose normals are within `max_chart_angle` (in radians,
+    /// below π / 2) of the normal of the triangle that starts them, and never cross group boundaries. Meshes from
+    /// `triangulate` give every CAD face its own vertices, so every face gets its own charts.
+    /// Charts keep their relative sizes and are packed in [0, 1]², with at least `padding` between them and half of it
+    /// to the borders.
+    pub fn unwrap_uvs(&self, max_chart_angle: f64, padding: f64) -> SharedMesh {
+        let (charts, chart_count) = self.get_charts(max_chart_angle.cos());
+
+        let mut unwrapped = self.clone();
+        unwrapped.uvs = None;
+        let mut chart_triangles = vec![Vec::new(); chart_count];
+        for (t, chart) in charts.iter().enumerate() {
+            chart_triangles[*chart as usize].push(t);
+        }
+
+        // Vertices stay in the first chart using them, and are copied for the other ones
+        let mut chart_vertices: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut vertex_charts: HashMap<u32, u32> = HashMap::new();
+        for (t, &chart) in charts.iter().enumerate() {
+            for x in 0..3 {
+                let vertex = self.triangles[t][x];
+                let new_vertex = match chart_vertices.get(&(chart, vertex)) {
+                    Some(new_vertex) => *new_vertex,
+                    None => {
+                        let new_vertex = if *vertex_charts.entry(vertex).or_insert(chart) == chart { vertex } else { unwrapped.copy_vertex(vertex) };
+                        chart_vertices.insert((chart, vertex), new_vertex);
+                        new_vertex
+                    },
+                };
+                unwrapped.triangles[t][x] = new_vertex;
+            }
+        }
+
+        let mut charts: Vec<Chart> = chart_triangles.iter().map(|triangles| Chart::new(&unwrapped, triangles)).collect();
+        for chart in charts.iter_mut() {
+            chart.flatten();
+        }
+        let mut uvs = vec![DVec2::zeros(); unwrapped.positions.len()];
+        pack_charts(&mut charts, padding);
+        for chart in charts.iter() {
+            for (vertex, uv) in chart.vertices.iter().zip(chart.uvs.iter()) {
+                uvs[*vertex as usize] = *uv;
+            }
+        }
+        unwrapped.uvs = Some(uvs);
+        unwrapped
+    }
+
+    // Chart of every triangle, grown breadth first from the first triangle that isn't in a chart yet
+    fn get_charts(&self, min_cosine: f64) -> (Vec<u32>, usize) {
+        let edges = build_edge_map(&self.triangles);
+        // Triangles outside of every group are kept apart from those of the first group
+        let mut groups = vec![usize::MAX; self.triangles.len()];
+        for (g, group) in self.groups.iter().enumerate() {
+            for t in group.triangle_range() {
+                groups[t] = g;
+            }
+        }
+        let normals: Vec<DVec3> = self.triangles.iter().map(|triangle| {
+            let [a, b, c] = [0, 1, 2].map(|x| self.positions[triangle[x] as usize]);
+            (b - a).cross(&(c - a)).try_normalize(0.0).unwrap_or_else(DVec3::zeros)
+        }).collect();
+
+        let mut charts = vec![u32::MAX; self.triangles.len()];
+        let mut chart_count = 0;
+        let mut queue = VecDeque::new();
+        for seed in 0..self.triangles.len() {
+            if charts[seed] != u32::MAX {
+                continue;
+            }
+            charts[seed] = chart_count;
+            queue.push_back(seed);
+            while let Some(t) = queue.pop_front() {
+                for x in 0..3 {
+                    let triangle = &self.triangles[t];
+                    let faces = &edges[&get_edge_key(triangle[x], triangle[(x + 1) % 3])];
+                    let [(t1, _), (t2, _)] = faces[..] else { continue };
+                    let neighbor = if t1 as usize == t { t2 as usize } else { t1 as usize };
+                    if charts[neighbor] == u32::MAX && groups[neighbor] == groups[seed] && normals[neighbor].dot(&normals[seed]) >= min_cosine {
+                        charts[neighbor] = chart_count;
+                        queue.push_back(neighbor);
+                    }
+                }
+            }
+            chart_count += 1;
+        }
+        (charts, chart_count as usize)
+    }
+}
+
+struct Chart {
+    /// Vertices of the mesh in the chart
+    vertices: Vec<u32>,
+    positions: Vec<DVec3>,
+    triangles: Vec<[usize; 3]>,
+    uvs: Vec<DVec2>,
+    area: f64,
+}
+
+impl Chart {
+    fn new(shared_mesh: &SharedMesh, triangles: &[usize]) -> Self {
+        let mut local_indices: HashMap<u32, usize> = HashMap::new();
+        let mut vertices = Vec::new();
+        let triangles: Vec<[usize; 3]> = triangles.iter().map(|&t| [0, 1, 2].map(|x| {
+            let vertex = shared_mesh.triangles[t][x];
+            *local_indices.entry(vertex).or_insert_with(|| {
+                vertices.push(vertex);
+                vertices.len() - 1
+            })
+        })).collect();
+        let positions: Vec<DVec3> = vertices.iter().map(|&v| shared_mesh.positions[v as usize]).collect();
+        let area = triangles.iter().map(|[a, b, c]| 0.5 * (positions[*b] - positions[*a]).cross(&(positions[*c] - positions[*a])).magnitude()).sum();
+        Chart { uvs: vec![DVec2::zeros(); vertices.len()], vertices, positions, triangles, area }
+    }
+
+    // Least squares conformal map, with two far apart vertices pinned at their distance in 3D. The conformal energy of a
+    // triangle is |Σ e_j U_j|² / A in complex numbers, with U_j the uv of a corner and e_j the opposite edge in the plane
+    // of the triangle. It is minimized with conjugate gradients on the least squares problem (CGLS).
+    fn flatten(&mut self) {
+        let farthest = |from: usize| (0..self.positions.len())
+            .max_by(|&a, &b| (self.positions[a] - self.positions[from]).magnitude_squared().total_cmp(&(self.positions[b] - self.positions[from]).magnitude_squared()))
+            .unwrap_or(0);
+        let first_pin = farthest(0);
+        let second_pin = farthest(first_pin);
+        let distance = (self.positions[second_pin] - self.positions[first_pin]).magnitude();
+
+        // Rows of the system, with unknowns interleaved as u0, v0, u1, v1...
+        let mut rows: Vec<[(usize, f64); 6]> = Vec::with_capacity(self.triangles.len() * 2);
+        for [a, b, c] in self.triangles.iter() {
+            let (p0, p1, p2) = (self.positions[*a], self.positions[*b], self.positions[*c]);
+            let normal = (p1 - p0).cross(&(p2 - p0));
+            let double_area = normal.magnitude();
+            if double_area == 0.0 {
+                continue;
+            }
+            // Corners in an orthonormal frame of the triangle plane
+            let x_axis = (p1 - p0).normalize();
+            let y_axis = normal.cross(&x_axis).normalize();
+            let local = [DVec2::zeros(), DVec2::new((p1 - p0).magnitude(), 0.0), DVec2::new((p2 - p0).dot(&x_axis), (p2 - p0).dot(&y_axis))];
+            let corners = [*a, *b, *c];
+            let weight = 1.0 / (0.5 * double_area).sqrt();
+            let mut real = [(0, 0.0); 6];
+            let mut imaginary = [(0, 0.0); 6];
+            for j in 0..3 {
+                let edge = (local[(j + 2) % 3] - local[(j + 1) % 3]) * weight;
+                let (u, v) = (2 * corners[j], 2 * corners[j] + 1);
+                real[2 * j] = (u, edge.x);
+                real[2 * j + 1] = (v, -edge.y);
+                imaginary[2 * j] = (u, edge.y);
+                imaginary[2 * j + 1] = (v, edge.x);
+            }
+            rows.push(real);
+            rows.push(imaginary);
+        }
+
+        let unknown_count = 2 * self.positions.len();
+        let mut is_free = vec![true; unknown_count];
+        let mut x = vec![0.0; unknown_count];
+        for (pin, u) in [(first_pin, 0.0), (second_pin, distance)] {
+            x[2 * pin] = u;
+            is_free[2 * pin] = false;
+            is_free[2 * pin + 1] = false;
+        }
+
+        let multiply = |x: &[f64]| -> Vec<f64> { rows.iter().map(|row| row.iter().map(|(i, w)| w * x[*i]).sum()).collect() };
+        let multiply_transposed = |r: &[f64]| -> Vec<f64> {
+            let mut s = vec![0.0; unknown_count];
+            for (row, r) in rows.iter().zip(r.iter()) {
+                for (i, w) in row.iter() {
+                    if is_free[*i] {
+                        s[*i] += w * r;
+                    }
+                }
+            }
+            s
+        };
+        let dot = |a: &[f64], b: &[f64]| a.iter().zip(b.iter()).map(|(a, b)| a * b).sum::<f64>();
+
+        let mut r: Vec<f64> = multiply(&x).iter().map(|ax| -ax).collect();
+        let mut s = multiply_transposed(&r);
+        let mut p = s.clone();
+        let mut gamma = dot(&s, &s);
+        let tolerance = gamma * 1e-24;
+        for _ in 0..SOLVER_ITERATIONS_PER_UNKNOWN * unknown_count {
+            if gamma <= tolerance || gamma == 0.0 {
+                break;
+            }
+            let q = multiply(&p);
+            let alpha = gamma / dot(&q, &q);
+            for i in 0..unknown_count {
+                x[i] += alpha * p[i];
+            }
+            for i in 0..r.len() {
+                r[i] -= alpha * q[i];
+            }
+            s = multiply_transposed(&r);
+            let next_gamma = dot(&s, &s);
+            let beta = next_gamma / gamma;
+            gamma = next_gamma;
+            for i in 0..unknown_count {
+                p[i] = s[i] + beta * p[i];
+            }
+        }
+        self.uvs = (0..self.positions.len()).map(|v| DVec2::new(x[2 * v], x[2 * v + 1])).collect();
+
+        // Same area as in 3D, landscape and starting at the origin
+        let uv_area: f64 = self.triangles.iter().map(|[a, b, c]| 0.5 * (self.uvs[*b] - self.uvs[*a]).perp(&(self.uvs[*c] - self.uvs[*a]))).sum();
+        let scale = if uv_area > 0.0 { (self.area / uv_area).sqrt() } else { 1.0 };
+        let (min, max) = self.get_bounds();
+        let is_portrait = max.y - min.y > max.x - min.x;
+        for uv in self.uvs.iter_mut() {
+            let uv_scaled = (*uv - min) * scale;
+            *uv = if is_portrait { DVec2::new((max.y - min.y) * scale - uv_scaled.y, uv_scaled.x) } else { uv_scaled };
+        }
+    }
+
+    fn get_bounds(&self) -> (DVec2, DVec2) {
+        self.uvs.iter().fold((DVec2::repeat(f64::MAX), DVec2::repeat(f64::MIN)), |(min, max), uv| (min.inf(uv), max.sup(uv)))
+    }
+}
+
+// Shelf packing of the charts by decreasing height. Padding is relative to the size of the atlas, which depends on
+// the padding, so charts are packed again with the size they lead to until it is large enough.
+fn pack_charts(charts: &mut [Chart], padding: f64) {
+    let sizes: Vec<DVec2> = charts.iter().map(|chart| chart.get_bounds().1.sup(&DVec2::zeros())).collect();
+    let mut order: Vec<usize> = (0..charts.len()).collect();
+    order.sort_by(|&a, &b| sizes[b].y.total_cmp(&sizes[a].y));
+
+    let pack = |margin: f64| -> (Vec<DVec2>, f64) {
+        let area: f64 = sizes.iter().map(|size| (size.x + margin) * (size.y + margin)).sum();
+        let width = area.sqrt().max(sizes.iter().map(|size| size.x + margin).fold(0.0, f64::max));
+        let mut offsets = vec![DVec2::zeros(); charts.len()];
+        let (mut x, mut y, mut shelf_height, mut atlas_width) = (0.0, 0.0, 0.0, 0.0f64);
+        for &i in order.iter() {
+            if x > 0.0 && x + sizes[i].x + margin > width {
+                x = 0.0;
+                y += shelf_height;
+                shelf_height = 0.0;
+            }
+            offsets[i] = DVec2::new(x, y).add_scalar(margin * 0.5);
+            x += sizes[i].x + margin;
+            shelf_height = f64::max(shelf_height, sizes[i].y + margin);
+            atlas_width = atlas_width.max(x);
+        }
+        (offsets, atlas_width.max(y + shelf_height))
+    };
+
+    let mut size = sizes.iter().map(|size| size.x * size.y).sum::<f64>().sqrt();
+    let (mut offsets, mut atlas_size) = pack(padding * size);
+    for _ in 0..32 {
+        if atlas_size <= size {
+            break;
+        }
+        size = atlas_size;
+        (offsets, atlas_size) = pack(padding * size);
+    }
+    let scale = 1.0 / atlas_size.max(f64::MIN_POSITIVE);
+    for (chart, offset) in charts.iter_mut().zip(offsets.iter()) {
+        for uv in chart.uvs.iter_mut() {
+            *uv = (*uv + offset) * scale;
+        }
+    }
+}
+
+#[cfg(test)]
+mod uv_unwrap_tests {
+    use crate::mesh::*;
+    use crate::mesh::test_utils::{build_box, read_sphere};
+
+    // Box with a group per face
+    fn build_grouped_box(size: DVec3) -> SharedMesh {
+        SharedMesh { groups: (0..6).map(|face| Group::new(face * 6, 6)).collect(), ..build_box(DVec3::zeros(), size) }
+    }
+
+    fn get_uv_area(shared_mesh: &SharedMesh, triangle: &U32Vec3) -> f64 {
+        let uvs = shared_mesh.uvs.as_ref().unwrap();
+        let [a, b, c] = [0, 1, 2].map(|x| uvs[triangle[x] as usize]);
+        0.5 * (b - a).perp(&(c - a))
+    }
+
+    fn get_area(shared_mesh: &SharedMesh, triangle: &U32Vec3) -> f64 {
+        let [a, b, c] = [0, 1, 2].map(|x| shared_mesh.positions[triangle[x] as usize]);
+        0.5 * (b - a).cross(&(c - a)).magnitude()
+    }
+
+    // Bounds of the uvs of every chart, which are separate components once vertices are split along seams
+    fn get_chart_bounds(shared_mesh: &SharedMesh) -> Vec<(DVec2, DVec2)> {
+        let uvs = shared_mesh.uvs.as_ref().unwrap();
+        let edges = crate::mesh::topology::build_edge_map(&shared_mesh.triangles);
+        let (components, count) = crate::mesh::topology::get_components(&shared_mesh.triangles, &edges);
+        let mut bounds = vec![(DVec2::repeat(f64::MAX), DVec2::repeat(f64::MIN)); count];
+        for (triangle, component) in shared_mesh.triangles.iter().zip(components.iter()) {
+            for x in 0..3 {
+                let (min, max) = &mut bounds[*component as usize];
+                *min = min.inf(&uvs[triangle[x] as usize]);
+                *max = max.sup(&uvs[triangle[x] as usize]);
+            }
+        }
+        bounds
+    }
+
+    #[test]
+    fn unwrap_sphere() {
+        let sphere = read_sphere();
+        let padding = 0.01;
+        let unwrapped = sphere.unwrap_uvs(1.0, padding);
+        let uvs = unwrapped.uvs.as_ref().unwrap();
+        assert_eq!(uvs.len(), unwrapped.positions.len());
+        assert!(unwrapped.positions.len() > sphere.positions.len());
+
+        // Same surface, and no flipped triangles
+        for (triangle, original) in unwrapped.triangles.iter().zip(sphere.triangles.iter()) {
+            for x in 0..3 {
+                assert_eq!(unwrapped.positions[triangle[x] as usize], sphere.positions[original[x] as usize]);
+            }
+            assert!(get_uv_area(&unwrapped, triangle) > 0.0);
+        }
+
+        // Charts are apart and in the unit square
+        let bounds = get_chart_bounds(&unwrapped);
+        assert!(bounds.len() > 1);
+        for (i, (min, max)) in bounds.iter().enumerate() {
+            assert!(min.x >= padding * 0.5 - 1e-12 && min.y >= padding * 0.5 - 1e-12 && max.x <= 1.0 && max.y <= 1.0);
+            for (other_min, other_max) in bounds.iter().skip(i + 1) {
+                let gap = f64::max(f64::max(other_min.x - max.x, min.x - other_max.x), f64::max(other_min.y - max.y, min.y - other_max.y));
+                assert!(gap >= padding - 1e-12);
+            }
+        }
+
+        // Nearly uniform texel density
+        let ratios: Vec<f64> = unwrapped.triangles.iter().map(|t| get_uv_area(&unwrapped, t) / get_area(&unwrapped, t)).collect();
+        let mean = ratios.iter().sum::<f64>() / ratios.len() as f64;
+        assert!(ratios.iter().all(|ratio| (ratio / mean - 1.0).abs() < 0.5));
+    }
+
+    #[test]
+    fn unwrap_box_faces() {
+        // Faces are charts because of groups, even with a large angle
+        let unwrapped = build_grouped_box(DVec3::new(1., 2., 3.)).unwrap_uvs(1.5, 0.0);
+        assert_eq!(get_chart_bounds(&unwrapped).len(), 6);
+        assert_eq!(unwrapped.positions.len(), 24);
+
+        // Flat charts are unwrapped without distortion
+        let ratios: Vec<f64> = unwrapped.triangles.iter().map(|t| get_uv_area(&unwrapped, t) / get_area(&unwrapped, t)).collect();
+        assert!(ratios.iter().all(|ratio| (ratio - ratios[0]).abs() < 1e-9 * ratios[0]));
+
+        // Same charts from normals alone
+        let mut shared_mesh = build_grouped_box(DVec3::new(1., 2., 3.));
+        shared_mesh.groups.clear();
+        assert_eq!(get_chart_bounds(&shared_mesh.unwrap_uvs(0.5, 0.0)).len(), 6);
+    }
+
+    #[test]
+    fn ungrouped_triangles() {
+        // Flat square whose first triangle is the only one in a group
+        let shared_mesh = SharedMesh {
+            positions: vec![DVec3::new(0., 0., 0.), DVec3::new(1., 0., 0.), DVec3::new(1., 1., 0.), DVec3::new(0., 1., 0.)],
+            triangles: vec![U32Vec3::new(0, 1, 2), U32Vec3::new(0, 2, 3)],
+            groups: vec![Group::new(0, 3)],
+            normals: None, colors: None, uvs: None,
+        };
+        assert_eq!(get_chart_bounds(&shared_mesh.unwrap_uvs(0.5, 0.0)).len(), 2);
+    }
+}
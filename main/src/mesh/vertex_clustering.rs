@@ -0,0 +1,197 @@
+use nalgebra_glm as glm;
+use glm::{DVec3, U32Vec3};
+use super::SharedMesh;
+use crate::base::{Box3, SymmetricMatrix};
+use crate::io::stl;
+
+use hashbrown::{HashMap, HashSet};
+use std::io::{self, BufReader, SeekFrom};
+use std::io::prelude::*;
+
+const MAX_RESOLUTION: u32 = (1 << 21) - 1;
+
+/// Grid based vertex clustering (Rossignac–Borrel), with one quadric per cell to place its representative vertex,
+/// as in Lindstrom's out-of-core simplification. Triangles are fed one by one, so the input mesh never has to be in memory.
+pub struct VertexClustering {
+    bounds: Box3,
+    cell_size: f64,
+    cells: HashMap<u64, u32>,
+    quadrics: Vec<SymmetricMatrix>,
+    // Sum of positions and number of vertices per cell, as a fallback when the quadric can't be solved
+    sums: Vec<(DVec3, u32)>,
+    triangles: HashSet<[u32; 3]>,
+}
+
+impl VertexClustering {
+    /// Creates an empty grid over the given bounds, with `resolution` cells along its largest axis
+    pub fn new(bounds: Box3, resolution: u32) -> Self {
+        let size = bounds.size();
+        let largest = size.x.max(size.y).max(size.z);
+        let resolution = resolution.clamp(1, MAX_RESOLUTION);
+        VertexClustering {
+            bounds,
+            cell_size: if largest > 0.0 { largest / resolution as f64 } else { 1.0 },
+            cells: HashMap::new(),
+            quadrics: Vec::new(),
+            sums: Vec::new(),
+            triangles: HashSet::new(),
+        }
+    }
+
+    pub fn add_triangle(&mut self, triangle: &[DVec3; 3]) {
+        let normal = (triangle[1] - triangle[0]).cross(&(triangle[2] - triangle[0]));
+        let double_area = normal.magnitude();
+
+        // Quadric of the triangle plane, weighted by its area (quadric scales with the square of the normal)
+        let quadric = if double_area > 0.0 {
+            let weighted_normal = normal / double_area * (0.5 * double_area).sqrt();
+            SymmetricMatrix::from_normal(&weighted_normal, &-weighted_normal.dot(&triangle[0]))
+        } else {
+            SymmetricMatrix::default_zeroes()
+        };
+
+        let mut cells = [0u32; 3];
+        for (cell, position) in cells.iter_mut().zip(triangle.iter()) {
+            *cell = self.get_cell(position);
+            self.quadrics[*cell as usize] += quadric;
+            let sum = &mut self.sums[*cell as usize];
+            sum.0 += position;
+            sum.1 += 1;
+        }
+
+        // Triangles collapsing within a cell or on an edge are dropped
+        if cells[0] == cells[1] || cells[1] == cells[2] || cells[2] == cells[0] {
+            return;
+        }
+
+        // Rotate so that the smallest cell comes first, keeping winding, so that duplicates are detected
+        let first = (0..3).min_by_key(|i| cells[*i]).unwrap();
+        self.triangles.insert([cells[first], cells[(first + 1) % 3], cells[(first + 2) % 3]]);
+    }
+
+    pub fn build(self) -> SharedMesh {
+        let mut cell_triangles: Vec<[u32; 3]> = self.triangles.into_iter().collect();
+        cell_triangles.sort();
+
+        // Only cells referenced by a remaining triangle become vertices
+        let mut vertices = vec![u32::MAX; self.quadrics.len()];
+        let mut positions = Vec::new();
+        let mut triangles = Vec::with_capacity(cell_triangles.len());
+        for cell_triangle in cell_triangles.iter() {
+            let mut triangle = U32Vec3::default();
+            for (i, cell) in cell_triangle.iter().enumerate() {
+                let cell = *cell as usize;
+                if vertices[cell] == u32::MAX {
+                    vertices[cell] = positions.len() as u32;
+                    positions.push(get_representative(&self.quadrics[cell], &self.sums[cell], self.cell_size));
+                }
+                triangle[i] = vertices[cell];
+            }
+            triangles.push(triangle);
+        }
+
+        return SharedMesh {
+            groups: Vec::new(),
+            triangles,
+            positions,
+            normals: None,
+            colors: None,
+            uvs: None,
+        };
+
+        fn get_representative(quadric: &SymmetricMatrix, sum: &(DVec3, u32), cell_size: f64) -> DVec3 {
+            let mean = sum.0 / sum.1 as f64;
+            // Determinant scales with the cube of the quadric, hence a threshold relative to its trace
+            let trace = quadric.m[0] + quadric.m[4] + quadric.m[7];
+            match quadric.optimal_position(1e-9 * trace * trace * trace) {
+                // Representatives far from their cell are unreliable
+                Some(position) if (position - mean).amax() <= cell_size => position,
+                _ => mean,
+            }
+        }
+    }
+
+    fn get_cell(&mut self, position: &DVec3) -> u32 {
+        let relative = (position - self.bounds.min) / self.cell_size;
+        let coordinate = |x: f64| (x.max(0.0) as u64).min(MAX_RESOLUTION as u64);
+        let key = coordinate(relative.x) << 42 | coordinate(relative.y) << 21 | coordinate(relative.z);
+        let next_cell = self.quadrics.len() as u32;
+        let cell = *self.cells.entry(key).or_insert(next_cell);
+        if cell == next_cell {
+            self.quadrics.push(SymmetricMatrix::default_zeroes());
+            self.sums.push((DVec3::default(), 0));
+        }
+        cell
+    }
+}
+
+impl SharedMesh {
+    /// Fast simplification by vertex clustering, with `resolution` cells along the largest axis of the mesh.
+    /// Way faster than edge collapse decimation, but topology is not preserved.
+    pub fn simplify_by_clustering(&self, resolution: u32) -> SharedMesh {
+        let mut bounds = Box3::unfitted();
+        for position in self.positions.iter() {
+            bounds.expand(position);
+        }
+
+        let mut clustering = VertexClustering::new(bounds, resolution);
+        for triangle in self.triangles.iter() {
+            clustering.add_triangle(&[
+                self.positions[triangle[0] as usize],
+                self.positions[triangle[1] as usize],
+                self.positions[triangle[2] as usize]]);
+        }
+        clustering.build()
+    }
+}
+
+/// Out-of-core vertex clustering of a binary STL, streamed twice: once for the bounds, once for the triangles.
+pub fn simplify_stl<T: Read + Seek>(reader: &mut BufReader<T>, resolution: u32) -> io::Result<SharedMesh> {
+    let mut bounds = Box3::unfitted();
+    stl::read_triangles(reader, |triangle| {
+        for position in triangle.iter() {
+            bounds.expand(position);
+        }
+    })?;
+
+    reader.seek(SeekFrom::Start(0))?;
+
+    let mut clustering = VertexClustering::new(bounds, resolution);
+    stl::read_triangles(reader, |triangle| clustering.add_triangle(triangle))?;
+    Ok(clustering.build())
+}
+
+#[cfg(test)]
+mod vertex_clustering_tests {
+    use crate::mesh::*;
+    use super::simplify_stl;
+    use crate::io::stl;
+    use crate::mesh::test_utils::read_sphere;
+    use std::io::{BufReader, BufWriter, Cursor};
+
+    #[test]
+    fn simplify_by_clustering() {
+        let shared_mesh = read_sphere();
+        let simplified = shared_mesh.simplify_by_clustering(6);
+
+        assert!(simplified.triangles.len() > 20);
+        assert!(simplified.triangles.len() < shared_mesh.triangles.len() / 4);
+        for position in simplified.positions.iter() {
+            assert!((position.magnitude() - 1.0).abs() < 0.2);
+        }
+    }
+
+    #[test]
+    fn simplify_streamed_stl() {
+        let shared_mesh = read_sphere();
+        let mut bytes = Vec::new();
+        stl::write(&shared_mesh, &mut BufWriter::new(&mut bytes));
+
+        let read = stl::read(&mut BufReader::new(&bytes[..]));
+        assert_eq!(read.triangles.len(), shared_mesh.triangles.len());
+        assert_eq!(read.positions.len(), shared_mesh.positions.len());
+
+        let simplified = simplify_stl(&mut BufReader::new(Cursor::new(&bytes)), 6).unwrap();
+        assert_eq!(simplified.triangles.len(), read.simplify_by_clustering(6).triangles.len());
+    }
+}
@@ -0,0 +1,651 @@
+use nalgebra_glm as glm;
+use glm::{DVec3, U32Vec3};
+use crate::base::{Box3, Ray};
+use crate::mesh::SharedMesh;
+use super::queries::*;
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+use rayon::prelude::*;
+
+const BIN_COUNT: usize = 16;
+// Nodes with that many triangles or less may become leaves
+const MAX_LEAF_SIZE: u32 = 8;
+// Cost of visiting a node, relative to the cost of intersecting a triangle
+const TRAVERSAL_COST: f64 = 1.0;
+// Nodes with that many triangles or less are built as separate tasks when multithreaded
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+const PARALLEL_SUBTREE_SIZE: u32 = 1 << 15;
+
+#[derive(Debug, Copy, Clone)]
+struct Node {
+    bounds: Box3,
+    // Leaves reference `count` triangle indices starting at `first`, inner nodes (count 0) have their children at `first` and `first + 1`
+    first: u32,
+    count: u32,
+}
+
+/// Triangle hit by a ray, at `distance` along the ray
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RayHit {
+    pub triangle: u32,
+    pub distance: f64,
+    /// Weights of the three vertices of the triangle at the hit point
+    pub barycentric: DVec3,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ClosestPoint {
+    pub triangle: u32,
+    pub point: DVec3,
+    pub distance: f64,
+}
+
+/// Bounding volume hierarchy over the triangles of a mesh, built with a binned surface area heuristic (Wald, 2007).
+/// The BVH owns a copy of the positions and triangles, see `refit` to follow moving vertices.
+pub struct Bvh {
+    nodes: Vec<Node>,
+    // Triangle indices, ordered so that every leaf references a contiguous range
+    indices: Vec<u32>,
+    triangles: Vec<U32Vec3>,
+    positions: Vec<DVec3>,
+}
+
+impl From<&SharedMesh> for Bvh {
+    fn from(shared_mesh: &SharedMesh) -> Self {
+        Bvh::new(shared_mesh.positions.clone(), shared_mesh.triangles.clone())
+    }
+}
+
+impl Bvh {
+    pub fn new(positions: Vec<DVec3>, triangles: Vec<U32Vec3>) -> Self {
+        let mut bvh = Bvh {
+            nodes: Vec::new(),
+            indices: (0..triangles.len() as u32).collect(),
+            triangles,
+            positions,
+        };
+        bvh.build();
+        bvh
+    }
+
+    pub fn bounds(&self) -> Box3 {
+        self.nodes.first().map_or_else(Box3::unfitted, |root| root.bounds)
+    }
+
+    pub fn positions(&self) -> &[DVec3] {
+        &self.positions
+    }
+
+    pub fn triangles(&self) -> &[U32Vec3] {
+        &self.triangles
+    }
+
+    fn get_triangle(&self, triangle: u32) -> [DVec3; 3] {
+        let triangle = &self.triangles[triangle as usize];
+        [0, 1, 2].map(|x| self.positions[triangle[x] as usize])
+    }
+
+    fn get_triangle_bounds(&self, triangle: u32) -> Box3 {
+        let mut bounds = Box3::unfitted();
+        for position in self.get_triangle(triangle).iter() {
+            grow(&mut bounds, position, position);
+        }
+        bounds
+    }
+
+    fn build(&mut self) {
+        if self.triangles.is_empty() {
+            return;
+        }
+        let get_reference = |triangle: u32| Reference { bounds: self.get_triangle_bounds(triangle), triangle };
+        #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+        let mut references: Vec<Reference> = (0..self.triangles.len() as u32).into_par_iter().map(get_reference).collect();
+        #[cfg(any(not(feature = "parallel"), target_arch = "wasm32"))]
+        let mut references: Vec<Reference> = (0..self.triangles.len() as u32).map(get_reference).collect();
+
+        let (root_bounds, root_centroid_bounds) = get_bounds(&references);
+        let mut nodes = Vec::with_capacity(2 * self.triangles.len() / MAX_LEAF_SIZE as usize + 1);
+        nodes.push(Node { bounds: root_bounds, first: 0, count: self.triangles.len() as u32 });
+
+        #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+        {
+            // Large nodes are split first, then the subtrees below them are built in parallel, each in its own nodes
+            let mut subtrees = split_nodes(&mut nodes, vec![(0, root_centroid_bounds)], &mut references, 0, PARALLEL_SUBTREE_SIZE);
+            subtrees.sort_unstable_by_key(|(node_index, _)| nodes[*node_index].first);
+            let mut tasks = Vec::with_capacity(subtrees.len());
+            let (mut rest, mut offset) = (&mut references[..], 0);
+            for (node_index, centroid_bounds) in subtrees {
+                let root = nodes[node_index];
+                let (references, tail) = std::mem::take(&mut rest)[(root.first - offset) as usize..].split_at_mut(root.count as usize);
+                tasks.push((node_index, root, centroid_bounds, references));
+                rest = tail;
+                offset = root.first + root.count;
+            }
+            let subtrees: Vec<(usize, Vec<Node>)> = tasks.into_par_iter().map(|(node_index, root, centroid_bounds, references)| {
+                let mut subtree = vec![root];
+                split_nodes(&mut subtree, vec![(0, centroid_bounds)], references, root.first, 0);
+                (node_index, subtree)
+            }).collect();
+
+            // Subtree roots are already in place, their descendants are appended
+            for (node_index, subtree) in subtrees {
+                let base = nodes.len() as u32 - 1;
+                let mut subtree = subtree.into_iter().map(|node| if node.count == 0 { Node { first: node.first + base, ..node } } else { node });
+                nodes[node_index] = subtree.next().unwrap();
+                nodes.extend(subtree);
+            }
+        }
+        #[cfg(any(not(feature = "parallel"), target_arch = "wasm32"))]
+        split_nodes(&mut nodes, vec![(0, root_centroid_bounds)], &mut references, 0, 0);
+
+        self.nodes = nodes;
+        self.indices = references.iter().map(|reference| reference.triangle).collect();
+    }
+
+    /// Updates the bounds of every node after vertices moved, without changing the hierarchy.
+    /// This is much faster than a rebuild, but queries get slower as the mesh moves away from its shape at build time.
+    pub fn refit(&mut self, positions: &[DVec3]) {
+        assert_eq!(positions.len(), self.positions.len(), "Refitting requires the same vertices");
+        self.positions.copy_from_slice(positions);
+        // Children are always after their parent
+        for node_index in (0..self.nodes.len()).rev() {
+            let Node { first, count, .. } = self.nodes[node_index];
+            let mut bounds = Box3::unfitted();
+            if count == 0 {
+                bounds.merge(&self.nodes[first as usize].bounds);
+                bounds.merge(&self.nodes[first as usize + 1].bounds);
+            } else {
+                for &i in &self.indices[first as usize..(first + count) as usize] {
+                    bounds.merge(&self.get_triangle_bounds(i));
+                }
+            }
+            self.nodes[node_index].bounds = bounds;
+        }
+    }
+
+    /// First triangle hit by the ray within `max_distance`
+    pub fn cast_ray(&self, ray: &Ray, max_distance: f64) -> Option<RayHit> {
+        let mut closest = None;
+        self.visit_ray_hits(ray, max_distance, |hit| {
+            closest = Some(hit);
+            hit.distance
+        });
+        closest
+    }
+
+    /// Every triangle hit by the ray within `max_distance`, from the closest to the farthest
+    pub fn cast_ray_all(&self, ray: &Ray, max_distance: f64) -> Vec<RayHit> {
+        let mut hits = Vec::new();
+        self.visit_ray_hits(ray, max_distance, |hit| {
+            hits.push(hit);
+            max_distance
+        });
+        hits.sort_by(|a, b| a.distance.total_cmp(&b.distance).then(a.triangle.cmp(&b.triangle)));
+        hits
+    }
+
+    /// First triangle hit by the segment from `a` to `b`, with distances measured from `a`
+    pub fn intersect_segment(&self, a: &DVec3, b: &DVec3) -> Option<RayHit> {
+        let length = (b - a).magnitude();
+        if length == 0.0 {
+            return None;
+        }
+        self.cast_ray(&Ray::new(*a, b - a), length)
+    }
+
+    /// Every triangle hit by the segment from `a` to `b`, from `a` to `b`
+    pub fn intersect_segment_all(&self, a: &DVec3, b: &DVec3) -> Vec<RayHit> {
+        let length = (b - a).magnitude();
+        if length == 0.0 {
+            return Vec::new();
+        }
+        self.cast_ray_all(&Ray::new(*a, b - a), length)
+    }
+
+    // Calls `on_hit` for hits closer than the current maximum distance, which it returns
+    fn visit_ray_hits(&self, ray: &Ray, mut max_distance: f64, mut on_hit: impl FnMut(RayHit) -> f64) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let inverse_direction = DVec3::new(1.0 / ray.direction.x, 1.0 / ray.direction.y, 1.0 / ray.direction.z);
+        let mut stack = vec![(0u32, 0.0)];
+        while let Some((node_index, entry)) = stack.pop() {
+            if entry > max_distance {
+                continue;
+            }
+            let Node { first, count, .. } = self.nodes[node_index as usize];
+            if count > 0 {
+                for &triangle in &self.indices[first as usize..(first + count) as usize] {
+                    let [a, b, c] = self.get_triangle(triangle);
+                    if let Some((distance, barycentric)) = intersect_ray_triangle(ray, &a, &b, &c) {
+                        if distance <= max_distance {
+                            max_distance = on_hit(RayHit { triangle, distance, barycentric });
+                        }
+                    }
+                }
+                continue;
+            }
+            // Visits the nearest child first
+            let left = intersect_ray_box(ray, &inverse_direction, &self.nodes[first as usize].bounds, max_distance);
+            let right = intersect_ray_box(ray, &inverse_direction, &self.nodes[first as usize + 1].bounds, max_distance);
+            match (left, right) {
+                (Some(left), Some(right)) if left <= right => {
+                    stack.push((first + 1, right));
+                    stack.push((first, left));
+                },
+                (Some(left), Some(right)) => {
+                    stack.push((first, left));
+                    stack.push((first + 1, right));
+                },
+                (Some(left), None) => stack.push((first, left)),
+                (None, Some(right)) => stack.push((first + 1, right)),
+                (None, None) => (),
+            }
+        }
+    }
+
+    /// Closest point on the mesh to `point`, if any triangle is within `max_distance`
+    pub fn get_closest_point(&self, point: &DVec3, max_distance: f64) -> Option<ClosestPoint> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let mut closest = None;
+        let mut max_distance_squared = max_distance * max_distance;
+        let mut stack = vec![(0u32, self.nodes[0].bounds.distance_squared(point))];
+        while let Some((node_index, distance_squared)) = stack.pop() {
+            if distance_squared > max_distance_squared {
+                continue;
+            }
+            let Node { first, count, .. } = self.nodes[node_index as usize];
+            if count > 0 {
+                for &triangle in &self.indices[first as usize..(first + count) as usize] {
+                    let [a, b, c] = self.get_triangle(triangle);
+                    let closest_point = get_closest_point_on_triangle(point, &a, &b, &c);
+                    let distance_squared = (closest_point - point).magnitude_squared();
+                    if distance_squared <= max_distance_squared {
+                        max_distance_squared = distance_squared;
+                        closest = Some((triangle, closest_point));
+                    }
+                }
+                continue;
+            }
+            let left = self.nodes[first as usize].bounds.distance_squared(point);
+            let right = self.nodes[first as usize + 1].bounds.distance_squared(point);
+            if left <= right {
+                stack.push((first + 1, right));
+                stack.push((first, left));
+            } else {
+                stack.push((first, left));
+                stack.push((first + 1, right));
+            }
+        }
+        closest.map(|(triangle, closest_point)| ClosestPoint {
+            triangle,
+            point: closest_point,
+            distance: max_distance_squared.sqrt(),
+        })
+    }
+
+    /// Triangles overlapping the box, in increasing order
+    pub fn get_triangles_in_box(&self, bounds: &Box3) -> Vec<u32> {
+        let mut triangles = Vec::new();
+        self.visit_box_leaves(bounds, |triangle| {
+            let [a, b, c] = self.get_triangle(triangle);
+            if triangle_intersects_box(&a, &b, &c, bounds) {
+                triangles.push(triangle);
+            }
+        });
+        triangles.sort_unstable();
+        triangles
+    }
+
+    // Calls `visit` for every triangle in leaves whose bounds overlap the box
+    pub(crate) fn visit_box_leaves(&self, bounds: &Box3, mut visit: impl FnMut(u32)) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut stack = vec![0u32];
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index as usize];
+            if !node.bounds.intersects(bounds) {
+                continue;
+            }
+            if node.count > 0 {
+                for &triangle in &self.indices[node.first as usize..(node.first + node.count) as usize] {
+                    visit(triangle);
+                }
+            } else {
+                stack.push(node.first);
+                stack.push(node.first + 1);
+            }
+        }
+    }
+}
+
+// Triangle being sorted into the hierarchy. Its bounds move along with it, so that splits read memory sequentially.
+#[derive(Copy, Clone)]
+struct Reference {
+    bounds: Box3,
+    triangle: u32,
+}
+
+// Splits the nodes of the stack and their descendants until they become leaves. When `defer_count` isn't 0, nodes with
+// up to that many triangles are returned instead of being split. `references` starts at the reference `offset`.
+fn split_nodes(nodes: &mut Vec<Node>, mut stack: Vec<(usize, Box3)>, references: &mut [Reference], offset: u32, defer_count: u32) -> Vec<(usize, Box3)> {
+    let mut deferred = Vec::new();
+    let (mut bins, mut rights) = ([Bin::new(); BIN_COUNT], [Bin::new(); BIN_COUNT]);
+    while let Some((node_index, centroid_bounds)) = stack.pop() {
+        let Node { bounds: node_bounds, first, count } = nodes[node_index];
+        if count <= defer_count {
+            deferred.push((node_index, centroid_bounds));
+            continue;
+        }
+        if count <= 2 {
+            continue;
+        }
+        let references = &mut references[(first - offset) as usize..(first - offset + count) as usize];
+        let children = match find_split(references, &node_bounds, &centroid_bounds, &mut bins, &mut rights) {
+            Some(split) if split.cost < count as f64 || count > MAX_LEAF_SIZE => {
+                let scale = get_bin_scale(&centroid_bounds, split.axis, split.bin_count);
+                let min = centroid_bounds.min[split.axis];
+                let left_count = partition(references, |reference| get_bin(get_centroid(reference, split.axis), min, scale, split.bin_count) <= split.bin);
+                debug_assert_eq!(left_count as u32, split.left.count);
+                [split.left, split.right]
+            },
+            // All centroids at the same place: any split is as good
+            None if count > MAX_LEAF_SIZE => {
+                let (left, right) = references.split_at(references.len() / 2);
+                [left, right].map(|references| {
+                    let (bounds, centroid_bounds) = get_bounds(references);
+                    Bin { bounds, centroid_bounds, count: references.len() as u32 }
+                })
+            },
+            _ => continue,
+        };
+
+        nodes[node_index].first = nodes.len() as u32;
+        nodes[node_index].count = 0;
+        let mut child_first = first;
+        for child in children {
+            stack.push((nodes.len(), child.centroid_bounds));
+            nodes.push(Node { bounds: child.bounds, first: child_first, count: child.count });
+            child_first += child.count;
+        }
+    }
+    deferred
+}
+
+// Triangles binned along an axis, or on a side of a split
+#[derive(Copy, Clone)]
+struct Bin {
+    bounds: Box3,
+    centroid_bounds: Box3,
+    count: u32,
+}
+
+impl Bin {
+    fn new() -> Self {
+        Bin { bounds: Box3::unfitted(), centroid_bounds: Box3::unfitted(), count: 0 }
+    }
+
+    fn add(&mut self, reference: &Reference) {
+        let centroid = reference.bounds.center();
+        grow(&mut self.bounds, &reference.bounds.min, &reference.bounds.max);
+        grow(&mut self.centroid_bounds, &centroid, &centroid);
+        self.count += 1;
+    }
+
+    fn merge(&mut self, other: &Bin) {
+        grow(&mut self.bounds, &other.bounds.min, &other.bounds.max);
+        grow(&mut self.centroid_bounds, &other.centroid_bounds.min, &other.centroid_bounds.max);
+        self.count += other.count;
+    }
+}
+
+// Box3::merge with plain comparisons rather than f64::min and f64::max, which also handle NaN and are much slower in the
+// build loops. NaN coordinates are still ignored, as they are on the right of the comparisons.
+fn grow(bounds: &mut Box3, min: &DVec3, max: &DVec3) {
+    for x in 0..3 {
+        bounds.min[x] = if bounds.min[x] > min[x] { min[x] } else { bounds.min[x] };
+        bounds.max[x] = if bounds.max[x] < max[x] { max[x] } else { bounds.max[x] };
+    }
+}
+
+struct Split {
+    axis: usize,
+    // Last bin on the left side
+    bin: usize,
+    bin_count: usize,
+    // In triangle intersections
+    cost: f64,
+    left: Bin,
+    right: Bin,
+}
+
+fn get_bounds(references: &[Reference]) -> (Box3, Box3) {
+    let mut node_bounds = Box3::unfitted();
+    let mut centroid_bounds = Box3::unfitted();
+    for reference in references {
+        node_bounds.merge(&reference.bounds);
+        centroid_bounds.expand(&reference.bounds.center());
+    }
+    (node_bounds, centroid_bounds)
+}
+
+fn get_centroid(reference: &Reference, axis: usize) -> f64 {
+    0.5 * (reference.bounds.min[axis] + reference.bounds.max[axis])
+}
+
+fn get_bin_scale(centroid_bounds: &Box3, axis: usize, bin_count: usize) -> f64 {
+    let extent = centroid_bounds.max[axis] - centroid_bounds.min[axis];
+    if extent > 0.0 { bin_count as f64 / extent } else { 0.0 }
+}
+
+fn get_bin(value: f64, min: f64, scale: f64, bin_count: usize) -> usize {
+    (((value - min) * scale) as usize).min(bin_count - 1)
+}
+
+// Best split with the surface area heuristic, binning the centroids along the largest axis of their bounds.
+// Small nodes use as many bins as triangles. `bins` and `rights` are scratch buffers of `BIN_COUNT` bins.
+fn find_split(references: &[Reference], node_bounds: &Box3, centroid_bounds: &Box3, bins: &mut [Bin], rights: &mut [Bin]) -> Option<Split> {
+    let size = centroid_bounds.size();
+    let axis = if size.x >= size.y && size.x >= size.z { 0 } else if size.y >= size.z { 1 } else { 2 };
+    let bin_count = references.len().min(BIN_COUNT);
+    let scale = get_bin_scale(centroid_bounds, axis, bin_count);
+    if scale == 0.0 {
+        return None;
+    }
+    let min = centroid_bounds.min[axis];
+    let bins = &mut bins[..bin_count];
+    bins.fill(Bin::new());
+    for reference in references {
+        let bin = &mut bins[get_bin(get_centroid(reference, axis), min, scale, bin_count)];
+        bin.add(reference);
+    }
+
+    // Everything right of every split, sweeping from the right
+    let mut right = Bin::new();
+    for bin in (1..bin_count).rev() {
+        right.merge(&bins[bin]);
+        rights[bin - 1] = right;
+    }
+
+    let node_area = node_bounds.surface_area().max(f64::MIN_POSITIVE);
+    let mut best: Option<Split> = None;
+    let mut left = Bin::new();
+    for bin in 0..bin_count - 1 {
+        left.merge(&bins[bin]);
+        let right = &rights[bin];
+        if left.count == 0 || right.count == 0 {
+            continue;
+        }
+        let cost = TRAVERSAL_COST + (left.bounds.surface_area() * left.count as f64 + right.bounds.surface_area() * right.count as f64) / node_area;
+        if best.as_ref().is_none_or(|best| cost < best.cost) {
+            best = Some(Split { axis, bin, bin_count, cost, left, right: *right });
+        }
+    }
+    best
+}
+
+// Moves the references matching the predicate first, returning how many there are.
+// Swaps misplaced pairs from both ends, which moves far fewer references than a single forward sweep.
+fn partition(references: &mut [Reference], predicate: impl Fn(&Reference) -> bool) -> usize {
+    let (mut left, mut right) = (0, references.len());
+    loop {
+        while left < right && predicate(&references[left]) {
+            left += 1;
+        }
+        while left < right && !predicate(&references[right - 1]) {
+            right -= 1;
+        }
+        if left == right {
+            return left;
+        }
+        references.swap(left, right - 1);
+        left += 1;
+        right -= 1;
+    }
+}
+
+#[cfg(test)]
+mod bvh_tests {
+    use super::*;
+    use crate::mesh::test_utils::read_sphere;
+
+    // Deterministic points in [-1.5, 1.5]^3
+    fn get_sample_points(count: usize) -> Vec<DVec3> {
+        (0..count).map(|i| {
+            let i = i as f64;
+            DVec3::new((i * 12.9898).sin(), (i * 78.233).sin(), (i * 37.719).sin()) * 1.5
+        }).collect()
+    }
+
+    fn get_triangle(shared_mesh: &SharedMesh, triangle: usize) -> [DVec3; 3] {
+        [0, 1, 2].map(|x| shared_mesh.positions[shared_mesh.triangles[triangle][x] as usize])
+    }
+
+    #[test]
+    fn ray_hits_match_brute_force() {
+        let sphere = read_sphere();
+        let bvh = Bvh::from(&sphere);
+        let points = get_sample_points(200);
+        for (origin, target) in points.iter().zip(points.iter().skip(1)) {
+            let ray = Ray::new(*origin, target - origin);
+            let mut expected: Vec<(u32, f64)> = (0..sphere.triangles.len()).filter_map(|t| {
+                let [a, b, c] = get_triangle(&sphere, t);
+                intersect_ray_triangle(&ray, &a, &b, &c).map(|(distance, _)| (t as u32, distance))
+            }).collect();
+            expected.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+            let hits = bvh.cast_ray_all(&ray, f64::MAX);
+            assert_eq!(hits.len(), expected.len());
+            for (hit, expected) in hits.iter().zip(expected.iter()) {
+                assert_eq!(hit.distance, expected.1);
+            }
+            assert_eq!(bvh.cast_ray(&ray, f64::MAX).map(|hit| hit.distance), expected.first().map(|e| e.1));
+        }
+    }
+
+    #[test]
+    fn large_hierarchy_is_consistent() {
+        // Wavy grid, large enough to be built in several subtrees with the parallel feature
+        let size = 200;
+        let positions = (0..(size + 1) * (size + 1)).map(|i| {
+            let (x, y) = ((i % (size + 1)) as f64, (i / (size + 1)) as f64);
+            DVec3::new(x, y, (x * 0.3).sin() * (y * 0.2).cos())
+        }).collect();
+        let triangles = (0..size * size).flat_map(|i| {
+            let v = (i / size * (size + 1) + i % size) as u32;
+            let w = v + size as u32 + 1;
+            [U32Vec3::new(v, v + 1, w + 1), U32Vec3::new(v, w + 1, w)]
+        }).collect();
+        let bvh = Bvh::new(positions, triangles);
+
+        let mut seen = vec![false; bvh.triangles().len()];
+        for (node_index, node) in bvh.nodes.iter().enumerate() {
+            if node.count == 0 {
+                assert!(node.first as usize > node_index);
+                for child in &bvh.nodes[node.first as usize..node.first as usize + 2] {
+                    assert!((0..3).all(|x| child.bounds.min[x] >= node.bounds.min[x] && child.bounds.max[x] <= node.bounds.max[x]));
+                }
+            }
+            for &triangle in bvh.indices[node.first as usize..(node.first + node.count) as usize].iter() {
+                assert!(!seen[triangle as usize]);
+                seen[triangle as usize] = true;
+                assert!(bvh.get_triangle(triangle).iter().all(|p| node.bounds.contains(p)));
+            }
+        }
+        assert!(seen.iter().all(|&seen| seen));
+    }
+
+    #[test]
+    fn segment_through_sphere() {
+        let bvh = Bvh::from(&read_sphere());
+        let hits = bvh.intersect_segment_all(&DVec3::new(-2., 0.1, 0.2), &DVec3::new(2., 0.1, 0.2));
+        assert_eq!(hits.len(), 2);
+        assert!((hits[0].distance - 1.0).abs() < 0.05 && (hits[1].distance - 3.0).abs() < 0.05);
+
+        let hit = bvh.intersect_segment(&DVec3::new(0.1, 0.2, 0.), &DVec3::new(0.1, 0.2, 0.5));
+        assert!(hit.is_none());
+
+        let hit = bvh.intersect_segment(&DVec3::new(0.1, 0.2, 0.), &DVec3::new(0.1, 0.2, 5.)).unwrap();
+        let [a, b, c] = bvh.get_triangle(hit.triangle);
+        let point = a * hit.barycentric.x + b * hit.barycentric.y + c * hit.barycentric.z;
+        assert!((point - DVec3::new(0.1, 0.2, hit.distance)).magnitude() < 1e-9);
+    }
+
+    #[test]
+    fn closest_points_match_brute_force() {
+        let sphere = read_sphere();
+        let bvh = Bvh::from(&sphere);
+        for point in get_sample_points(200) {
+            let expected = (0..sphere.triangles.len()).map(|t| {
+                let [a, b, c] = get_triangle(&sphere, t);
+                (get_closest_point_on_triangle(&point, &a, &b, &c) - point).magnitude()
+            }).fold(f64::MAX, f64::min);
+
+            let closest = bvh.get_closest_point(&point, f64::MAX).unwrap();
+            assert!((closest.distance - expected).abs() < 1e-12);
+            assert!((closest.distance - (closest.point - point).magnitude()).abs() < 1e-12);
+            assert_eq!(bvh.get_closest_point(&point, expected * 0.99), None);
+        }
+    }
+
+    #[test]
+    fn box_query_matches_brute_force() {
+        let sphere = read_sphere();
+        let bvh = Bvh::from(&sphere);
+        for point in get_sample_points(50) {
+            let bounds = Box3::new(point - DVec3::new(0.3, 0.2, 0.4), point + DVec3::new(0.3, 0.2, 0.4));
+            let expected: Vec<u32> = (0..sphere.triangles.len() as u32).filter(|&t| {
+                let [a, b, c] = get_triangle(&sphere, t as usize);
+                triangle_intersects_box(&a, &b, &c, &bounds)
+            }).collect();
+            assert_eq!(bvh.get_triangles_in_box(&bounds), expected);
+        }
+    }
+
+    #[test]
+    fn refit_follows_vertices() {
+        let sphere = read_sphere();
+        let mut bvh = Bvh::from(&sphere);
+        let moved: Vec<DVec3> = sphere.positions.iter().map(|p| p * 2.0 + DVec3::new(5., 0., 0.)).collect();
+        bvh.refit(&moved);
+
+        assert!((bvh.bounds().center() - DVec3::new(5., 0., 0.)).magnitude() < 1e-9);
+        let hit = bvh.cast_ray(&Ray::new(DVec3::new(5., 0.05, -10.), DVec3::new(0., 0., 1.)), f64::MAX).unwrap();
+        assert!((hit.distance - 8.0).abs() < 0.1);
+        let closest = bvh.get_closest_point(&DVec3::new(5., 0., 0.), f64::MAX).unwrap();
+        assert!((closest.distance - 2.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn triangle_box_overlap() {
+        let (a, b, c) = (DVec3::new(0., 0., 0.), DVec3::new(2., 0., 0.), DVec3::new(0., 2., 0.));
+        let unit = |x: f64, y: f64, z: f64| Box3::new(DVec3::new(x, y, z), DVec3::new(x + 1., y + 1., z + 1.));
+        assert!(triangle_intersects_box(&a, &b, &c, &unit(-0.5, -0.5, -0.5)));
+        assert!(triangle_intersects_box(&a, &b, &c, &unit(0.4, 0.4, -0.5)));
+        // Near the hypotenuse, but outside
+        assert!(!triangle_intersects_box(&a, &b, &c, &unit(1.1, 1.1, -0.5)));
+        assert!(!triangle_intersects_box(&a, &b, &c, &unit(0.2, 0.2, 0.1)));
+    }
+}
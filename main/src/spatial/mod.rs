@@ -0,0 +1,5 @@
+pub mod queries;
+pub mod predicates;
+
+pub mod bvh;
+pub use bvh::{Bvh, RayHit, ClosestPoint};
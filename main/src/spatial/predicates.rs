@@ -0,0 +1,131 @@
+use nalgebra_glm as glm;
+use glm::{DVec2, DVec3};
+
+// Exact intersection tests between segments and triangles, built on Shewchuk's adaptive predicates.
+// Points on boundaries count as intersecting.
+
+/// Positive when `d` is below the plane of abc, that is when abc appears clockwise seen from `d`.
+/// Zero exactly when the four points are coplanar.
+pub fn orient3d(a: &DVec3, b: &DVec3, c: &DVec3, d: &DVec3) -> f64 {
+    geometry_predicates::orient3d([a.x, a.y, a.z], [b.x, b.y, b.z], [c.x, c.y, c.z], [d.x, d.y, d.z])
+}
+
+/// Positive when abc is counterclockwise, zero exactly when the points are collinear
+pub fn orient2d(a: &DVec2, b: &DVec2, c: &DVec2) -> f64 {
+    geometry_predicates::orient2d([a.x, a.y], [b.x, b.y], [c.x, c.y])
+}
+
+/// Axis along which the normal of the triangle is the largest. Dropping it projects the plane of the triangle
+/// onto a coordinate plane without making the figures in it degenerate.
+pub fn get_dominant_axis(a: &DVec3, b: &DVec3, c: &DVec3) -> usize {
+    let normal = (b - a).cross(&(c - a)).abs();
+    if normal.x >= normal.y && normal.x >= normal.z { 0 } else if normal.y >= normal.z { 1 } else { 2 }
+}
+
+pub fn project(point: &DVec3, dropped_axis: usize) -> DVec2 {
+    match dropped_axis {
+        0 => DVec2::new(point.y, point.z),
+        1 => DVec2::new(point.z, point.x),
+        _ => DVec2::new(point.x, point.y),
+    }
+}
+
+fn have_same_strict_sign(a: f64, b: f64) -> bool {
+    (a > 0.0 && b > 0.0) || (a < 0.0 && b < 0.0)
+}
+
+/// Whether `p`, collinear with ab, lies between a and b
+pub fn is_within_segment_2d(p: &DVec2, a: &DVec2, b: &DVec2) -> bool {
+    p.x >= a.x.min(b.x) && p.x <= a.x.max(b.x) && p.y >= a.y.min(b.y) && p.y <= a.y.max(b.y)
+}
+
+pub fn segments_intersect_2d(a: &DVec2, b: &DVec2, c: &DVec2, d: &DVec2) -> bool {
+    let (oa, ob) = (orient2d(c, d, a), orient2d(c, d, b));
+    let (oc, od) = (orient2d(a, b, c), orient2d(a, b, d));
+    if oa == 0.0 && ob == 0.0 {
+        // Collinear, the segments must overlap
+        return is_within_segment_2d(a, c, d) || is_within_segment_2d(b, c, d) || is_within_segment_2d(c, a, b);
+    }
+    if have_same_strict_sign(oa, ob) || have_same_strict_sign(oc, od) {
+        return false;
+    }
+    // One endpoint touching the other segment's line must also be on that segment
+    if oa == 0.0 { return is_within_segment_2d(a, c, d); }
+    if ob == 0.0 { return is_within_segment_2d(b, c, d); }
+    if oc == 0.0 { return is_within_segment_2d(c, a, b); }
+    if od == 0.0 { return is_within_segment_2d(d, a, b); }
+    true
+}
+
+pub fn point_in_triangle_2d(p: &DVec2, a: &DVec2, b: &DVec2, c: &DVec2) -> bool {
+    let signs = [orient2d(a, b, p), orient2d(b, c, p), orient2d(c, a, p)];
+    signs.iter().all(|&s| s >= 0.0) || signs.iter().all(|&s| s <= 0.0)
+}
+
+fn segment_intersects_triangle_2d(d: &DVec2, e: &DVec2, a: &DVec2, b: &DVec2, c: &DVec2) -> bool {
+    point_in_triangle_2d(d, a, b, c)
+        || point_in_triangle_2d(e, a, b, c)
+        || segments_intersect_2d(d, e, a, b)
+        || segments_intersect_2d(d, e, b, c)
+        || segments_intersect_2d(d, e, c, a)
+}
+
+/// Whether the segment de intersects the triangle abc
+pub fn segment_intersects_triangle(d: &DVec3, e: &DVec3, a: &DVec3, b: &DVec3, c: &DVec3) -> bool {
+    let (od, oe) = (orient3d(a, b, c, d), orient3d(a, b, c, e));
+    if od == 0.0 && oe == 0.0 {
+        let axis = get_dominant_axis(a, b, c);
+        let [d, e, a, b, c] = [d, e, a, b, c].map(|p| project(p, axis));
+        return segment_intersects_triangle_2d(&d, &e, &a, &b, &c);
+    }
+    if have_same_strict_sign(od, oe) {
+        return false;
+    }
+    // The line through de must go through the triangle: it turns the same way around all of its edges
+    let signs = [orient3d(d, e, a, b), orient3d(d, e, b, c), orient3d(d, e, c, a)];
+    signs.iter().all(|&s| s >= 0.0) || signs.iter().all(|&s| s <= 0.0)
+}
+
+/// Whether two triangles intersect. When they do, an edge of one of them crosses the other one.
+pub fn triangles_intersect(t1: &[DVec3; 3], t2: &[DVec3; 3]) -> bool {
+    (0..3).any(|i| segment_intersects_triangle(&t1[i], &t1[(i + 1) % 3], &t2[0], &t2[1], &t2[2]))
+        || (0..3).any(|i| segment_intersects_triangle(&t2[i], &t2[(i + 1) % 3], &t1[0], &t1[1], &t1[2]))
+}
+
+#[cfg(test)]
+mod predicates_tests {
+    use super::*;
+
+    fn triangle(points: [[f64; 3]; 3]) -> [DVec3; 3] {
+        points.map(|p| DVec3::new(p[0], p[1], p[2]))
+    }
+
+    #[test]
+    fn crossing_and_separate_triangles() {
+        let t1 = triangle([[0., 0., 0.], [2., 0., 0.], [0., 2., 0.]]);
+        assert!(triangles_intersect(&t1, &triangle([[0.5, 0.5, -1.], [0.5, 0.5, 1.], [3., 3., 0.5]])));
+        assert!(!triangles_intersect(&t1, &triangle([[0.5, 0.5, 0.1], [0.5, 0.5, 1.], [3., 3., 0.5]])));
+        // Touching on a vertex only
+        assert!(triangles_intersect(&t1, &triangle([[2., 0., 0.], [3., 0., 1.], [3., 0., -1.]])));
+        // Beyond the hypotenuse
+        assert!(!triangles_intersect(&t1, &triangle([[1.1, 1.1, -1.], [1.1, 1.1, 1.], [3., 3., 0.]])));
+    }
+
+    #[test]
+    fn coplanar_triangles() {
+        let t1 = triangle([[0., 0., 0.], [2., 0., 0.], [0., 2., 0.]]);
+        // Contained, overlapping, and separated by the hypotenuse
+        assert!(triangles_intersect(&t1, &triangle([[0.2, 0.2, 0.], [0.5, 0.2, 0.], [0.2, 0.5, 0.]])));
+        assert!(triangles_intersect(&t1, &triangle([[1., -1., 0.], [1., 1., 0.], [3., 0., 0.]])));
+        assert!(!triangles_intersect(&t1, &triangle([[1.1, 1.1, 0.], [3., 1., 0.], [1., 3., 0.]])));
+    }
+
+    #[test]
+    fn nearly_coplanar_is_exact() {
+        // The second triangle is above the plane of the first by the smallest step of its coordinates
+        let t1 = triangle([[0., 0., 1.], [2., 0., 1.], [0., 2., 1.]]);
+        let above = 1.0 + f64::EPSILON;
+        assert!(!triangles_intersect(&t1, &triangle([[0.2, 0.2, above], [0.5, 0.2, above], [0.2, 0.5, above]])));
+        assert!(triangles_intersect(&t1, &triangle([[0.2, 0.2, above], [0.5, 0.2, 1.], [0.2, 0.5, above]])));
+    }
+}
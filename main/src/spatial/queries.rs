@@ -0,0 +1,123 @@
+use nalgebra_glm as glm;
+use glm::DVec3;
+use crate::base::{Box3, Ray};
+
+/// Closest point to `p` on the triangle abc (Ericson, Real-Time Collision Detection, 5.1.5)
+pub fn get_closest_point_on_triangle(p: &DVec3, a: &DVec3, b: &DVec3, c: &DVec3) -> DVec3 {
+    let ab = b - a;
+    let ac = c - a;
+    let ap = p - a;
+    let d1 = ab.dot(&ap);
+    let d2 = ac.dot(&ap);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return *a;
+    }
+
+    let bp = p - b;
+    let d3 = ab.dot(&bp);
+    let d4 = ac.dot(&bp);
+    if d3 >= 0.0 && d4 <= d3 {
+        return *b;
+    }
+
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a + ab * (d1 / (d1 - d3));
+    }
+
+    let cp = p - c;
+    let d5 = ab.dot(&cp);
+    let d6 = ac.dot(&cp);
+    if d6 >= 0.0 && d5 <= d6 {
+        return *c;
+    }
+
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a + ac * (d2 / (d2 - d6));
+    }
+
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+
+    let denominator = 1.0 / (va + vb + vc);
+    a + ab * (vb * denominator) + ac * (vc * denominator)
+}
+
+/// Distance along the ray to the triangle abc, with the barycentric coordinates of the hit (Möller–Trumbore).
+/// Both sides of the triangle are hit.
+pub fn intersect_ray_triangle(ray: &Ray, a: &DVec3, b: &DVec3, c: &DVec3) -> Option<(f64, DVec3)> {
+    let ab = b - a;
+    let ac = c - a;
+    let p = ray.direction.cross(&ac);
+    let determinant = ab.dot(&p);
+    // Ray parallel to the triangle
+    if determinant == 0.0 {
+        return None;
+    }
+    let inverse_determinant = 1.0 / determinant;
+
+    let ao = ray.origin - a;
+    let u = ao.dot(&p) * inverse_determinant;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = ao.cross(&ab);
+    let v = ray.direction.dot(&q) * inverse_determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let distance = ac.dot(&q) * inverse_determinant;
+    if distance < 0.0 {
+        return None;
+    }
+    Some((distance, DVec3::new(1.0 - u - v, u, v)))
+}
+
+/// Distance along the ray to where it enters the box (zero if it starts inside), if it does before `max_distance`.
+/// `inverse_direction` is the componentwise inverse of the ray direction.
+pub fn intersect_ray_box(ray: &Ray, inverse_direction: &DVec3, bounds: &Box3, max_distance: f64) -> Option<f64> {
+    let mut near = 0.0f64;
+    let mut far = max_distance;
+    for i in 0..3 {
+        // Ray parallel to the slab
+        if ray.direction[i] == 0.0 {
+            if ray.origin[i] < bounds.min[i] || ray.origin[i] > bounds.max[i] {
+                return None;
+            }
+            continue;
+        }
+        let t1 = (bounds.min[i] - ray.origin[i]) * inverse_direction[i];
+        let t2 = (bounds.max[i] - ray.origin[i]) * inverse_direction[i];
+        near = near.max(t1.min(t2));
+        far = far.min(t1.max(t2));
+    }
+    if near <= far {
+        Some(near)
+    } else {
+        None
+    }
+}
+
+/// Whether the triangle abc overlaps the box, with the separating axis theorem (Akenine-Möller)
+pub fn triangle_intersects_box(a: &DVec3, b: &DVec3, c: &DVec3, bounds: &Box3) -> bool {
+    let center = bounds.center();
+    let half_size = bounds.size() / 2.0;
+    let vertices = [a - center, b - center, c - center];
+    let edges = [vertices[1] - vertices[0], vertices[2] - vertices[1], vertices[0] - vertices[2]];
+    let box_axes = [DVec3::x(), DVec3::y(), DVec3::z()];
+
+    let is_separating = |axis: DVec3| {
+        let projections = vertices.map(|vertex| vertex.dot(&axis));
+        let radius = half_size.x * axis.x.abs() + half_size.y * axis.y.abs() + half_size.z * axis.z.abs();
+        projections[0].min(projections[1]).min(projections[2]) > radius
+            || projections[0].max(projections[1]).max(projections[2]) < -radius
+    };
+
+    if box_axes.iter().any(|axis| is_separating(*axis)) || is_separating(edges[0].cross(&edges[1])) {
+        return false;
+    }
+    !edges.iter().any(|edge| box_axes.iter().any(|axis| is_separating(edge.cross(axis))))
+}
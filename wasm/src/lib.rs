@@ -11,6 +11,8 @@ use log::{Level};
 pub struct Parameters {
   pub polygon_reduction: f32,
   pub export_format: u32,
+  /// Reorders triangles and vertices for GPU caches before export
+  pub optimize: bool,
 }
 
 #[wasm_bindgen]
@@ -19,7 +21,8 @@ impl Parameters {
   pub fn new() -> Parameters {
     Parameters {
       export_format: 0,
-      polygon_reduction: 0.0 
+      polygon_reduction: 0.0,
+      optimize: false,
     }
   }
 }
@@ -51,15 +54,39 @@ pub fn read_obj(parameters: &Parameters, bytes: &[u8]) -> Vec<u8> {
   let step = StepFile::parse(&flat);
 
   set_progress(0.5, "Tesselating...");
-  let (mesh, _stats) = triangulate(&step);
+  let (mut mesh, _stats) = triangulate(&step);
+
+  if parameters.optimize {
+    set_progress(0.7, "Optimizing...");
+    let report = mesh.optimize_for_gpu(1.05);
+    log::info!("ACMR {:.3} -> {:.3}, ATVR {:.3} -> {:.3}", report.before.acmr, report.after.acmr, report.before.atvr, report.after.atvr);
+  }
 
   set_progress(0.75, "Writing...");
   let mut result = Vec::new();
   {
     let mut writer = BufWriter::new(&mut result);
-    nanomesh::io::obj::write(&mesh, &mut writer);
+    match parameters.export_format {
+      // Meshlet buffers, ready for upload by the viewer
+      1 => mesh.build_meshlets(64, 124, 0.25).write(&mesh.positions, &mut writer).unwrap(),
+      _ => nanomesh::io::obj::write(&mesh, &mut writer),
+    }
   }
 
   set_progress(1., "Done!");
   return result;
+}
+
+/// Tessellates a STEP file and returns the topology report of the mesh (see `SharedMesh::analyze`) as a plain object
+#[wasm_bindgen]
+pub fn analyze(bytes: &[u8]) -> JsValue {
+
+  use step::step_file::StepFile;
+  use triangulate::triangulate::triangulate;
+
+  let flat = StepFile::strip_flatten(bytes);
+  let step = StepFile::parse(&flat);
+  let (mesh, _stats) = triangulate(&step);
+
+  JsValue::from_serde(&mesh.analyze()).expect("Failed to serialize the topology report")
 }
\ No newline at end of file